@@ -0,0 +1,205 @@
+use crate::memory_controller::MemoryPtr;
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+//ntsc output rates, in cpu cycles
+const RATE_TABLE: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+pub struct Dmc {
+    irq_enabled: bool,
+    irq_flag: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    output_level: u8,
+}
+
+impl Dmc {
+    pub fn new() -> Dmc {
+        Dmc {
+            irq_enabled: false,
+            irq_flag: false,
+            looping: false,
+            timer_period: RATE_TABLE[0],
+            timer: RATE_TABLE[0],
+            sample_address: 0xc000,
+            sample_length: 1,
+            current_address: 0xc000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            output_level: 0,
+        }
+    }
+
+    //$4010: IL-- RRRR
+    pub fn write_control(&mut self, v: u8) {
+        self.irq_enabled = v & 0x80 != 0;
+        if !self.irq_enabled {
+            self.irq_flag = false;
+        }
+        self.looping = v & 0x40 != 0;
+        self.timer_period = RATE_TABLE[(v & 0x0f) as usize];
+    }
+
+    //$4011: -DDD DDDD
+    pub fn write_output_level(&mut self, v: u8) {
+        self.output_level = v & 0x7f;
+    }
+
+    //$4012: AAAA AAAA
+    pub fn write_sample_address(&mut self, v: u8) {
+        self.sample_address = 0xc000 | ((v as u16) << 6);
+    }
+
+    //$4013: LLLL LLLL
+    pub fn write_sample_length(&mut self, v: u8) {
+        self.sample_length = ((v as u16) << 4) | 1;
+    }
+
+    //bit 4 of $4015
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.irq_flag = false;
+
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub fn irq_flag(&self) -> bool {
+        self.irq_flag
+    }
+
+    //address the memory reader wants to fetch, if the sample buffer needs refilling
+    pub fn pending_fetch(&self) -> Option<MemoryPtr> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(MemoryPtr(self.current_address))
+        } else {
+            None
+        }
+    }
+
+    pub fn load_sample(&mut self, v: u8) {
+        self.sample_buffer = Some(v);
+
+        //the address wraps around to $8000 instead of $0000
+        self.current_address = if self.current_address == 0xffff { 0x8000 } else { self.current_address + 1 };
+        self.bytes_remaining -= 1;
+
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    //clocked every cpu cycle, as the rate table is already expressed in cpu cycles
+    pub fn clock_timer(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period;
+
+        if !self.silence {
+            if self.shift_register & 0x1 != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(v) => {
+                    self.silence = false;
+                    self.shift_register = v;
+                }
+                None => {
+                    self.silence = true;
+                }
+            }
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        self.output_level
+    }
+}
+
+impl SaveState for Dmc {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.irq_enabled);
+        w.bool(self.irq_flag);
+        w.bool(self.looping);
+        w.u16(self.timer_period);
+        w.u16(self.timer);
+        w.u16(self.sample_address);
+        w.u16(self.sample_length);
+        w.u16(self.current_address);
+        w.u16(self.bytes_remaining);
+        w.bool(self.sample_buffer.is_some());
+        w.u8(self.sample_buffer.unwrap_or(0));
+        w.u8(self.shift_register);
+        w.u8(self.bits_remaining);
+        w.bool(self.silence);
+        w.u8(self.output_level);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.irq_enabled = r.bool()?;
+        self.irq_flag = r.bool()?;
+        self.looping = r.bool()?;
+        self.timer_period = r.u16()?;
+        if !RATE_TABLE.contains(&self.timer_period) {
+            return Err(StateError::InvalidData);
+        }
+        self.timer = r.u16()?;
+        self.sample_address = r.u16()?;
+        self.sample_length = r.u16()?;
+        self.current_address = r.u16()?;
+        self.bytes_remaining = r.u16()?;
+        let has_sample = r.bool()?;
+        let sample = r.u8()?;
+        self.sample_buffer = has_sample.then_some(sample);
+        self.shift_register = r.u8()?;
+        self.bits_remaining = r.u8()?;
+        if !(1..=8).contains(&self.bits_remaining) {
+            return Err(StateError::InvalidData);
+        }
+        self.silence = r.bool()?;
+        self.output_level = r.u8()? & 0x7f;
+        Ok(())
+    }
+}
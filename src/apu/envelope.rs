@@ -0,0 +1,84 @@
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+pub struct Envelope {
+    start: bool,
+    looping: bool,
+    constant_volume: bool,
+    volume: u8,
+    divider: u8,
+    decay_level: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            start: false,
+            looping: false,
+            constant_volume: false,
+            volume: 0,
+            divider: 0,
+            decay_level: 0,
+        }
+    }
+
+    //takes the lower 6 bits of $4000/$4004/$400c (--LC VVVV)
+    pub fn write(&mut self, v: u8) {
+        self.looping = v & 0x20 != 0;
+        self.constant_volume = v & 0x10 != 0;
+        self.volume = v & 0x0f;
+    }
+
+    pub fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay_level = 15;
+            self.divider = self.volume;
+            return;
+        }
+
+        if self.divider > 0 {
+            self.divider -= 1;
+            return;
+        }
+
+        self.divider = self.volume;
+        if self.decay_level > 0 {
+            self.decay_level -= 1;
+        } else if self.looping {
+            self.decay_level = 15;
+        }
+    }
+
+    pub fn output(&self) -> u8 {
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.decay_level
+        }
+    }
+}
+
+impl SaveState for Envelope {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.start);
+        w.bool(self.looping);
+        w.bool(self.constant_volume);
+        w.u8(self.volume);
+        w.u8(self.divider);
+        w.u8(self.decay_level);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.start = r.bool()?;
+        self.looping = r.bool()?;
+        self.constant_volume = r.bool()?;
+        self.volume = r.u8()? & 0x0f;
+        self.divider = r.u8()?;
+        self.decay_level = r.u8()? & 0x0f;
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+//step positions in cpu cycles (ntsc)
+const STEP_1: u32 = 7457;
+const STEP_2: u32 = 14913;
+const STEP_3: u32 = 22371;
+const STEP_4: u32 = 29829;
+const STEP_5: u32 = 37281;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FrameCounterMode {
+    FourStep,
+    FiveStep,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct FrameSignal {
+    pub quarter_frame: bool,
+    pub half_frame: bool,
+}
+
+impl FrameSignal {
+    const NONE: FrameSignal = FrameSignal { quarter_frame: false, half_frame: false };
+    const QUARTER: FrameSignal = FrameSignal { quarter_frame: true, half_frame: false };
+    const HALF: FrameSignal = FrameSignal { quarter_frame: true, half_frame: true };
+}
+
+pub struct FrameCounter {
+    mode: FrameCounterMode,
+    irq_inhibit: bool,
+    irq_flag: bool,
+    cycle: u32,
+    reset_delay: u8,
+}
+
+impl FrameCounter {
+    pub fn new() -> FrameCounter {
+        FrameCounter {
+            mode: FrameCounterMode::FourStep,
+            irq_inhibit: false,
+            irq_flag: false,
+            cycle: 0,
+            reset_delay: 0,
+        }
+    }
+
+    //$4017: MI-- ----
+    pub fn write(&mut self, v: u8, odd_cycle: bool) {
+        self.mode = if v & 0x80 != 0 { FrameCounterMode::FiveStep } else { FrameCounterMode::FourStep };
+        self.irq_inhibit = v & 0x40 != 0;
+        if self.irq_inhibit {
+            self.irq_flag = false;
+        }
+
+        //the sequencer is reset 3 or 4 cpu cycles after the write, depending on the apu cycle parity
+        self.reset_delay = if odd_cycle { 4 } else { 3 };
+    }
+
+    pub fn irq_flag(&self) -> bool {
+        self.irq_flag
+    }
+
+    pub fn clear_irq_flag(&mut self) {
+        self.irq_flag = false;
+    }
+
+    //called once per cpu cycle
+    pub fn clock(&mut self) -> FrameSignal {
+        if self.reset_delay > 0 {
+            self.reset_delay -= 1;
+            if self.reset_delay == 0 {
+                self.cycle = 0;
+                //entering 5-step mode immediately clocks all units
+                if self.mode == FrameCounterMode::FiveStep {
+                    return FrameSignal::HALF;
+                }
+                return FrameSignal::NONE;
+            }
+        }
+
+        self.cycle += 1;
+
+        //the irq flag is raised on the last three cycles of the 4-step sequence, so acknowledging it on
+        //one of the first two doesn't keep it cleared
+        if self.mode == FrameCounterMode::FourStep && self.cycle >= STEP_4 - 1 && !self.irq_inhibit {
+            self.irq_flag = true;
+        }
+
+        match (self.mode, self.cycle) {
+            (_, STEP_1) | (_, STEP_3) => FrameSignal::QUARTER,
+            (_, STEP_2) => FrameSignal::HALF,
+            (FrameCounterMode::FourStep, STEP_4) => FrameSignal::HALF,
+            (FrameCounterMode::FourStep, c) if c > STEP_4 => {
+                self.cycle = 0;
+                FrameSignal::NONE
+            }
+            (FrameCounterMode::FiveStep, STEP_5) => FrameSignal::HALF,
+            (FrameCounterMode::FiveStep, c) if c > STEP_5 => {
+                self.cycle = 0;
+                FrameSignal::NONE
+            }
+            _ => FrameSignal::NONE,
+        }
+    }
+}
+
+impl SaveState for FrameCounter {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.mode == FrameCounterMode::FiveStep);
+        w.bool(self.irq_inhibit);
+        w.bool(self.irq_flag);
+        w.u32(self.cycle);
+        w.u8(self.reset_delay);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.mode = if r.bool()? { FrameCounterMode::FiveStep } else { FrameCounterMode::FourStep };
+        self.irq_inhibit = r.bool()?;
+        self.irq_flag = r.bool()?;
+        self.cycle = r.u32()?;
+        self.reset_delay = r.u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+//levels of every channel dac: 4 bits for pulse, triangle and noise, 7 bits for the dmc
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct ChannelLevels {
+    pub pulse1: u8,
+    pub pulse2: u8,
+    pub triangle: u8,
+    pub noise: u8,
+    pub dmc: u8,
+}
+
+//non-linear dac model, see https://www.nesdev.org/wiki/APU_Mixer
+pub struct Mixer {
+    pulse_table: [f32; 31],
+    tnd_table: [f32; 203],
+}
+
+impl Mixer {
+    pub fn new() -> Mixer {
+        let mut pulse_table = [0.0; 31];
+        for (n, v) in pulse_table.iter_mut().enumerate().skip(1) {
+            *v = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+
+        let mut tnd_table = [0.0; 203];
+        for (n, v) in tnd_table.iter_mut().enumerate().skip(1) {
+            *v = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+
+        Mixer { pulse_table, tnd_table }
+    }
+
+    //output in the 0.0..1.0 range
+    pub fn mix(&self, levels: ChannelLevels) -> f32 {
+        let pulse = (levels.pulse1 & 0xf) + (levels.pulse2 & 0xf);
+        let tnd = 3 * (levels.triangle & 0xf) as usize + 2 * (levels.noise & 0xf) as usize + (levels.dmc & 0x7f) as usize;
+
+        self.pulse_table[pulse as usize] + self.tnd_table[tnd]
+    }
+}
+
+//first order filters modelling the analog stage of the console
+enum FilterKind {
+    HighPass,
+    LowPass,
+}
+
+struct Filter {
+    kind: FilterKind,
+    alpha: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl Filter {
+    fn new(kind: FilterKind, cutoff: f32, sample_rate: u32) -> Filter {
+        let rc = 1.0 / (2.0 * std::f32::consts::PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        let alpha = match kind {
+            FilterKind::HighPass => rc / (rc + dt),
+            FilterKind::LowPass => dt / (rc + dt),
+        };
+
+        Filter {
+            kind,
+            alpha,
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let output = match self.kind {
+            FilterKind::HighPass => self.alpha * (self.previous_output + input - self.previous_input),
+            FilterKind::LowPass => self.previous_output + self.alpha * (input - self.previous_output),
+        };
+
+        self.previous_input = input;
+        self.previous_output = output;
+        output
+    }
+}
+
+pub struct FilterChain {
+    filters: [Filter; 3],
+}
+
+impl FilterChain {
+    pub fn new(sample_rate: u32) -> FilterChain {
+        FilterChain {
+            filters: [
+                Filter::new(FilterKind::HighPass, 90.0, sample_rate),
+                Filter::new(FilterKind::HighPass, 440.0, sample_rate),
+                Filter::new(FilterKind::LowPass, 14000.0, sample_rate),
+            ],
+        }
+    }
+
+    pub fn process(&mut self, input: f32) -> f32 {
+        self.filters.iter_mut().fold(input, |v, f| f.process(v))
+    }
+}
+
+impl SaveState for FilterChain {
+    fn save_state(&self, w: &mut StateWriter) {
+        for f in self.filters.iter() {
+            w.u32(f.previous_input.to_bits());
+            w.u32(f.previous_output.to_bits());
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for f in self.filters.iter_mut() {
+            f.previous_input = f32::from_bits(r.u32()?);
+            f.previous_output = f32::from_bits(r.u32()?);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+mod dmc;
+mod envelope;
+mod frame_counter;
+mod length_counter;
+mod mixer;
+mod noise;
+mod output;
+mod pulse;
+mod resampler;
+mod triangle;
+
+use crate::{cpu::{Cpu, IRQ_DMC, IRQ_FRAME_COUNTER}, memory_controller::MemoryPtr};
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+use dmc::Dmc;
+use frame_counter::FrameCounter;
+use mixer::ChannelLevels;
+use noise::Noise;
+use output::AudioOutput;
+use pulse::{Pulse, SweepNegate};
+use triangle::Triangle;
+
+pub const CPU_FREQUENCY: u32 = 1789773;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+const STATUS_PULSE1: u8 = 1 << 0;
+const STATUS_PULSE2: u8 = 1 << 1;
+const STATUS_TRIANGLE: u8 = 1 << 2;
+const STATUS_NOISE: u8 = 1 << 3;
+const STATUS_DMC: u8 = 1 << 4;
+const STATUS_FRAME_IRQ: u8 = 1 << 6;
+const STATUS_DMC_IRQ: u8 = 1 << 7;
+
+//cpu cycles lost each time the dmc memory reader fetches a sample byte, the last one reads it
+pub(crate) const DMC_STALL_CYCLES: u64 = 4;
+
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+
+    //cpu cycle up to which the apu has been run
+    cycle: u64,
+    //a sample fetch was handed out and has not been loaded yet
+    dmc_fetching: bool,
+
+    output: AudioOutput,
+    samples: Vec<i16>,
+}
+
+impl Apu {
+    pub fn new(sample_rate: u32) -> Apu {
+        Apu {
+            pulse1: Pulse::new(SweepNegate::OnesComplement),
+            pulse2: Pulse::new(SweepNegate::TwosComplement),
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            frame_counter: FrameCounter::new(),
+            cycle: 0,
+            dmc_fetching: false,
+            output: AudioOutput::new(sample_rate),
+            samples: Vec::new(),
+        }
+    }
+
+    //runs the apu until it reaches the cpu cycle count, raising the irq line if needed. stops early with
+    //the address of a sample byte when the dmc needs one, the caller reads it over the bus while stalling
+    //the cpu and hands it to `load_dmc_sample`
+    pub fn catch_up(&mut self, cpu: &mut Cpu) -> Option<MemoryPtr> {
+        while self.cycle < cpu.cycle_count {
+            if !self.dmc_fetching {
+                if let Some(addr) = self.dmc.pending_fetch() {
+                    self.dmc_fetching = true;
+                    self.update_irq(cpu);
+                    return Some(addr);
+                }
+            }
+
+            self.step();
+        }
+
+        self.update_irq(cpu);
+        None
+    }
+
+    pub fn load_dmc_sample(&mut self, v: u8) {
+        self.dmc.load_sample(v);
+        self.dmc_fetching = false;
+    }
+
+    //a reset silences every channel, like writing 0 to $4015
+    pub fn reset(&mut self, cpu: &mut Cpu) {
+        self.write(MemoryPtr(0x4015), 0, cpu);
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.frame_counter.irq_flag() || self.dmc.irq_flag()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.output.sample_rate()
+    }
+
+    //drops any audio not yet returned by `end_frame`
+    #[allow(dead_code)]
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.output = AudioOutput::new(sample_rate);
+    }
+
+    //slightly speeds up or slows down the output, for dynamic rate control in frontends
+    pub fn set_rate_adjustment(&mut self, ratio: f64) {
+        self.output.set_rate_adjustment(ratio);
+    }
+
+    //samples produced by the last call to `end_frame`
+    #[allow(dead_code)]
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    //resamples everything emulated since the previous call into the host-rate sample buffer
+    pub fn end_frame(&mut self) {
+        self.samples.clear();
+        self.output.end_frame(&mut self.samples);
+    }
+
+    fn step(&mut self) {
+        let signal = self.frame_counter.clock();
+        if signal.quarter_frame {
+            self.pulse1.clock_quarter_frame();
+            self.pulse2.clock_quarter_frame();
+            self.triangle.clock_quarter_frame();
+            self.noise.clock_quarter_frame();
+        }
+        if signal.half_frame {
+            self.pulse1.clock_half_frame();
+            self.pulse2.clock_half_frame();
+            self.triangle.clock_half_frame();
+            self.noise.clock_half_frame();
+        }
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+
+        //pulse timers are clocked every apu cycle, which is every other cpu cycle
+        if self.cycle & 1 == 0 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+
+        self.cycle += 1;
+        self.output.push_levels(self.levels());
+    }
+
+    fn levels(&self) -> ChannelLevels {
+        ChannelLevels {
+            pulse1: self.pulse1.output(),
+            pulse2: self.pulse2.output(),
+            triangle: self.triangle.output(),
+            noise: self.noise.output(),
+            dmc: self.dmc.output(),
+        }
+    }
+
+    //callers are expected to catch the apu up before accessing its registers
+    pub fn read(&mut self, addr: MemoryPtr, cpu: &mut Cpu) -> u8 {
+        if addr.0 != 0x4015 {
+            return 0;
+        }
+
+        let mut status = 0;
+        if self.pulse1.length_counter.active() {
+            status |= STATUS_PULSE1;
+        }
+        if self.pulse2.length_counter.active() {
+            status |= STATUS_PULSE2;
+        }
+        if self.triangle.length_counter.active() {
+            status |= STATUS_TRIANGLE;
+        }
+        if self.noise.length_counter.active() {
+            status |= STATUS_NOISE;
+        }
+        if self.dmc.active() {
+            status |= STATUS_DMC;
+        }
+        if self.frame_counter.irq_flag() {
+            status |= STATUS_FRAME_IRQ;
+        }
+        if self.dmc.irq_flag() {
+            status |= STATUS_DMC_IRQ;
+        }
+
+        //reading the status acknowledges the frame interrupt
+        self.frame_counter.clear_irq_flag();
+        self.update_irq(cpu);
+
+        status
+    }
+
+    //the frame counter and the dmc drive their own part of the irq line, a mapper irq is left alone
+    fn update_irq(&self, cpu: &mut Cpu) {
+        cpu.set_irq(IRQ_FRAME_COUNTER, self.frame_counter.irq_flag());
+        cpu.set_irq(IRQ_DMC, self.dmc.irq_flag());
+    }
+
+    pub fn write(&mut self, addr: MemoryPtr, v: u8, cpu: &mut Cpu) {
+        match addr.0 {
+            0x4000 => self.pulse1.write_control(v),
+            0x4001 => self.pulse1.write_sweep(v),
+            0x4002 => self.pulse1.write_timer_low(v),
+            0x4003 => self.pulse1.write_timer_high(v),
+            0x4004 => self.pulse2.write_control(v),
+            0x4005 => self.pulse2.write_sweep(v),
+            0x4006 => self.pulse2.write_timer_low(v),
+            0x4007 => self.pulse2.write_timer_high(v),
+            0x4008 => self.triangle.write_linear_counter(v),
+            0x400a => self.triangle.write_timer_low(v),
+            0x400b => self.triangle.write_timer_high(v),
+            0x400c => self.noise.write_control(v),
+            0x400e => self.noise.write_period(v),
+            0x400f => self.noise.write_length(v),
+            0x4010 => {
+                self.dmc.write_control(v);
+                self.update_irq(cpu);
+            }
+            0x4011 => self.dmc.write_output_level(v),
+            0x4012 => self.dmc.write_sample_address(v),
+            0x4013 => self.dmc.write_sample_length(v),
+            0x4015 => {
+                self.pulse1.length_counter.set_enabled(v & STATUS_PULSE1 != 0);
+                self.pulse2.length_counter.set_enabled(v & STATUS_PULSE2 != 0);
+                self.triangle.length_counter.set_enabled(v & STATUS_TRIANGLE != 0);
+                self.noise.length_counter.set_enabled(v & STATUS_NOISE != 0);
+                self.dmc.set_enabled(v & STATUS_DMC != 0);
+                self.update_irq(cpu);
+            }
+            0x4017 => {
+                self.frame_counter.write(v, self.cycle & 1 == 1);
+                self.update_irq(cpu);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl SaveState for Apu {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.pulse1.save_state(w);
+        self.pulse2.save_state(w);
+        self.triangle.save_state(w);
+        self.noise.save_state(w);
+        self.dmc.save_state(w);
+        self.frame_counter.save_state(w);
+        w.u64(self.cycle);
+        self.output.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.pulse1.load_state(r)?;
+        self.pulse2.load_state(r)?;
+        self.triangle.load_state(r)?;
+        self.noise.load_state(r)?;
+        self.dmc.load_state(r)?;
+        self.frame_counter.load_state(r)?;
+        self.cycle = r.u64()?;
+        self.output.load_state(r)?;
+        self.samples.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
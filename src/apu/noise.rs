@@ -0,0 +1,106 @@
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+
+//ntsc timer periods, in cpu cycles
+const PERIOD_TABLE: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+pub struct Noise {
+    short_mode: bool,
+    timer_period: u16,
+    timer: u16,
+    shift_register: u16,
+    pub envelope: Envelope,
+    pub length_counter: LengthCounter,
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise {
+            short_mode: false,
+            timer_period: PERIOD_TABLE[0],
+            timer: 0,
+            shift_register: 1,
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+        }
+    }
+
+    //$400c: --LC VVVV
+    pub fn write_control(&mut self, v: u8) {
+        self.length_counter.set_halt(v & 0x20 != 0);
+        self.envelope.write(v);
+    }
+
+    //$400e: M--- PPPP
+    pub fn write_period(&mut self, v: u8) {
+        self.short_mode = v & 0x80 != 0;
+        self.timer_period = PERIOD_TABLE[(v & 0x0f) as usize];
+    }
+
+    //$400f: LLLL L---
+    pub fn write_length(&mut self, v: u8) {
+        self.length_counter.load(v >> 3);
+        self.envelope.restart();
+    }
+
+    //clocked every cpu cycle, as the period table is already expressed in cpu cycles
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period - 1;
+
+        let tap = if self.short_mode { 6 } else { 1 };
+        let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 0x1;
+        self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    #[allow(dead_code)]
+    pub fn shift_register(&self) -> u16 {
+        self.shift_register
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.active() || self.shift_register & 0x1 != 0 {
+            return 0;
+        }
+
+        self.envelope.output()
+    }
+}
+
+impl SaveState for Noise {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.short_mode);
+        w.u16(self.timer_period);
+        w.u16(self.timer);
+        w.u16(self.shift_register);
+        self.envelope.save_state(w);
+        self.length_counter.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.short_mode = r.bool()?;
+        self.timer_period = r.u16()?;
+        if !PERIOD_TABLE.contains(&self.timer_period) {
+            return Err(StateError::InvalidData);
+        }
+        self.timer = r.u16()?;
+        self.shift_register = r.u16()?;
+        self.envelope.load_state(r)?;
+        self.length_counter.load_state(r)
+    }
+}
@@ -0,0 +1,102 @@
+use super::mixer::{ChannelLevels, FilterChain, Mixer};
+use super::resampler::Resampler;
+use super::CPU_FREQUENCY;
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+//mixer output is converted to integer amplitudes before resampling, so results are exact and reproducible
+const AMPLITUDE_SCALE: f32 = 32768.0;
+
+//audio output stage of the 2a03: takes the channel dac levels once per cpu cycle
+//and produces filtered pcm samples at the host sample rate
+pub struct AudioOutput {
+    mixer: Mixer,
+    resampler: Resampler,
+    filters: FilterChain,
+    sample_rate: u32,
+
+    clock: u32,
+    last_levels: Option<ChannelLevels>,
+    raw_samples: Vec<i32>,
+}
+
+impl AudioOutput {
+    pub fn new(sample_rate: u32) -> AudioOutput {
+        AudioOutput {
+            mixer: Mixer::new(),
+            resampler: Resampler::new(CPU_FREQUENCY, sample_rate),
+            filters: FilterChain::new(sample_rate),
+            sample_rate,
+            clock: 0,
+            last_levels: None,
+            raw_samples: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn set_rate_adjustment(&mut self, ratio: f64) {
+        self.resampler.set_ratio(ratio);
+    }
+
+    pub fn push_levels(&mut self, levels: ChannelLevels) {
+        if self.last_levels != Some(levels) {
+            self.last_levels = Some(levels);
+
+            let amplitude = (self.mixer.mix(levels) * AMPLITUDE_SCALE) as i32;
+            self.resampler.set_amplitude(self.clock, amplitude);
+        }
+
+        self.clock += 1;
+    }
+
+    //appends the samples for every cycle pushed since the last call
+    pub fn end_frame(&mut self, output: &mut Vec<i16>) {
+        self.raw_samples.clear();
+        self.resampler.end_frame(self.clock, &mut self.raw_samples);
+        self.clock = 0;
+
+        for v in self.raw_samples.iter() {
+            let filtered = self.filters.process(*v as f32 / AMPLITUDE_SCALE);
+            output.push((filtered.clamp(-1.0, 1.0) * i16::MAX as f32) as i16);
+        }
+    }
+}
+
+impl SaveState for AudioOutput {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u32(self.sample_rate);
+        w.u32(self.clock);
+        let levels = self.last_levels;
+        w.bool(levels.is_some());
+        let levels = levels.unwrap_or_default();
+        w.bytes(&[levels.pulse1, levels.pulse2, levels.triangle, levels.noise, levels.dmc]);
+        self.resampler.save_state(w);
+        self.filters.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        let sample_rate = r.u32()?;
+        if sample_rate != self.sample_rate {
+            //the state was saved with another output rate, it can only be skipped
+            AudioOutput::new(sample_rate).load_state(r)?;
+            *self = AudioOutput::new(self.sample_rate);
+            return Ok(());
+        }
+
+        self.clock = r.u32()?;
+        let has_levels = r.bool()?;
+        let mut levels = [0; 5];
+        r.bytes(&mut levels)?;
+        self.last_levels = has_levels.then_some(ChannelLevels {
+            pulse1: levels[0],
+            pulse2: levels[1],
+            triangle: levels[2],
+            noise: levels[3],
+            dmc: levels[4],
+        });
+        self.resampler.load_state(r)?;
+        self.filters.load_state(r)
+    }
+}
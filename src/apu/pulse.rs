@@ -0,0 +1,189 @@
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+//the two pulse channels only differ in how the sweep unit negates the period change
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SweepNegate {
+    OnesComplement,
+    TwosComplement,
+}
+
+struct Sweep {
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+}
+
+pub struct Pulse {
+    negate_mode: SweepNegate,
+    duty: u8,
+    sequence_pos: u8,
+    timer_period: u16,
+    timer: u16,
+    sweep: Sweep,
+    pub envelope: Envelope,
+    pub length_counter: LengthCounter,
+}
+
+impl Pulse {
+    pub fn new(negate_mode: SweepNegate) -> Pulse {
+        Pulse {
+            negate_mode,
+            duty: 0,
+            sequence_pos: 0,
+            timer_period: 0,
+            timer: 0,
+            sweep: Sweep {
+                enabled: false,
+                period: 0,
+                negate: false,
+                shift: 0,
+                divider: 0,
+                reload: false,
+            },
+            envelope: Envelope::new(),
+            length_counter: LengthCounter::new(),
+        }
+    }
+
+    //$4000/$4004: DDLC VVVV
+    pub fn write_control(&mut self, v: u8) {
+        self.duty = v >> 6;
+        self.length_counter.set_halt(v & 0x20 != 0);
+        self.envelope.write(v);
+    }
+
+    //$4001/$4005: EPPP NSSS
+    pub fn write_sweep(&mut self, v: u8) {
+        self.sweep.enabled = v & 0x80 != 0;
+        self.sweep.period = (v >> 4) & 0x7;
+        self.sweep.negate = v & 0x08 != 0;
+        self.sweep.shift = v & 0x7;
+        self.sweep.reload = true;
+    }
+
+    //$4002/$4006: LLLL LLLL
+    pub fn write_timer_low(&mut self, v: u8) {
+        self.timer_period = (self.timer_period & 0x700) | v as u16;
+    }
+
+    //$4003/$4007: LLLL LHHH
+    pub fn write_timer_high(&mut self, v: u8) {
+        self.timer_period = (self.timer_period & 0xff) | (((v & 0x7) as u16) << 8);
+        self.length_counter.load(v >> 3);
+        self.sequence_pos = 0;
+        self.envelope.restart();
+    }
+
+    //clocked once every apu cycle (every other cpu cycle)
+    pub fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.sequence_pos = (self.sequence_pos + 1) & 0x7;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift > 0 && !self.is_muted() {
+            self.timer_period = self.target_period();
+        }
+
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn timer_period(&self) -> u16 {
+        self.timer_period
+    }
+
+    fn target_period(&self) -> u16 {
+        let change = self.timer_period >> self.sweep.shift;
+        if !self.sweep.negate {
+            return self.timer_period + change;
+        }
+
+        match self.negate_mode {
+            SweepNegate::OnesComplement => self.timer_period.saturating_sub(change + 1),
+            SweepNegate::TwosComplement => self.timer_period.saturating_sub(change),
+        }
+    }
+
+    //the sweep unit mutes the channel even when it is disabled
+    fn is_muted(&self) -> bool {
+        self.timer_period < 8 || self.target_period() > 0x7ff
+    }
+
+    pub fn output(&self) -> u8 {
+        if !self.length_counter.active() || self.is_muted() {
+            return 0;
+        }
+
+        if DUTY_TABLE[self.duty as usize][self.sequence_pos as usize] == 0 {
+            return 0;
+        }
+
+        self.envelope.output()
+    }
+}
+
+impl SaveState for Pulse {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.duty);
+        w.u8(self.sequence_pos);
+        w.u16(self.timer_period);
+        w.u16(self.timer);
+        w.bool(self.sweep.enabled);
+        w.u8(self.sweep.period);
+        w.bool(self.sweep.negate);
+        w.u8(self.sweep.shift);
+        w.u8(self.sweep.divider);
+        w.bool(self.sweep.reload);
+        self.envelope.save_state(w);
+        self.length_counter.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.duty = r.u8()? & 0x3;
+        self.sequence_pos = r.u8()? & 0x7;
+        self.timer_period = r.u16()?;
+        self.timer = r.u16()?;
+        self.sweep.enabled = r.bool()?;
+        self.sweep.period = r.u8()?;
+        self.sweep.negate = r.bool()?;
+        self.sweep.shift = r.u8()? & 0x7;
+        self.sweep.divider = r.u8()?;
+        self.sweep.reload = r.bool()?;
+        self.envelope.load_state(r)?;
+        self.length_counter.load_state(r)?;
+
+        //the period register has 11 bits, a larger one overflows the sweep target
+        if self.timer_period > 0x7ff {
+            return Err(StateError::InvalidData);
+        }
+        Ok(())
+    }
+}
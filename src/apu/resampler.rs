@@ -0,0 +1,158 @@
+use std::f64::consts::PI;
+
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+//band-limited step synthesis, in the spirit of blargg's blip_buf:
+//every amplitude change is added to a delta buffer as a windowed sinc impulse,
+//and the buffer is integrated when samples are read back.
+const KERNEL_WIDTH: usize = 16;
+const PHASE_BITS: u32 = 6;
+const PHASES: usize = 1 << PHASE_BITS;
+const KERNEL_UNIT_BITS: u32 = 15;
+const FRACTION_BITS: u32 = 32;
+
+//fraction of the host sample rate that the kernel lets through
+const CUTOFF: f64 = 0.45;
+
+pub struct Resampler {
+    kernel: Box<[[i32; KERNEL_WIDTH]; PHASES]>,
+    clock_rate: u32,
+    sample_rate: u32,
+    factor: u64,
+
+    //position of clock 0 of the current frame, in host samples with 32 fractional bits
+    offset: u64,
+    amplitude: i32,
+    integrator: i64,
+    buffer: Vec<i64>,
+}
+
+impl Resampler {
+    pub fn new(clock_rate: u32, sample_rate: u32) -> Resampler {
+        Resampler {
+            kernel: Box::new(build_kernel()),
+            clock_rate,
+            sample_rate,
+            factor: ((sample_rate as u64) << FRACTION_BITS) / clock_rate as u64,
+            offset: 0,
+            amplitude: 0,
+            integrator: 0,
+            buffer: vec![0; KERNEL_WIDTH],
+        }
+    }
+
+    //stretches the output by a small ratio, so frontends can keep their audio buffer from draining or overflowing
+    pub fn set_ratio(&mut self, ratio: f64) {
+        self.factor = if ratio == 1.0 {
+            ((self.sample_rate as u64) << FRACTION_BITS) / self.clock_rate as u64
+        } else {
+            (self.sample_rate as f64 * ratio * (1u64 << FRACTION_BITS) as f64 / self.clock_rate as f64) as u64
+        };
+    }
+
+    //sets the input amplitude starting at the given clock, relative to the start of the frame
+    pub fn set_amplitude(&mut self, clock: u32, amplitude: i32) {
+        let delta = amplitude - self.amplitude;
+        if delta == 0 {
+            return;
+        }
+        self.amplitude = amplitude;
+
+        let position = self.offset + clock as u64 * self.factor;
+        let index = (position >> FRACTION_BITS) as usize;
+        let phase = ((position >> (FRACTION_BITS - PHASE_BITS)) as usize) & (PHASES - 1);
+
+        if self.buffer.len() < index + KERNEL_WIDTH {
+            self.buffer.resize(index + KERNEL_WIDTH, 0);
+        }
+
+        for (v, k) in self.buffer[index..index + KERNEL_WIDTH].iter_mut().zip(self.kernel[phase].iter()) {
+            *v += delta as i64 * *k as i64;
+        }
+    }
+
+    //closes a frame of the given length in clocks, and appends the finished samples to `output`
+    pub fn end_frame(&mut self, clocks: u32, output: &mut Vec<i32>) {
+        self.offset += clocks as u64 * self.factor;
+        let available = (self.offset >> FRACTION_BITS) as usize;
+
+        if self.buffer.len() < available + KERNEL_WIDTH {
+            self.buffer.resize(available + KERNEL_WIDTH, 0);
+        }
+
+        for v in self.buffer.drain(..available) {
+            self.integrator += v;
+            output.push((self.integrator >> KERNEL_UNIT_BITS) as i32);
+        }
+
+        self.offset -= (available as u64) << FRACTION_BITS;
+    }
+}
+
+fn build_kernel() -> [[i32; KERNEL_WIDTH]; PHASES] {
+    let mut kernel = [[0i32; KERNEL_WIDTH]; PHASES];
+    let half = (KERNEL_WIDTH / 2) as f64;
+
+    for (phase, taps) in kernel.iter_mut().enumerate() {
+        let fraction = phase as f64 / PHASES as f64;
+
+        let mut values = [0f64; KERNEL_WIDTH];
+        for (i, v) in values.iter_mut().enumerate() {
+            //distance between this tap and the (fractional) position of the impulse
+            let x = i as f64 - (half - 1.0) - fraction;
+
+            let sinc = if x == 0.0 {
+                1.0
+            } else {
+                (2.0 * PI * CUTOFF * x).sin() / (2.0 * PI * CUTOFF * x)
+            };
+
+            //blackman window over the kernel width
+            let w = (x + half) / (2.0 * half);
+            let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+
+            *v = sinc * window;
+        }
+
+        //every phase must add up to exactly one unit, or steps would leave a dc error behind
+        let sum: f64 = values.iter().sum();
+        let unit = (1i32 << KERNEL_UNIT_BITS) as f64;
+        for (t, v) in taps.iter_mut().zip(values.iter()) {
+            *t = (v / sum * unit).round() as i32;
+        }
+        let error = (1i32 << KERNEL_UNIT_BITS) - taps.iter().sum::<i32>();
+        taps[KERNEL_WIDTH / 2 - 1] += error;
+    }
+
+    kernel
+}
+
+//the kernel and rates come from the constructor, only the stream position and pending output are saved
+impl SaveState for Resampler {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u64(self.factor);
+        w.u64(self.offset);
+        w.u32(self.amplitude as u32);
+        w.i64(self.integrator);
+        w.u32(self.buffer.len() as u32);
+        for v in self.buffer.iter() {
+            w.i64(*v);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.factor = r.u64()?;
+        self.offset = r.u64()?;
+        self.amplitude = r.u32()? as i32;
+        self.integrator = r.i64()?;
+        let len = r.u32()? as usize;
+        if len < KERNEL_WIDTH || len > self.clock_rate as usize {
+            return Err(StateError::InvalidData);
+        }
+        self.buffer.clear();
+        for _ in 0..len {
+            self.buffer.push(r.i64()?);
+        }
+        Ok(())
+    }
+}
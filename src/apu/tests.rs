@@ -0,0 +1,614 @@
+use super::*;
+use super::frame_counter::FrameSignal;
+use super::mixer::Mixer;
+use super::resampler::Resampler;
+use crate::cpu::{CpuMemory, IRQ_MAPPER};
+use crate::memory_controller::Ram;
+
+fn write_registers(apu: &mut Apu, cpu: &mut Cpu, writes: &[(u16, u8)]) {
+    for (addr, v) in writes {
+        apu.write(MemoryPtr(*addr), *v, cpu);
+    }
+}
+
+//runs the apu up to the cpu, with dmc samples fetched from `memory` and the cpu stalled like on the console bus
+fn catch_up(apu: &mut Apu, cpu: &mut Cpu, memory: &mut Ram) {
+    while let Some(addr) = apu.catch_up(cpu) {
+        cpu.cycle_count += DMC_STALL_CYCLES - 1;
+        let v = memory.read(addr, cpu);
+        cpu.cycle_count += 1;
+        apu.load_dmc_sample(v);
+    }
+}
+
+#[test]
+fn test_pulse_duty_sequence() {
+    let mut pulse = Pulse::new(SweepNegate::OnesComplement);
+    pulse.length_counter.set_enabled(true);
+
+    //50% duty, constant volume 15, timer period 8
+    pulse.write_control(0xbf);
+    pulse.write_timer_low(0x08);
+    pulse.write_timer_high(0x08);
+
+    let mut output = Vec::new();
+    for _ in 0..8 {
+        output.push(pulse.output());
+        for _ in 0..9 {
+            pulse.clock_timer();
+        }
+    }
+
+    assert_eq!(output, vec![0, 15, 15, 15, 15, 0, 0, 0]);
+}
+
+#[test]
+fn test_pulse_length_counter() {
+    let mut pulse = Pulse::new(SweepNegate::OnesComplement);
+    pulse.length_counter.set_enabled(true);
+
+    //length index 3 loads a length of 2
+    pulse.write_control(0x9f);
+    pulse.write_timer_low(0x08);
+    pulse.write_timer_high(0x18);
+    assert_eq!(pulse.length_counter.value(), 2);
+
+    pulse.clock_half_frame();
+    assert!(pulse.length_counter.active());
+    pulse.clock_half_frame();
+    assert!(!pulse.length_counter.active());
+
+    //halted counters keep their value
+    pulse.write_control(0xbf);
+    pulse.write_timer_high(0x18);
+    pulse.clock_half_frame();
+    pulse.clock_half_frame();
+    assert_eq!(pulse.length_counter.value(), 2);
+
+    //disabled channels ignore length loads
+    pulse.length_counter.set_enabled(false);
+    pulse.write_timer_high(0x18);
+    assert!(!pulse.length_counter.active());
+}
+
+#[test]
+fn test_pulse_envelope_decay() {
+    let mut pulse = Pulse::new(SweepNegate::OnesComplement);
+
+    //envelope period 1, no loop
+    pulse.write_control(0x01);
+    pulse.write_timer_high(0x00);
+
+    pulse.clock_quarter_frame();
+    assert_eq!(pulse.envelope.output(), 15);
+    pulse.clock_quarter_frame();
+    assert_eq!(pulse.envelope.output(), 15);
+    pulse.clock_quarter_frame();
+    assert_eq!(pulse.envelope.output(), 14);
+
+    for _ in 0..40 {
+        pulse.clock_quarter_frame();
+    }
+    assert_eq!(pulse.envelope.output(), 0);
+}
+
+#[test]
+fn test_pulse_sweep() {
+    let mut pulse = Pulse::new(SweepNegate::OnesComplement);
+    pulse.length_counter.set_enabled(true);
+    pulse.write_control(0xbf);
+    pulse.write_timer_low(0x00);
+    pulse.write_timer_high(0x09);
+
+    //enabled, period 0, shift 1: 0x100 -> 0x180
+    pulse.write_sweep(0x81);
+    pulse.clock_half_frame();
+    assert_eq!(pulse.timer_period(), 0x180);
+
+    //negated sweep on pulse 1 subtracts one more than pulse 2
+    pulse.write_sweep(0x89);
+    pulse.clock_half_frame();
+    assert_eq!(pulse.timer_period(), 0x180 - 0xc0 - 1);
+
+    let mut pulse2 = Pulse::new(SweepNegate::TwosComplement);
+    pulse2.length_counter.set_enabled(true);
+    pulse2.write_timer_low(0x80);
+    pulse2.write_timer_high(0x09);
+    pulse2.write_sweep(0x89);
+    pulse2.clock_half_frame();
+    assert_eq!(pulse2.timer_period(), 0x180 - 0xc0);
+
+    //target periods above $7ff mute the channel even with the sweep disabled
+    pulse.write_sweep(0x01);
+    pulse.write_timer_low(0xff);
+    pulse.write_timer_high(0x0e);
+    assert_eq!(pulse.output(), 0);
+}
+
+#[test]
+fn test_frame_counter_four_step() {
+    let mut counter = FrameCounter::new();
+    let mut signals = Vec::new();
+
+    for cycle in 1..=29830 {
+        let signal = counter.clock();
+        if signal != FrameSignal::default() {
+            signals.push((cycle, signal.half_frame));
+        }
+    }
+
+    assert_eq!(signals, vec![(7457, false), (14913, true), (22371, false), (29829, true)]);
+    assert!(counter.irq_flag());
+}
+
+#[test]
+fn test_frame_irq_flag_cycles() {
+    let mut counter = FrameCounter::new();
+    for _ in 1..=29827 {
+        counter.clock();
+    }
+    assert!(!counter.irq_flag());
+
+    //set on 29828, 29829 and 29830, where the sequence starts over, even when acknowledged in between
+    for _ in 29828..=29830 {
+        counter.clock();
+        assert!(counter.irq_flag());
+        counter.clear_irq_flag();
+    }
+    counter.clock();
+    assert!(!counter.irq_flag());
+}
+
+#[test]
+fn test_frame_counter_five_step() {
+    let mut counter = FrameCounter::new();
+    counter.write(0x80, false);
+
+    let mut signals = Vec::new();
+    for cycle in 1..=37285 {
+        let signal = counter.clock();
+        if signal != FrameSignal::default() {
+            signals.push((cycle, signal.half_frame));
+        }
+    }
+
+    //the write clocks every unit once the reset delay expires
+    assert_eq!(signals, vec![(3, true), (7460, false), (14916, true), (22374, false), (37284, true)]);
+    assert!(!counter.irq_flag());
+}
+
+#[test]
+fn test_frame_irq_status() {
+    let mut apu = Apu::new(DEFAULT_SAMPLE_RATE);
+    let mut cpu = Cpu::new();
+
+    cpu.cycle_count = 30000;
+    catch_up(&mut apu, &mut cpu, &mut Ram::new());
+    assert!(cpu.irq_requested());
+
+    assert_eq!(apu.read(MemoryPtr(0x4015), &mut cpu) & STATUS_FRAME_IRQ, STATUS_FRAME_IRQ);
+    assert!(!cpu.irq_requested());
+    assert_eq!(apu.read(MemoryPtr(0x4015), &mut cpu) & STATUS_FRAME_IRQ, 0);
+
+    //inhibited frame counters never raise the interrupt
+    write_registers(&mut apu, &mut cpu, &[(0x4017, 0x40)]);
+    cpu.cycle_count = 90000;
+    catch_up(&mut apu, &mut cpu, &mut Ram::new());
+    assert!(!cpu.irq_requested());
+}
+
+//a band-limited step can be spread over two samples, so only the start of each step is counted
+fn count_rising_edges(samples: &[i16]) -> usize {
+    let steps: Vec<bool> = samples.windows(2).map(|w| w[1] as i32 - w[0] as i32 > 2000).collect();
+    steps.windows(2).filter(|w| !w[0] && w[1]).count()
+}
+
+#[test]
+fn test_pulse_sample_stream() {
+    let mut apu = Apu::new(DEFAULT_SAMPLE_RATE);
+    let mut cpu = Cpu::new();
+    cpu.cycle_count = 0;
+
+    write_registers(&mut apu, &mut cpu, &[
+        (0x4015, 0x01),
+        (0x4000, 0xbf),
+        (0x4002, 0xfd),
+        (0x4003, 0x08),
+    ]);
+
+    assert_eq!(apu.read(MemoryPtr(0x4015), &mut cpu) & STATUS_PULSE1, STATUS_PULSE1);
+
+    //one 60hz frame worth of cpu cycles
+    cpu.cycle_count = 29781;
+    catch_up(&mut apu, &mut cpu, &mut Ram::new());
+    apu.end_frame();
+    let samples = apu.samples().to_vec();
+    assert_eq!(samples.len(), 733);
+
+    //period $fd gives a ~440hz square wave: 16 * (0xfd + 1) cpu cycles per period.
+    //the high-pass filters make every edge decay towards zero, so look for the steps instead
+    assert_eq!(count_rising_edges(&samples), 8);
+
+    //the next frame continues where the previous one stopped
+    cpu.cycle_count += 29781;
+    catch_up(&mut apu, &mut cpu, &mut Ram::new());
+    apu.end_frame();
+    assert_eq!(apu.samples().len(), 734);
+    assert_eq!(count_rising_edges(apu.samples()), 7);
+
+    write_registers(&mut apu, &mut cpu, &[(0x4015, 0x00)]);
+    assert_eq!(apu.read(MemoryPtr(0x4015), &mut cpu) & STATUS_PULSE1, 0);
+}
+
+#[test]
+fn test_triangle_sequence() {
+    let mut triangle = Triangle::new();
+    triangle.length_counter.set_enabled(true);
+
+    //control flag set, linear counter reload 0x7f, timer period 2
+    triangle.write_linear_counter(0xff);
+    triangle.write_timer_low(0x02);
+    triangle.write_timer_high(0x08);
+
+    //the sequencer does not advance until the linear counter is loaded
+    for _ in 0..9 {
+        triangle.clock_timer();
+    }
+    assert_eq!(triangle.output(), 15);
+
+    triangle.clock_quarter_frame();
+    assert_eq!(triangle.linear_counter(), 0x7f);
+
+    let mut output = Vec::new();
+    for _ in 0..34 {
+        for _ in 0..3 {
+            triangle.clock_timer();
+        }
+        output.push(triangle.output());
+    }
+
+    let mut expected: Vec<u8> = (0..15).rev().collect();
+    expected.extend(0..16);
+    expected.extend([15, 14, 13]);
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn test_triangle_linear_counter() {
+    let mut triangle = Triangle::new();
+    triangle.length_counter.set_enabled(true);
+
+    //control flag clear: the reload flag is cleared after the first quarter frame
+    triangle.write_linear_counter(0x02);
+    triangle.write_timer_high(0x08);
+
+    triangle.clock_quarter_frame();
+    assert_eq!(triangle.linear_counter(), 2);
+    triangle.clock_quarter_frame();
+    assert_eq!(triangle.linear_counter(), 1);
+    triangle.clock_quarter_frame();
+    assert_eq!(triangle.linear_counter(), 0);
+    triangle.clock_quarter_frame();
+    assert_eq!(triangle.linear_counter(), 0);
+
+    //the silenced channel holds its current output level
+    let level = triangle.output();
+    for _ in 0..100 {
+        triangle.clock_timer();
+    }
+    assert_eq!(triangle.output(), level);
+}
+
+fn noise_sequence_length(mode: u8) -> u32 {
+    let mut noise = Noise::new();
+    noise.write_period(mode);
+
+    let mut steps = 0;
+    loop {
+        for _ in 0..4 {
+            noise.clock_timer();
+        }
+        steps += 1;
+        if noise.shift_register() == 1 {
+            return steps;
+        }
+    }
+}
+
+#[test]
+fn test_noise_lfsr() {
+    let mut noise = Noise::new();
+    noise.write_period(0x00);
+
+    for _ in 0..4 {
+        noise.clock_timer();
+    }
+    assert_eq!(noise.shift_register(), 0x4000);
+
+    assert_eq!(noise_sequence_length(0x00), 32767);
+    assert_eq!(noise_sequence_length(0x80), 93);
+}
+
+#[test]
+fn test_noise_output() {
+    let mut noise = Noise::new();
+    noise.length_counter.set_enabled(true);
+    noise.write_control(0x1a);
+    noise.write_period(0x00);
+    noise.write_length(0x08);
+
+    //bit 0 of the shift register mutes the channel
+    assert_eq!(noise.output(), 0);
+    for _ in 0..4 {
+        noise.clock_timer();
+    }
+    assert_eq!(noise.output(), 0x0a);
+
+    noise.length_counter.set_enabled(false);
+    assert_eq!(noise.output(), 0);
+}
+
+#[test]
+fn test_status_length_counters() {
+    let mut apu = Apu::new(DEFAULT_SAMPLE_RATE);
+    let mut cpu = Cpu::new();
+
+    write_registers(&mut apu, &mut cpu, &[
+        (0x4017, 0x40),
+        (0x4015, 0x0f),
+        (0x4003, 0x08),
+        (0x4007, 0x08),
+        (0x400b, 0x08),
+        (0x400f, 0x08),
+    ]);
+    assert_eq!(apu.read(MemoryPtr(0x4015), &mut cpu), 0x0f);
+
+    write_registers(&mut apu, &mut cpu, &[(0x4015, 0x05)]);
+    assert_eq!(apu.read(MemoryPtr(0x4015), &mut cpu), 0x05);
+
+    //length index 3 runs out after two half frames
+    write_registers(&mut apu, &mut cpu, &[(0x400b, 0x18), (0x4015, 0x0c), (0x400b, 0x18), (0x400f, 0x18)]);
+    cpu.cycle_count += 40000;
+    catch_up(&mut apu, &mut cpu, &mut Ram::new());
+    assert_eq!(apu.read(MemoryPtr(0x4015), &mut cpu), 0x00);
+}
+
+fn sample_ram(data: &[u8]) -> Ram {
+    //ram is mirrored every 2kb, so $c000 maps to the start of the buffer
+    let mut state = [0u8; 2048];
+    state[..data.len()].copy_from_slice(data);
+
+    let mut ram = Ram::new();
+    ram.set_ram_state(state);
+    ram
+}
+
+#[test]
+fn test_dmc_output_unit() {
+    let mut dmc = Dmc::new();
+    dmc.write_control(0x0f);
+    dmc.write_output_level(0x40);
+    dmc.write_sample_length(0x00);
+    dmc.set_enabled(true);
+
+    assert_eq!(dmc.pending_fetch(), Some(MemoryPtr(0xc000)));
+    dmc.load_sample(0b0000_1111);
+    assert_eq!(dmc.pending_fetch(), None);
+    assert!(!dmc.active());
+
+    //the first output cycle is silent and only ends by moving the buffered byte into the shift register
+    //the timer only picks up the new rate once the power-up period expires
+    for _ in 0..428 {
+        dmc.clock_timer();
+    }
+    let mut levels = vec![dmc.output()];
+    for _ in 0..16 {
+        for _ in 0..54 {
+            dmc.clock_timer();
+        }
+        levels.push(dmc.output());
+    }
+
+    let mut expected = vec![0x40; 8];
+    expected.extend([0x42, 0x44, 0x46, 0x48, 0x46, 0x44, 0x42, 0x40, 0x40]);
+    assert_eq!(levels, expected);
+}
+
+#[test]
+fn test_dmc_irq_and_stall() {
+    let mut apu = Apu::new(DEFAULT_SAMPLE_RATE);
+    let mut cpu = Cpu::new();
+    let mut ram = sample_ram(&[0xff; 17]);
+
+    //irq enabled, no loop, fastest rate, 17 byte sample at $c000
+    write_registers(&mut apu, &mut cpu, &[
+        (0x4017, 0x40),
+        (0x4010, 0x8f),
+        (0x4012, 0x00),
+        (0x4013, 0x01),
+        (0x4015, 0x10),
+    ]);
+    assert_eq!(apu.read(MemoryPtr(0x4015), &mut cpu), STATUS_DMC);
+
+    let start = cpu.cycle_count;
+    cpu.cycle_count += 1;
+    catch_up(&mut apu, &mut cpu, &mut ram);
+
+    //the first fetch happens right away and steals cpu cycles
+    assert_eq!(cpu.cycle_count, start + 1 + DMC_STALL_CYCLES);
+    assert!(!cpu.irq_requested());
+
+    //each byte lasts 8 * 54 cpu cycles
+    cpu.cycle_count += 17 * 8 * 54;
+    catch_up(&mut apu, &mut cpu, &mut ram);
+    assert!(cpu.irq_requested());
+    assert_eq!(apu.read(MemoryPtr(0x4015), &mut cpu), STATUS_DMC_IRQ);
+    assert_eq!(apu.dmc.output(), 126);
+
+    //reading the status does not acknowledge the dmc interrupt, writing it does
+    assert!(cpu.irq_requested());
+    write_registers(&mut apu, &mut cpu, &[(0x4015, 0x00)]);
+    assert!(!cpu.irq_requested());
+    assert_eq!(apu.read(MemoryPtr(0x4015), &mut cpu), 0);
+
+    //a mapper irq on the same line stays raised through the dmc and frame counter acknowledges
+    cpu.set_irq(IRQ_MAPPER, true);
+    write_registers(&mut apu, &mut cpu, &[(0x4010, 0x0f), (0x4015, 0x00), (0x4017, 0x40)]);
+    assert_eq!(cpu.irq_sources, IRQ_MAPPER);
+}
+
+#[test]
+fn test_dmc_loop() {
+    let mut apu = Apu::new(DEFAULT_SAMPLE_RATE);
+    let mut cpu = Cpu::new();
+    let mut ram = sample_ram(&[0x00; 1]);
+
+    write_registers(&mut apu, &mut cpu, &[
+        (0x4017, 0x40),
+        (0x4010, 0xcf),
+        (0x4011, 0x7f),
+        (0x4013, 0x00),
+        (0x4015, 0x10),
+    ]);
+
+    let start = cpu.cycle_count;
+    cpu.cycle_count += 100 * 8 * 54;
+    catch_up(&mut apu, &mut cpu, &mut ram);
+
+    //looping samples never raise the interrupt and keep fetching
+    assert!(!cpu.irq_requested());
+    assert_eq!(apu.read(MemoryPtr(0x4015), &mut cpu), STATUS_DMC);
+    assert!(cpu.cycle_count - start > 100 * 8 * 54 + 100 * DMC_STALL_CYCLES);
+    assert_eq!(apu.dmc.output(), 1);
+}
+
+#[test]
+fn test_mixer_tables() {
+    let mixer = Mixer::new();
+
+    assert_eq!(mixer.mix(ChannelLevels::default()), 0.0);
+
+    let pulse = mixer.mix(ChannelLevels { pulse1: 15, pulse2: 15, ..Default::default() });
+    assert!((pulse - 0.2575).abs() < 0.0001);
+
+    let tnd = mixer.mix(ChannelLevels { triangle: 15, noise: 15, dmc: 127, ..Default::default() });
+    assert!((tnd - 0.7425).abs() < 0.0001);
+
+    //the dac is non-linear: doubling a level does not double the output
+    let single = mixer.mix(ChannelLevels { pulse1: 8, ..Default::default() });
+    let double = mixer.mix(ChannelLevels { pulse1: 8, pulse2: 8, ..Default::default() });
+    assert!(double < 2.0 * single);
+}
+
+#[test]
+fn test_resampler_step() {
+    let mut resampler = Resampler::new(CPU_FREQUENCY, 44100);
+    let mut output = Vec::new();
+
+    resampler.set_amplitude(1000, 10000);
+    resampler.end_frame(29781, &mut output);
+    assert_eq!(output.len(), 733);
+
+    //the kernel adds up to exactly one, so the step settles on the exact amplitude
+    assert!(output[..20].iter().all(|v| *v == 0));
+    assert!(output[50..].iter().all(|v| *v == 10000));
+
+    //ringing around the step stays close to the gibbs overshoot of a sinc kernel
+    let overshoot = output.iter().max().unwrap() - 10000;
+    assert!(overshoot > 0 && overshoot < 1500);
+}
+
+#[test]
+fn test_resampler_sample_counts() {
+    for (rate, expected) in [(44100, [733, 734, 734]), (48000, [798, 799, 799])] {
+        let mut resampler = Resampler::new(CPU_FREQUENCY, rate);
+        let mut lengths = Vec::new();
+        for _ in 0..3 {
+            let mut output = Vec::new();
+            resampler.end_frame(29781, &mut output);
+            lengths.push(output.len());
+        }
+        assert_eq!(lengths, expected);
+    }
+}
+
+fn render_triangle(period_low: u8, period_high: u8, sample_rate: u32) -> Vec<i16> {
+    let mut apu = Apu::new(sample_rate);
+    let mut cpu = Cpu::new();
+
+    write_registers(&mut apu, &mut cpu, &[
+        (0x4017, 0x40),
+        (0x4015, 0x04),
+        (0x4008, 0xff),
+        (0x400a, period_low),
+        (0x400b, period_high),
+    ]);
+
+    //let the filters settle for a frame before capturing
+    let mut samples = Vec::new();
+    for frame in 0..2 {
+        cpu.cycle_count += 29781;
+        catch_up(&mut apu, &mut cpu, &mut Ram::new());
+        apu.end_frame();
+        if frame == 1 {
+            samples.extend_from_slice(apu.samples());
+        }
+    }
+
+    samples
+}
+
+#[test]
+fn test_output_band_limited() {
+    //~437hz triangle
+    let audible = render_triangle(0x7f, 0x00, 48000);
+    //~56khz triangle, well above the host nyquist frequency
+    let ultrasonic = render_triangle(0x00, 0x00, 48000);
+
+    let peak = |s: &[i16]| s.iter().map(|v| (*v as i32).abs()).max().unwrap();
+    assert!(peak(&audible) > 2000);
+    assert!(peak(&ultrasonic) * 20 < peak(&audible));
+}
+
+#[test]
+fn test_output_deterministic() {
+    let first = render_triangle(0x55, 0x01, 44100);
+    let second = render_triangle(0x55, 0x01, 44100);
+
+    assert_eq!(first.len(), 734);
+    assert_eq!(first, second);
+}
+
+//saves a channel, overwrites part of its state after the 12 byte header and loads it back
+fn load_patched<T: SaveState>(channel: &mut T, offset: usize, patch: &[u8]) -> Result<(), StateError> {
+    let mut w = StateWriter::new();
+    channel.save_state(&mut w);
+    let mut data = w.into_inner();
+    data[12 + offset..12 + offset + patch.len()].copy_from_slice(patch);
+    channel.load_state(&mut StateReader::new(&data)?)
+}
+
+#[test]
+fn test_corrupt_channel_states() {
+    let mut noise = Noise::new();
+    assert!(load_patched(&mut noise, 1, &0x0004u16.to_le_bytes()).is_ok());
+    //a timer period of 0 would underflow on the next reload
+    assert!(matches!(load_patched(&mut noise, 1, &[0, 0]), Err(StateError::InvalidData)));
+    assert!(matches!(load_patched(&mut noise, 1, &[5, 0]), Err(StateError::InvalidData)));
+
+    let mut dmc = Dmc::new();
+    assert!(load_patched(&mut dmc, 18, &[8]).is_ok());
+    assert!(matches!(load_patched(&mut dmc, 18, &[0]), Err(StateError::InvalidData)));
+    assert!(matches!(load_patched(&mut dmc, 18, &[9]), Err(StateError::InvalidData)));
+    assert!(matches!(load_patched(&mut dmc, 3, &[0, 0]), Err(StateError::InvalidData)));
+
+    //periods have 11 bits, $ffff would overflow the sweep target of the pulse
+    let mut pulse = Pulse::new(SweepNegate::OnesComplement);
+    assert!(load_patched(&mut pulse, 2, &0x07ffu16.to_le_bytes()).is_ok());
+    assert!(matches!(load_patched(&mut pulse, 2, &[0xff, 0xff]), Err(StateError::InvalidData)));
+    assert!(matches!(load_patched(&mut pulse, 2, &[0x00, 0x08]), Err(StateError::InvalidData)));
+
+    let mut triangle = Triangle::new();
+    assert!(load_patched(&mut triangle, 4, &0x07ffu16.to_le_bytes()).is_ok());
+    assert!(matches!(load_patched(&mut triangle, 4, &[0xff, 0xff]), Err(StateError::InvalidData)));
+}
@@ -0,0 +1,120 @@
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+use super::length_counter::LengthCounter;
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+pub struct Triangle {
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    timer_period: u16,
+    timer: u16,
+    sequence_pos: u8,
+    pub length_counter: LengthCounter,
+}
+
+impl Triangle {
+    pub fn new() -> Triangle {
+        Triangle {
+            control: false,
+            linear_reload_value: 0,
+            linear_counter: 0,
+            linear_reload: false,
+            timer_period: 0,
+            timer: 0,
+            sequence_pos: 0,
+            length_counter: LengthCounter::new(),
+        }
+    }
+
+    //$4008: CRRR RRRR
+    pub fn write_linear_counter(&mut self, v: u8) {
+        self.control = v & 0x80 != 0;
+        self.length_counter.set_halt(self.control);
+        self.linear_reload_value = v & 0x7f;
+    }
+
+    //$400a: LLLL LLLL
+    pub fn write_timer_low(&mut self, v: u8) {
+        self.timer_period = (self.timer_period & 0x700) | v as u16;
+    }
+
+    //$400b: LLLL LHHH
+    pub fn write_timer_high(&mut self, v: u8) {
+        self.timer_period = (self.timer_period & 0xff) | (((v & 0x7) as u16) << 8);
+        self.length_counter.load(v >> 3);
+        self.linear_reload = true;
+    }
+
+    //unlike the other channels, the triangle timer is clocked every cpu cycle
+    pub fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.timer_period;
+        if self.linear_counter > 0 && self.length_counter.active() {
+            self.sequence_pos = (self.sequence_pos + 1) & 0x1f;
+        }
+    }
+
+    pub fn clock_quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub fn clock_half_frame(&mut self) {
+        self.length_counter.clock();
+    }
+
+    #[allow(dead_code)]
+    pub fn linear_counter(&self) -> u8 {
+        self.linear_counter
+    }
+
+    //a silenced triangle keeps outputting the last step of the sequence
+    pub fn output(&self) -> u8 {
+        SEQUENCE[self.sequence_pos as usize]
+    }
+}
+
+impl SaveState for Triangle {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.control);
+        w.u8(self.linear_reload_value);
+        w.u8(self.linear_counter);
+        w.bool(self.linear_reload);
+        w.u16(self.timer_period);
+        w.u16(self.timer);
+        w.u8(self.sequence_pos);
+        self.length_counter.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.control = r.bool()?;
+        self.linear_reload_value = r.u8()?;
+        self.linear_counter = r.u8()?;
+        self.linear_reload = r.bool()?;
+        self.timer_period = r.u16()?;
+        self.timer = r.u16()?;
+        self.sequence_pos = r.u8()? & 0x1f;
+        self.length_counter.load_state(r)?;
+
+        if self.timer_period > 0x7ff {
+            return Err(StateError::InvalidData);
+        }
+        Ok(())
+    }
+}
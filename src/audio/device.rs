@@ -0,0 +1,120 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use log::error;
+
+use super::AudioSink;
+
+//amount of audio kept queued for the device
+const TARGET_LATENCY_MS: usize = 60;
+
+//plays samples through the default output device of the host
+pub struct DeviceSink {
+    _stream: cpal::Stream,
+    queue: Arc<Mutex<VecDeque<i16>>>,
+    sample_rate: u32,
+    target_len: usize,
+}
+
+#[derive(Debug)]
+pub enum DeviceSinkError {
+    NoDevice,
+    UnsupportedFormat(cpal::SampleFormat),
+    Config(cpal::DefaultStreamConfigError),
+    Build(cpal::BuildStreamError),
+    Play(cpal::PlayStreamError),
+}
+
+impl fmt::Display for DeviceSinkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceSinkError::NoDevice => write!(f, "no output device"),
+            DeviceSinkError::UnsupportedFormat(format) => write!(f, "unsupported sample format {}", format),
+            DeviceSinkError::Config(e) => write!(f, "{}", e),
+            DeviceSinkError::Build(e) => write!(f, "{}", e),
+            DeviceSinkError::Play(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl DeviceSink {
+    pub fn new() -> Result<DeviceSink, DeviceSinkError> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .ok_or(DeviceSinkError::NoDevice)?;
+        let supported = device.default_output_config().map_err(DeviceSinkError::Config)?;
+
+        let sample_format = supported.sample_format();
+        let config: cpal::StreamConfig = supported.into();
+        let channels = config.channels as usize;
+        let sample_rate = config.sample_rate.0;
+
+        let queue = Arc::new(Mutex::new(VecDeque::new()));
+        let source = queue.clone();
+        let on_error = |e| error!("audio stream error: {}", e);
+
+        let stream = match sample_format {
+            cpal::SampleFormat::I16 => device.build_output_stream(
+                &config,
+                move |data: &mut [i16], _: &cpal::OutputCallbackInfo| fill(data, channels, &source, |v| v),
+                on_error,
+                None,
+            ),
+            cpal::SampleFormat::F32 => device.build_output_stream(
+                &config,
+                move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
+                    fill(data, channels, &source, |v| v as f32 / i16::MAX as f32)
+                },
+                on_error,
+                None,
+            ),
+            format => return Err(DeviceSinkError::UnsupportedFormat(format)),
+        }
+        .map_err(DeviceSinkError::Build)?;
+        stream.play().map_err(DeviceSinkError::Play)?;
+
+        Ok(DeviceSink {
+            _stream: stream,
+            queue,
+            sample_rate,
+            target_len: sample_rate as usize * TARGET_LATENCY_MS / 1000,
+        })
+    }
+}
+
+//copies queued mono samples to every channel of the device, repeating the last one on underruns
+fn fill<T: Copy>(data: &mut [T], channels: usize, queue: &Mutex<VecDeque<i16>>, convert: impl Fn(i16) -> T) {
+    let mut queue = queue.lock().unwrap();
+    let mut last = 0;
+    for frame in data.chunks_mut(channels) {
+        if let Some(v) = queue.pop_front() {
+            last = v;
+        }
+        for out in frame.iter_mut() {
+            *out = convert(last);
+        }
+    }
+}
+
+impl AudioSink for DeviceSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_samples(&mut self, samples: &[i16]) {
+        //wait for the device to drain the queue, this is what limits the frame rate
+        while self.queue.lock().unwrap().len() > 2 * self.target_len {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        self.queue.lock().unwrap().extend(samples.iter());
+    }
+
+    fn buffer_level(&self) -> Option<f64> {
+        Some(self.queue.lock().unwrap().len() as f64 / self.target_len as f64)
+    }
+}
@@ -0,0 +1,92 @@
+#[cfg(feature = "audio-device")]
+pub mod device;
+pub mod null;
+pub mod wav;
+
+use std::{
+    io::{self, Seek, Write},
+    time::{Duration, Instant},
+};
+
+use crate::Nes;
+use wav::WavWriter;
+
+//ntsc consoles run at ~60.0988 frames per second
+const FRAME_DURATION: Duration = Duration::from_nanos(16_639_267);
+
+//largest deviation from the nominal sample rate used by dynamic rate control
+const MAX_RATE_DELTA: f64 = 0.005;
+
+pub trait AudioSink {
+    fn sample_rate(&self) -> u32;
+
+    //queues one frame of mono samples. real-time sinks block here while their buffer is full,
+    //which is what paces the emulation
+    fn push_samples(&mut self, samples: &[i16]);
+
+    //fill level of the playback buffer relative to the target latency (1.0 is on target).
+    //sinks that do not play in real time return None
+    fn buffer_level(&self) -> Option<f64>;
+}
+
+//resampling ratio that moves the buffer level of a real-time sink back towards its target
+pub fn rate_adjustment(buffer_level: f64) -> f64 {
+    1.0 + MAX_RATE_DELTA * (1.0 - buffer_level).clamp(-1.0, 1.0)
+}
+
+//sleeps until the next frame is due, for sinks that cannot pace the emulation themselves
+pub struct FrameLimiter {
+    next_frame: Instant,
+}
+
+impl Default for FrameLimiter {
+    fn default() -> Self {
+        FrameLimiter::new()
+    }
+}
+
+impl FrameLimiter {
+    pub fn new() -> FrameLimiter {
+        FrameLimiter {
+            next_frame: Instant::now(),
+        }
+    }
+
+    pub fn wait(&mut self) {
+        self.next_frame += FRAME_DURATION;
+
+        let now = Instant::now();
+        if self.next_frame > now {
+            std::thread::sleep(self.next_frame - now);
+        } else if now - self.next_frame > FRAME_DURATION * 4 {
+            //too far behind, don't try to catch up
+            self.next_frame = now;
+        }
+    }
+}
+
+//runs the console as fast as possible for up to `frames` frames, recording the audio of every frame.
+//`after_frame` gets the number of frames run so far and ends the run early by returning false.
+//returns the number of frames that ran
+pub fn run_headless<W, F>(console: &mut Nes, frames: u64, mut recorder: Option<&mut WavWriter<W>>, mut after_frame: F) -> io::Result<u64>
+where
+    W: Write + Seek,
+    F: FnMut(&mut Nes, u64) -> bool,
+{
+    let mut frame = 0;
+    while frame < frames {
+        console.run_frame();
+        frame += 1;
+
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.write_samples(console.audio_samples())?;
+        }
+        if !after_frame(console, frame) {
+            break;
+        }
+    }
+    Ok(frame)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,24 @@
+use super::AudioSink;
+
+//discards every sample, for machines without a sound device
+pub struct NullSink {
+    sample_rate: u32,
+}
+
+impl NullSink {
+    pub fn new(sample_rate: u32) -> NullSink {
+        NullSink { sample_rate }
+    }
+}
+
+impl AudioSink for NullSink {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push_samples(&mut self, _: &[i16]) {}
+
+    fn buffer_level(&self) -> Option<f64> {
+        None
+    }
+}
@@ -0,0 +1,90 @@
+use std::io::Cursor;
+
+use super::wav::{grow_data_size, WavWriter};
+use super::{rate_adjustment, run_headless};
+use crate::{mappers::{chr::ChrMemory, nrom}, Nes};
+
+//enables pulse 1 with a constant volume tone and spins forever
+const TONE_PROGRAM: [u8; 23] = [
+    0xa9, 0x01, 0x8d, 0x15, 0x40, //lda #$01, sta $4015
+    0xa9, 0xbf, 0x8d, 0x00, 0x40, //lda #$bf, sta $4000
+    0xa9, 0xfd, 0x8d, 0x02, 0x40, //lda #$fd, sta $4002
+    0xa9, 0x08, 0x8d, 0x03, 0x40, //lda #$08, sta $4003
+    0x4c, 0x14, 0x80, //jmp $8014
+];
+
+fn tone_console() -> Nes {
+    let mut prg_rom = [0; 16384];
+    prg_rom[..TONE_PROGRAM.len()].copy_from_slice(&TONE_PROGRAM);
+    //reset vector
+    prg_rom[0x3ffc] = 0x00;
+    prg_rom[0x3ffd] = 0x80;
+
+    let cartridge = nrom::Nrom::new(&vec![prg_rom], ChrMemory::ram(8192), nrom::Mirroring::Horizontal).ok().unwrap();
+    Nes::new(Box::new(cartridge))
+}
+
+fn record_tone(frames: u64) -> Vec<u8> {
+    let mut console = tone_console();
+    let mut recorder = WavWriter::new(Cursor::new(Vec::new()), console.sample_rate()).unwrap();
+    assert_eq!(run_headless(&mut console, frames, Some(&mut recorder), |_, _| true).unwrap(), frames);
+    recorder.finish().unwrap().into_inner()
+}
+
+//64-bit fnv-1a, stable across rust versions unlike DefaultHasher
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, v| (hash ^ *v as u64).wrapping_mul(0x100000001b3))
+}
+
+#[test]
+fn test_wav_header() {
+    let mut writer = WavWriter::new(Cursor::new(Vec::new()), 44100).unwrap();
+    writer.write_samples(&[0, 1, -1]).unwrap();
+    writer.write_samples(&[0x1234]).unwrap();
+    let data = writer.finish().unwrap().into_inner();
+
+    assert_eq!(data.len(), 44 + 8);
+    assert_eq!(&data[0..4], b"RIFF");
+    assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()), 36 + 8);
+    assert_eq!(&data[8..16], b"WAVEfmt ");
+    assert_eq!(u32::from_le_bytes(data[24..28].try_into().unwrap()), 44100);
+    assert_eq!(u32::from_le_bytes(data[28..32].try_into().unwrap()), 88200);
+    assert_eq!(&data[36..40], b"data");
+    assert_eq!(u32::from_le_bytes(data[40..44].try_into().unwrap()), 8);
+    assert_eq!(&data[44..], &[0, 0, 1, 0, 0xff, 0xff, 0x34, 0x12]);
+}
+
+#[test]
+fn test_wav_size_limit() {
+    assert_eq!(grow_data_size(8, 4).unwrap(), 16);
+    //the riff size is the data size plus 36 and has to fit in 32 bits as well
+    assert_eq!(grow_data_size(u32::MAX - 38, 1).unwrap(), u32::MAX - 36);
+    assert!(grow_data_size(u32::MAX - 37, 1).is_err());
+    assert!(grow_data_size(0, usize::MAX).is_err());
+}
+
+#[test]
+fn test_rate_adjustment() {
+    assert_eq!(rate_adjustment(1.0), 1.0);
+
+    //a draining buffer asks for more samples, a full one for fewer
+    assert!(rate_adjustment(0.5) > 1.0);
+    assert!(rate_adjustment(1.5) < 1.0);
+
+    assert_eq!(rate_adjustment(0.0), rate_adjustment(-3.0));
+    assert_eq!(rate_adjustment(2.0), rate_adjustment(10.0));
+}
+
+#[test]
+fn test_headless_recording() {
+    let wav = record_tone(30);
+
+    //one frame is 733 or 734 samples at 44.1 kHz
+    let samples = (wav.len() - 44) / 2;
+    assert!((30 * 733..=30 * 734).contains(&samples));
+    assert!(wav[44..].iter().any(|v| *v != 0));
+
+    //the recording only depends on the rom, so it can be compared by hash
+    assert_eq!(fnv1a(&wav), fnv1a(&record_tone(30)));
+    assert_eq!(fnv1a(&wav), 0x06425e64bd8041e5);
+}
@@ -0,0 +1,126 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use log::error;
+
+use super::AudioSink;
+
+const HEADER_SIZE: u32 = 44;
+//the riff size field counts everything after it, so it runs out slightly before 4 GiB of samples
+const MAX_DATA_SIZE: u32 = u32::MAX - (HEADER_SIZE - 8);
+
+//16-bit mono pcm wav file writer. the header sizes are patched in by `finish`
+pub struct WavWriter<W: Write + Seek> {
+    output: W,
+    sample_rate: u32,
+    data_size: u32,
+}
+
+impl WavWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<WavWriter<BufWriter<File>>> {
+        WavWriter::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut output: W, sample_rate: u32) -> io::Result<WavWriter<W>> {
+        write_header(&mut output, sample_rate, 0)?;
+        Ok(WavWriter {
+            output,
+            sample_rate,
+            data_size: 0,
+        })
+    }
+
+    //fails without writing anything once the file would be too large for the header sizes
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        let data_size = grow_data_size(self.data_size, samples.len())?;
+        for v in samples {
+            self.output.write_all(&v.to_le_bytes())?;
+        }
+        self.data_size = data_size;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.output.seek(SeekFrom::Start(0))?;
+        write_header(&mut self.output, self.sample_rate, self.data_size)?;
+        self.output.seek(SeekFrom::End(0))?;
+        self.output.flush()?;
+        Ok(self.output)
+    }
+}
+
+pub(super) fn grow_data_size(data_size: u32, samples: usize) -> io::Result<u32> {
+    samples.checked_mul(2)
+        .and_then(|bytes| u32::try_from(bytes).ok())
+        .and_then(|bytes| data_size.checked_add(bytes))
+        .filter(|size| *size <= MAX_DATA_SIZE)
+        .ok_or_else(|| io::Error::other("the wav file reached its size limit of 4 GiB"))
+}
+
+fn write_header<W: Write>(output: &mut W, sample_rate: u32, data_size: u32) -> io::Result<()> {
+    //format description: http://soundfile.sapp.org/doc/WaveFormat/
+    output.write_all(b"RIFF")?;
+    output.write_all(&(HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+    output.write_all(b"WAVE")?;
+
+    output.write_all(b"fmt ")?;
+    output.write_all(&16u32.to_le_bytes())?;
+    output.write_all(&1u16.to_le_bytes())?; //pcm
+    output.write_all(&1u16.to_le_bytes())?; //mono
+    output.write_all(&sample_rate.to_le_bytes())?;
+    output.write_all(&(sample_rate * 2).to_le_bytes())?; //byte rate
+    output.write_all(&2u16.to_le_bytes())?; //block align
+    output.write_all(&16u16.to_le_bytes())?; //bits per sample
+
+    output.write_all(b"data")?;
+    output.write_all(&data_size.to_le_bytes())?;
+    Ok(())
+}
+
+//writes everything to a wav file instead of playing it
+pub struct WavSink {
+    writer: Option<WavWriter<BufWriter<File>>>,
+}
+
+impl WavSink {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<WavSink> {
+        Ok(WavSink {
+            writer: Some(WavWriter::create(path, sample_rate)?),
+        })
+    }
+}
+
+impl AudioSink for WavSink {
+    fn sample_rate(&self) -> u32 {
+        self.writer.as_ref().map_or(0, |w| w.sample_rate)
+    }
+
+    fn push_samples(&mut self, samples: &[i16]) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(e) = writer.write_samples(samples) {
+                error!("failed writing audio: {}", e);
+                self.writer = None;
+            }
+        }
+    }
+
+    fn buffer_level(&self) -> Option<f64> {
+        None
+    }
+}
+
+impl Drop for WavSink {
+    fn drop(&mut self) {
+        if let Some(writer) = self.writer.take() {
+            if let Err(e) = writer.finish() {
+                error!("failed finishing wav file: {}", e);
+            }
+        }
+    }
+}
+
@@ -0,0 +1,64 @@
+use std::{
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+
+use crate::mappers::Cartridge;
+
+//keeps the battery backed ram of a cartridge in a .sav file next to the rom
+pub struct BatterySave {
+    path: PathBuf,
+    last_saved: Vec<u8>,
+}
+
+impl BatterySave {
+    pub fn new<P: AsRef<Path>>(rom_path: P) -> BatterySave {
+        BatterySave {
+            path: rom_path.as_ref().with_extension("sav"),
+            last_saved: Vec::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    //a missing save file is not an error, the game starts with empty ram
+    pub fn load(&mut self, cartridge: &mut dyn Cartridge) -> io::Result<()> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        if cartridge.load_save_ram(&data) {
+            debug!("loaded {}", self.path.display());
+            self.last_saved = data;
+        } else {
+            warn!("{} does not match the size of the cartridge ram, ignoring it", self.path.display());
+        }
+        Ok(())
+    }
+
+    //writes the ram if it changed since the last flush
+    pub fn flush(&mut self, cartridge: &dyn Cartridge) -> io::Result<()> {
+        let Some(ram) = cartridge.save_ram() else {
+            return Ok(());
+        };
+        if ram == self.last_saved.as_slice() {
+            return Ok(());
+        }
+
+        //write to a temporary file first so a crash can't leave a half written save
+        let temporary = self.path.with_extension("sav.tmp");
+        fs::write(&temporary, ram)?;
+        fs::rename(&temporary, &self.path)?;
+
+        self.last_saved = ram.to_vec();
+        debug!("saved {}", self.path.display());
+        Ok(())
+    }
+}
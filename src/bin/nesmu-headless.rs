@@ -0,0 +1,166 @@
+use std::{env, fs, io::BufWriter, process::exit};
+
+use nesmu::{
+    audio::{run_headless, wav::WavWriter},
+    headless::{self, blargg, image, script::InputScript, Condition},
+    Nes, Renderer, HEIGHT, WIDTH,
+};
+
+const DEFAULT_FRAMES: u64 = 600;
+
+//exit codes
+const SUCCESS: i32 = 0;
+const FAILED: i32 = 1;
+const ERROR: i32 = 2;
+
+const USAGE: &str = "Usage: nesmu-headless <rom filename> [--frames <n>] [--until <addr>=<value>|<addr>!=<value>] \
+[--input <script>] [--png <filename>] [--ppm <filename>] [--record-audio <filename>] [--expect-hash <hash>] [--blargg] [--dot-ppu] [--no-sprite-limit]";
+
+//runs a rom without a window, for ci. exits with 0 when the run succeeded, 1 when the --until condition
+//was not reached within the frame limit or the framebuffer hash is not the expected one, 2 on errors
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() <= 1 {
+        println!("{}", USAGE);
+        exit(ERROR);
+    }
+
+    let mut frames = DEFAULT_FRAMES;
+    let mut condition = None;
+    let mut script = None;
+    let mut png = None;
+    let mut ppm = None;
+    let mut record_audio = None;
+    let mut expected_hash = None;
+    let mut test_rom = false;
+    let mut dot_ppu = false;
+    let mut unlimited_sprites = false;
+
+    let mut options = args[2..].iter();
+    while let Some(arg) = options.next() {
+        if arg == "--blargg" {
+            test_rom = true;
+            continue;
+        }
+        if arg == "--dot-ppu" {
+            dot_ppu = true;
+            continue;
+        }
+        if arg == "--no-sprite-limit" {
+            unlimited_sprites = true;
+            continue;
+        }
+
+        let Some(value) = options.next() else {
+            fail(&format!("missing value for {}\n{}", arg, USAGE));
+        };
+        match arg.as_str() {
+            "--frames" => frames = value.parse().unwrap_or_else(|_| fail(&format!("invalid frame count {}", value))),
+            "--until" => condition = Some(Condition::parse(value).unwrap_or_else(|e| fail(&e.to_string()))),
+            "--input" => {
+                let text = fs::read_to_string(value).unwrap_or_else(|e| fail(&format!("could not read {}: {}", value, e)));
+                script = Some(InputScript::parse(&text).unwrap_or_else(|e| fail(&format!("{}: {}", value, e))));
+            },
+            "--png" => png = Some(value),
+            "--ppm" => ppm = Some(value),
+            "--record-audio" => record_audio = Some(value),
+            "--expect-hash" => {
+                let hash = u64::from_str_radix(value.trim_start_matches("0x"), 16);
+                expected_hash = Some(hash.unwrap_or_else(|_| fail(&format!("invalid hash {}", value))));
+            },
+            _ => fail(&format!("unknown option {}\n{}", arg, USAGE)),
+        }
+    }
+
+    let mut console = Nes::load_rom(&args[1]).unwrap_or_else(|e| fail(&format!("could not load {}: {}", args[1], e)));
+    if dot_ppu {
+        console.set_renderer(Renderer::Dot);
+    }
+    console.set_unlimited_sprites(unlimited_sprites);
+
+    if test_rom {
+        exit(run_test_rom(&mut console, frames));
+    }
+
+    let mut recorder = record_audio.map(|filename| {
+        WavWriter::create(filename, console.sample_rate()).unwrap_or_else(|e| fail(&format!("could not create {}: {}", filename, e)))
+    });
+
+    //the script is applied before every frame, the condition checked after it
+    let apply_script = |console: &mut Nes, frame: u64| {
+        if let Some(script) = script.as_ref() {
+            script.apply(frame, console);
+        }
+    };
+    apply_script(&mut console, 0);
+
+    let mut reached = false;
+    let frame = run_headless(&mut console, frames, recorder.as_mut(), |console, frame| {
+        reached = condition.is_some_and(|c| c.check(console.peek(c.addr)));
+        apply_script(console, frame);
+        !reached
+    })
+    .unwrap_or_else(|e| fail(&format!("could not record audio: {}", e)));
+
+    if let Some(Err(e)) = recorder.map(|r| r.finish()) {
+        fail(&format!("could not finish the audio recording: {}", e));
+    }
+
+    let hash = headless::hash(console.framebuffer());
+    println!("frames: {}", frame);
+    println!("hash: {:016x}", hash);
+
+    let pixels = console.framebuffer_rgb();
+    if let Some(filename) = png {
+        write_image(filename, |w| image::write_png(w, WIDTH, HEIGHT, &pixels));
+    }
+    if let Some(filename) = ppm {
+        write_image(filename, |w| image::write_ppm(w, WIDTH, HEIGHT, &pixels));
+    }
+
+    let mut status = SUCCESS;
+    if let Some(c) = condition.filter(|_| !reached) {
+        println!("${:04x} did not become {}{:02x} within {} frames", c.addr, if c.equal { "" } else { "not " }, c.value, frames);
+        status = FAILED;
+    }
+    if expected_hash.is_some_and(|h| h != hash) {
+        println!("hash mismatch, expected {:016x}", expected_hash.unwrap());
+        status = FAILED;
+    }
+    exit(status);
+}
+
+//runs a rom that reports its result at $6000, see headless::blargg
+fn run_test_rom(console: &mut Nes, frames: u64) -> i32 {
+    match blargg::run_test_rom(console, frames) {
+        Ok(result) => {
+            println!("{}", result.text);
+            if result.passed() {
+                SUCCESS
+            } else {
+                println!("failed with code {}", result.code);
+                FAILED
+            }
+        },
+        Err(e) => {
+            println!("{}", e);
+            FAILED
+        },
+    }
+}
+
+fn write_image<F: FnOnce(&mut BufWriter<fs::File>) -> std::io::Result<()>>(filename: &str, write: F) {
+    let result = fs::File::create(filename).and_then(|f| {
+        let mut w = BufWriter::new(f);
+        write(&mut w)?;
+        std::io::Write::flush(&mut w)
+    });
+    if let Err(e) = result {
+        fail(&format!("could not write {}: {}", filename, e));
+    }
+}
+
+fn fail(message: &str) -> ! {
+    println!("{}", message);
+    exit(ERROR);
+}
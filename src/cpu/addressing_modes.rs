@@ -3,16 +3,40 @@ use crate::memory_controller::MemoryPtr;
 use super::{CpuMemory, CpuContext};
 
 
+//how an instruction uses its operand, stores and read-modify-writes always pay for fixing an indexed address
+#[derive(Clone, Copy, PartialEq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+//every bus access takes a cycle, so the modes perform the same reads as the real cpu, dummy ones included.
+//new() fetches the operand and does the address calculation, get() and set() access the operand itself
 pub trait AddrMode<'a, T: CpuMemory> {
     type Tp;
     fn new(context: &mut CpuContext<'a, T>) -> Self;
     fn get(&self, context: &mut CpuContext<'a, T>) -> Self::Tp;
     fn bytes_read() -> u16;
-    fn cycles() -> u64;
+
+    //modes that add an index to a 16 bit address first read from the address with the old high byte
+    fn fix_address(&self, _context: &mut CpuContext<'a, T>, _access: Access) {}
 }
 
 pub trait AddrModeWrite<'a, T: CpuMemory> {
     fn set(&self,  context: &mut CpuContext<'a, T>, v: u8);
+
+    //read-modify-write instructions write the unmodified value back before the result
+    fn modify(&self, context: &mut CpuContext<'a, T>, old: u8, new: u8) {
+        self.set(context, old);
+        self.set(context, new);
+    }
+}
+
+//indexed modes, the unstable stores mix the high byte of the unindexed address into the value they write
+pub trait AddrModeIndexed<'a, T: CpuMemory> {
+    fn address(&self) -> MemoryPtr;
+    fn index(context: &CpuContext<'a, T>) -> u8;
 }
 
 
@@ -25,14 +49,11 @@ impl <'a, T: CpuMemory> AddrMode<'a, T> for Immediate {
     }
     
     fn get(&self, context: &mut CpuContext<'a, T>) -> u8 {
-        context.memory.read(context.state.program_counter + 1, context.state)
+        context.read(context.state.program_counter + 1)
     }
     fn bytes_read() -> u16 {
         1
     }
-    fn cycles() -> u64 {
-        0
-    }
 }
 
 
@@ -44,24 +65,21 @@ impl <'a, T: CpuMemory> AddrMode<'a, T> for ZeroPage {
     type Tp = u8;
     fn new(context: &mut CpuContext<'a, T>) -> ZeroPage {
         ZeroPage { 
-            addr: MemoryPtr(context.memory.read(context.state.program_counter + 1, context.state) as u16)
+            addr: MemoryPtr(context.read(context.state.program_counter + 1) as u16)
         }
     }
     fn get(&self, context: &mut CpuContext<'a, T>) -> u8 {
-        context.memory.read(self.addr, context.state)
+        context.read(self.addr)
     }
     fn bytes_read() -> u16 {
         1
     }
-    fn cycles() -> u64 {
-        1
-    }
 }
 
 
 impl <'a, T: CpuMemory> AddrModeWrite<'a, T> for ZeroPage {
     fn set(&self, context: &mut CpuContext<'a, T>, v: u8) {
-        context.memory.write(self.addr, v, context.state);
+        context.write(self.addr, v);
     }
 }
 
@@ -73,24 +91,24 @@ pub struct ZeroPageX {
 impl <'a, T: CpuMemory> AddrMode<'a, T> for ZeroPageX {
     type Tp = u8;
     fn new(context: &mut CpuContext<'a, T>) -> ZeroPageX {
+        let base = context.read(context.state.program_counter + 1);
+        //the cpu reads the unindexed address while it adds the index
+        context.read(MemoryPtr(base as u16));
         ZeroPageX { 
-            addr: MemoryPtr(context.memory.read(context.state.program_counter + 1, context.state).wrapping_add(context.state.x) as u16),
+            addr: MemoryPtr(base.wrapping_add(context.state.x) as u16),
         }
     }
     fn get(&self, context: &mut CpuContext<'a, T>) -> u8 {
-        context.memory.read(self.addr, context.state)
+        context.read(self.addr)
     }
     fn bytes_read() -> u16 {
         1
     }
-    fn cycles() -> u64 {
-        2
-    }
 }
 
 impl <'a, T: CpuMemory> AddrModeWrite<'a, T> for ZeroPageX {
     fn set(&self, context: &mut CpuContext<'a, T>, v: u8) {
-        context.memory.write(self.addr, v, context.state);
+        context.write(self.addr, v);
     }
 }
 
@@ -102,25 +120,25 @@ pub struct ZeroPageY {
 impl <'a, T: CpuMemory> AddrMode<'a, T> for ZeroPageY {
     type Tp = u8;
     fn new(context: &mut CpuContext<'a, T>) -> ZeroPageY {
+        let base = context.read(context.state.program_counter + 1);
+        //the cpu reads the unindexed address while it adds the index
+        context.read(MemoryPtr(base as u16));
         ZeroPageY { 
-            addr: MemoryPtr(context.memory.read(context.state.program_counter + 1, context.state).wrapping_add(context.state.y) as u16),
+            addr: MemoryPtr(base.wrapping_add(context.state.y) as u16),
         }
     }
     fn get(&self, context: &mut CpuContext<'a, T>) -> u8 {
-        context.memory.read(self.addr, context.state)
+        context.read(self.addr)
     }
     fn bytes_read() -> u16 {
         1
     }
-    fn cycles() -> u64 {
-        2
-    }
 }
 
 
 impl <'a, T: CpuMemory> AddrModeWrite<'a, T> for ZeroPageY {
     fn set(&self, context: &mut CpuContext<'a, T>, v: u8) {
-        context.memory.write(self.addr, v, context.state);
+        context.write(self.addr, v);
     }
 }
 
@@ -132,92 +150,111 @@ pub struct Absolute {
 impl <'a, T: CpuMemory> AddrMode<'a, T> for Absolute {
     type Tp = u8;
     fn new(context: &mut CpuContext<'a, T>) -> Absolute {
-        let low = context.memory.read(context.state.program_counter + 1, context.state) as u16;
-        let high = context.memory.read(context.state.program_counter + 2, context.state) as u16;
+        let low = context.read(context.state.program_counter + 1) as u16;
+        let high = context.read(context.state.program_counter + 2) as u16;
         Absolute { 
             addr: MemoryPtr(high << 8 | low)
         }
     }
     fn get(&self, context: &mut CpuContext<'a, T>) -> u8 {
-        context.memory.read(self.addr, context.state)
+        context.read(self.addr)
     }
     fn bytes_read() -> u16 {
         2
     }
-    fn cycles() -> u64 {
-        2
-    }
 }
 
 
 impl <'a, T: CpuMemory> AddrModeWrite<'a, T> for Absolute {
     fn set(&self, context: &mut CpuContext<'a, T>, v: u8) {
-        context.memory.write(self.addr, v, context.state);
+        context.write(self.addr, v);
     }
 }
 
 
 pub struct AbsoluteX {
-    addr: MemoryPtr
+    addr: MemoryPtr,
+    unfixed: MemoryPtr,
 }
 
 impl <'a, T: CpuMemory> AddrMode<'a, T> for AbsoluteX {
     type Tp = u8;
     fn new(context: &mut CpuContext<'a, T>) -> AbsoluteX {
-        let low = context.memory.read(context.state.program_counter + 1, context.state) as u16;
-        let high = context.memory.read(context.state.program_counter + 2, context.state) as u16;
-        //TODO: check if page boundary was crossed and add extra cycle
+        let low = context.read(context.state.program_counter + 1) as u16;
+        let high = context.read(context.state.program_counter + 2) as u16;
+        let addr = (high << 8 | low).wrapping_add(context.state.x as u16);
         AbsoluteX { 
-            addr: MemoryPtr((high << 8 | low) + context.state.x as u16)
+            addr: MemoryPtr(addr),
+            unfixed: MemoryPtr((high << 8) | (addr & 0xff)),
         }
     }
     fn get(&self, context: &mut CpuContext<'a, T>) -> u8 {
-        context.memory.read(self.addr, context.state)
+        context.read(self.addr)
     }
     fn bytes_read() -> u16 {
         2
     }
-    fn cycles() -> u64 {
-        2
+    fn fix_address(&self, context: &mut CpuContext<'a, T>, access: Access) {
+        fix_indexed_address(context, self.addr, self.unfixed, access);
     }
 }
 
 
 impl <'a, T: CpuMemory> AddrModeWrite<'a, T> for AbsoluteX {
     fn set(&self, context: &mut CpuContext<'a, T>, v: u8) {
-        context.memory.write(self.addr, v, context.state);
+        context.write(self.addr, v);
+    }
+}
+
+impl <'a, T: CpuMemory> AddrModeIndexed<'a, T> for AbsoluteX {
+    fn address(&self) -> MemoryPtr {
+        self.addr
+    }
+    fn index(context: &CpuContext<'a, T>) -> u8 {
+        context.state.x
     }
 }
 
 pub struct AbsoluteY {
-    addr: MemoryPtr
+    addr: MemoryPtr,
+    unfixed: MemoryPtr,
 }
 
 impl <'a, T: CpuMemory> AddrMode<'a, T> for AbsoluteY {
     type Tp = u8;
     fn new(context: &mut CpuContext<'a, T>) -> AbsoluteY {
-        let low = context.memory.read(context.state.program_counter + 1, context.state) as u16;
-        let high = context.memory.read(context.state.program_counter + 2, context.state) as u16;
-        //TODO: check if page boundary was crossed
+        let low = context.read(context.state.program_counter + 1) as u16;
+        let high = context.read(context.state.program_counter + 2) as u16;
+        let addr = (high << 8 | low).wrapping_add(context.state.y as u16);
         AbsoluteY { 
-            addr: MemoryPtr((high << 8 | low).wrapping_add(context.state.y as u16))
+            addr: MemoryPtr(addr),
+            unfixed: MemoryPtr((high << 8) | (addr & 0xff)),
         }
     }
     fn get(&self, context: &mut CpuContext<'a, T>) -> u8 {
-        context.memory.read(self.addr, context.state)
+        context.read(self.addr)
     }
     fn bytes_read() -> u16 {
         2
     }
-    fn cycles() -> u64 {
-        2
+    fn fix_address(&self, context: &mut CpuContext<'a, T>, access: Access) {
+        fix_indexed_address(context, self.addr, self.unfixed, access);
     }
 }
 
 
 impl <'a, T: CpuMemory> AddrModeWrite<'a, T> for AbsoluteY {
     fn set(&self, context: &mut CpuContext<'a, T>, v: u8) {
-        context.memory.write(self.addr, v, context.state);
+        context.write(self.addr, v);
+    }
+}
+
+impl <'a, T: CpuMemory> AddrModeIndexed<'a, T> for AbsoluteY {
+    fn address(&self) -> MemoryPtr {
+        self.addr
+    }
+    fn index(context: &CpuContext<'a, T>) -> u8 {
+        context.state.y
     }
 }
 
@@ -229,56 +266,78 @@ pub struct IndirectX {
 impl <'a, T: CpuMemory> AddrMode<'a, T> for IndirectX {
     type Tp = u8;
     fn new(context: &mut CpuContext<'a, T>) -> IndirectX {
-        let base = (context.memory.read(context.state.program_counter + 1, context.state) as u16 + context.state.x as u16) & 0xff;
+        let operand = context.read(context.state.program_counter + 1);
+        context.read(MemoryPtr(operand as u16));
+        let base = operand.wrapping_add(context.state.x);
+        let low = context.read(MemoryPtr(base as u16)) as u16;
+        let high = context.read(MemoryPtr(base.wrapping_add(1) as u16)) as u16;
         IndirectX { 
-            addr: MemoryPtr((context.memory.read(MemoryPtr((base+1) & 0xff), context.state) as u16) << 8 | context.memory.read(MemoryPtr(base), context.state) as u16) 
+            addr: MemoryPtr(high << 8 | low)
         }
     }
     fn get(&self, context: &mut CpuContext<'a, T>) -> u8 {
-        context.memory.read(self.addr, context.state)
+        context.read(self.addr)
     }
     fn bytes_read() -> u16 {
         1
     }
-    fn cycles() -> u64 {
-        4
-    }
 }
 
 
 impl <'a, T: CpuMemory> AddrModeWrite<'a, T> for IndirectX {
     fn set(&self, context: &mut CpuContext<'a, T>, v: u8) {
-        context.memory.write(self.addr, v, context.state);
+        context.write(self.addr, v);
     }
 }
 
 pub struct IndirectY {
-    addr: MemoryPtr
+    addr: MemoryPtr,
+    unfixed: MemoryPtr,
 }
 
 impl <'a, T: CpuMemory> AddrMode<'a, T> for IndirectY {
     type Tp = u8;
     fn new(context: &mut CpuContext<'a, T>) -> IndirectY {
-        let base = context.memory.read(context.state.program_counter + 1, context.state) as u16;
+        let base = context.read(context.state.program_counter + 1);
+        let low = context.read(MemoryPtr(base as u16)) as u16;
+        let high = context.read(MemoryPtr(base.wrapping_add(1) as u16)) as u16;
+        let addr = (high << 8 | low).wrapping_add(context.state.y as u16);
         IndirectY { 
-            addr: MemoryPtr((context.memory.read(MemoryPtr((base+1) & 0xff), context.state) as u16) << 8 | context.memory.read(MemoryPtr(base), context.state) as u16) + context.state.y.into() 
+            addr: MemoryPtr(addr),
+            unfixed: MemoryPtr((high << 8) | (addr & 0xff)),
         }
     }
     fn get(&self, context: &mut CpuContext<'a, T>) -> u8 {
-        context.memory.read(self.addr, context.state)
+        context.read(self.addr)
     }
     fn bytes_read() -> u16 {
         1
     }
-    fn cycles() -> u64 {
-        3
+    fn fix_address(&self, context: &mut CpuContext<'a, T>, access: Access) {
+        fix_indexed_address(context, self.addr, self.unfixed, access);
     }
 }
 
 
 impl <'a, T: CpuMemory> AddrModeWrite<'a, T> for IndirectY {
     fn set(&self, context: &mut CpuContext<'a, T>, v: u8) {
-        context.memory.write(self.addr, v, context.state);
+        context.write(self.addr, v);
+    }
+}
+
+impl <'a, T: CpuMemory> AddrModeIndexed<'a, T> for IndirectY {
+    fn address(&self) -> MemoryPtr {
+        self.addr
+    }
+    fn index(context: &CpuContext<'a, T>) -> u8 {
+        context.state.y
+    }
+}
+
+//the address with the old high byte is read while the carry is added, reads skip it when there is no carry
+fn fix_indexed_address<T: CpuMemory>(context: &mut CpuContext<T>, addr: MemoryPtr, unfixed: MemoryPtr, access: Access) {
+    if access != Access::Read || addr != unfixed {
+        context.read(unfixed);
     }
 }
 
@@ -286,7 +345,9 @@ pub struct Accumulator;
 
 impl <'a, T: CpuMemory> AddrMode<'a, T> for Accumulator {
     type Tp = u8;
-    fn new(_: &mut CpuContext<'a, T>) -> Accumulator {
+    fn new(context: &mut CpuContext<'a, T>) -> Accumulator {
+        //one byte instructions still read the byte after the opcode
+        context.read(context.state.program_counter + 1);
         Accumulator
     }
     fn get(&self, context: &mut CpuContext<'a, T>) -> u8 {
@@ -295,9 +356,6 @@ impl <'a, T: CpuMemory> AddrMode<'a, T> for Accumulator {
     fn bytes_read() -> u16 {
         0
     }
-    fn cycles() -> u64 {
-        0
-    }
 }
 
 
@@ -305,6 +363,9 @@ impl <'a, T: CpuMemory> AddrModeWrite<'a, T> for Accumulator {
     fn set(&self, context: &mut CpuContext<'a, T>, v: u8) {
         context.state.accumulator = v
     }
+    fn modify(&self, context: &mut CpuContext<'a, T>, _: u8, new: u8) {
+        self.set(context, new);
+    }
 }
 
 
@@ -312,7 +373,8 @@ pub struct Implied;
 
 impl <'a, T: CpuMemory> AddrMode<'a, T> for Implied {
     type Tp = u8;
-    fn new(_: &mut CpuContext<'a, T>) -> Implied {
+    fn new(context: &mut CpuContext<'a, T>) -> Implied {
+        context.read(context.state.program_counter + 1);
         Implied
     }
     fn get(&self, _: &mut CpuContext<'a, T>) -> u8 {
@@ -321,9 +383,6 @@ impl <'a, T: CpuMemory> AddrMode<'a, T> for Implied {
     fn bytes_read() -> u16 {
         0
     }
-    fn cycles() -> u64 {
-        0
-    }
 }
 
 
@@ -335,8 +394,8 @@ pub struct ImmediateU16 {
 impl <'a, T: CpuMemory> AddrMode<'a, T> for ImmediateU16 {
     type Tp = u16;
     fn new(context: &mut CpuContext<'a, T>) -> ImmediateU16 {
-        let low = context.memory.read(context.state.program_counter + 1, context.state) as u16;
-        let high = context.memory.read(context.state.program_counter + 2, context.state) as u16;
+        let low = context.read(context.state.program_counter + 1) as u16;
+        let high = context.read(context.state.program_counter + 2) as u16;
         ImmediateU16 { 
             operand: (high << 8 | low)
         }
@@ -347,9 +406,6 @@ impl <'a, T: CpuMemory> AddrMode<'a, T> for ImmediateU16 {
     fn bytes_read() -> u16 {
         2
     }
-    fn cycles() -> u64 {
-        2
-    }
 }
 
 pub struct AbsoluteU16 {
@@ -359,22 +415,19 @@ pub struct AbsoluteU16 {
 impl <'a, T: CpuMemory> AddrMode<'a, T> for AbsoluteU16 {
     type Tp = u16;
     fn new(context: &mut CpuContext<'a, T>) -> AbsoluteU16 {
-        let low = context.memory.read(context.state.program_counter + 1, context.state) as u16;
-        let high = context.memory.read(context.state.program_counter + 2, context.state) as u16;
+        let low = context.read(context.state.program_counter + 1) as u16;
+        let high = context.read(context.state.program_counter + 2) as u16;
         AbsoluteU16 { 
             addr: MemoryPtr(high << 8 | low)
         }
     }
     fn get(&self, context: &mut CpuContext<'a, T>) -> u16 {
-        let low = context.memory.read(self.addr, context.state) as u16;
-        let high = context.memory.read(MemoryPtr((self.addr.0 & 0xff00) | (self.addr.0.wrapping_add(1) &0xff)), context.state )as u16;
+        let low = context.read(self.addr) as u16;
+        let high = context.read(MemoryPtr((self.addr.0 & 0xff00) | (self.addr.0.wrapping_add(1) &0xff))) as u16;
 
         high << 8 | low
     }
     fn bytes_read() -> u16 {
         2
     }
-    fn cycles() -> u64 {
-        2
-    }
 }
@@ -3,27 +3,34 @@ use std::num::Wrapping;
 
 use crate::memory_controller::MemoryPtr;
 
+use super::addressing_modes::Access;
 use super::addressing_modes::AddrMode;
+use super::addressing_modes::AddrModeIndexed;
 use super::addressing_modes::AddrModeWrite;
 use super::BitField;
 use super::CpuContext;
 use super::CpuMemory;
 use super::Flags;
+use super::IRQ_VECTOR;
 
 pub(super) trait Operation<'a, K: AddrMode<'a, T>, T: CpuMemory> {
+    //the cycles are counted by the bus accesses of the addressing mode and the operation
     fn exec(state: &mut CpuContext<'a, T>) {
-        match Self::operation(&K::new(state), state) {
+        let input = K::new(state);
+        input.fix_address(state, Self::access());
+        match Self::operation(&input, state) {
             Some(x) => {
                 state.state.program_counter = x;
             }
             None => state.state.program_counter += 1 + K::bytes_read(),
         }
-        state.state.cycle_count += Self::get_cycles() + K::cycles();
     }
 
     fn operation(input: &K, state: &mut CpuContext<'a, T>) -> Option<MemoryPtr>;
 
-    fn get_cycles() -> u64;
+    fn access() -> Access {
+        Access::Read
+    }
 }
 
 pub(super) struct AdcOp<K> {
@@ -33,31 +40,31 @@ pub(super) struct AdcOp<K> {
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for AdcOp<K> {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
         let src = input.get(cpu);
-        let tmp = u16::from(src)
-            + u16::from(cpu.state.accumulator)
-            + (if cpu.state.flags.get(Flags::Carry) {
-                1
-            } else {
-                0
-            });
-
-        cpu.state.flags.set(Flags::Zero, (tmp & 0xff) == 0);
-        cpu.state.flags.set(Flags::Sign, (tmp & (1 << 7)) != 0);
-        cpu.state.flags.set(
-            Flags::Overflow,
-            (((cpu.state.accumulator ^ src) & 0x80) == 0)
-                && ((u16::from(cpu.state.accumulator) ^ tmp) & 0x80) != 0,
-        );
-        cpu.state.flags.set(Flags::Carry, tmp > 0xff);
-
-        cpu.state.accumulator = tmp as u8;
+        adc_implementation(src, cpu);
 
         None
     }
+}
 
-    fn get_cycles() -> u64 {
-        2
-    }
+fn adc_implementation<T: CpuMemory>(src: u8, cpu: &mut CpuContext<T>) {
+    let tmp = u16::from(src)
+        + u16::from(cpu.state.accumulator)
+        + (if cpu.state.flags.get(Flags::Carry) {
+            1
+        } else {
+            0
+        });
+
+    cpu.state.flags.set(Flags::Zero, (tmp & 0xff) == 0);
+    cpu.state.flags.set(Flags::Sign, (tmp & (1 << 7)) != 0);
+    cpu.state.flags.set(
+        Flags::Overflow,
+        (((cpu.state.accumulator ^ src) & 0x80) == 0)
+            && ((u16::from(cpu.state.accumulator) ^ tmp) & 0x80) != 0,
+    );
+    cpu.state.flags.set(Flags::Carry, tmp > 0xff);
+
+    cpu.state.accumulator = tmp as u8;
 }
 
 pub(super) struct AndOp<K> {
@@ -73,10 +80,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for AndO
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct AslOp<K> {
@@ -87,20 +90,21 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Opera
     for AslOp<K>
 {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
-        let mut src = input.get(cpu);
+        let old = input.get(cpu);
+        let mut src = old;
         cpu.state
             .flags
             .set(Flags::Carry, if src & 0x80 != 0 { true } else { false });
         src <<= 1;
         cpu.state.flags.set(Flags::Zero, src == 0);
         cpu.state.flags.set(Flags::Sign, (src & (1 << 7)) != 0);
-        input.set(cpu, src);
+        input.modify(cpu, old, src);
 
         None
     }
 
-    fn get_cycles() -> u64 {
-        2
+    fn access() -> Access {
+        Access::ReadModifyWrite
     }
 }
 
@@ -110,15 +114,13 @@ pub(super) struct BccOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for BccOp<K> {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let offset = input.get(cpu);
         if !cpu.state.flags.get(Flags::Carry) {
-            Some(branch_implementation(input, cpu))
+            Some(branch_implementation(offset, cpu))
         } else {
             None
         }
     }
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct BcsOp<K> {
@@ -127,15 +129,13 @@ pub(super) struct BcsOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for BcsOp<K> {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let offset = input.get(cpu);
         if cpu.state.flags.get(Flags::Carry) {
-            Some(branch_implementation(input, cpu))
+            Some(branch_implementation(offset, cpu))
         } else {
             None
         }
     }
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct BeqOp<K> {
@@ -144,15 +144,13 @@ pub(super) struct BeqOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for BeqOp<K> {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let offset = input.get(cpu);
         if cpu.state.flags.get(Flags::Zero) {
-            Some(branch_implementation(input, cpu))
+            Some(branch_implementation(offset, cpu))
         } else {
             None
         }
     }
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct BitOp<K> {
@@ -171,10 +169,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for BitO
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct BmiOp<K> {
@@ -183,15 +177,13 @@ pub(super) struct BmiOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for BmiOp<K> {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let offset = input.get(cpu);
         if cpu.state.flags.get(Flags::Sign) {
-            Some(branch_implementation(input, cpu))
+            Some(branch_implementation(offset, cpu))
         } else {
             None
         }
     }
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct BneOp<K> {
@@ -200,15 +192,13 @@ pub(super) struct BneOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for BneOp<K> {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let offset = input.get(cpu);
         if !cpu.state.flags.get(Flags::Zero) {
-            Some(branch_implementation(input, cpu))
+            Some(branch_implementation(offset, cpu))
         } else {
             None
         }
     }
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct BplOp<K> {
@@ -217,14 +207,25 @@ pub(super) struct BplOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for BplOp<K> {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let offset = input.get(cpu);
         if !cpu.state.flags.get(Flags::Sign) {
-            Some(branch_implementation(input, cpu))
+            Some(branch_implementation(offset, cpu))
         } else {
             None
         }
     }
-    fn get_cycles() -> u64 {
-        2
+}
+
+pub(super) struct BrkOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T>> Operation<'a, K, T> for BrkOp<K> {
+    fn operation(_: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        //brk is followed by a padding byte that the return address skips
+        let return_address = cpu.state.program_counter + 2;
+        cpu.interrupt(return_address, IRQ_VECTOR, true);
+        Some(cpu.state.program_counter)
     }
 }
 
@@ -234,15 +235,13 @@ pub(super) struct BvcOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for BvcOp<K> {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let offset = input.get(cpu);
         if !cpu.state.flags.get(Flags::Overflow) {
-            Some(branch_implementation(input, cpu))
+            Some(branch_implementation(offset, cpu))
         } else {
             None
         }
     }
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct BvsOp<K> {
@@ -251,15 +250,13 @@ pub(super) struct BvsOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for BvsOp<K> {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let offset = input.get(cpu);
         if cpu.state.flags.get(Flags::Overflow) {
-            Some(branch_implementation(input, cpu))
+            Some(branch_implementation(offset, cpu))
         } else {
             None
         }
     }
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct ClcOp<K> {
@@ -272,10 +269,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T>> Operation<'a, K, T> for ClcOp<K> {
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct CldOp<K> {
@@ -288,10 +281,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T>> Operation<'a, K, T> for CldOp<K> {
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct CliOp<K> {
@@ -303,10 +292,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T>> Operation<'a, K, T> for CliOp<K> {
         cpu.state.flags.set(Flags::InterruptDisable, false);
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct ClvOp<K> {
@@ -319,10 +304,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T>> Operation<'a, K, T> for ClvOp<K> {
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct CmpOp<K> {
@@ -342,10 +323,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for CmpO
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct CpxOp<K> {
@@ -365,10 +342,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for CpxO
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct CpyOp<K> {
@@ -388,10 +361,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for CpyO
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct DecOp<K> {
@@ -402,16 +371,17 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Opera
     for DecOp<K>
 {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
-        let result = input.get(cpu).wrapping_sub(1);
+        let old = input.get(cpu);
+        let result = old.wrapping_sub(1);
         cpu.state.flags.set(Flags::Sign, (result & 0x80) != 0);
         cpu.state.flags.set(Flags::Zero, result == 0);
 
-        input.set(cpu, result);
+        input.modify(cpu, old, result);
         None
     }
 
-    fn get_cycles() -> u64 {
-        4
+    fn access() -> Access {
+        Access::ReadModifyWrite
     }
 }
 
@@ -428,10 +398,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T>> Operation<'a, K, T> for DexOp<K> {
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct DeyOp<K> {
@@ -447,10 +413,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T>> Operation<'a, K, T> for DeyOp<K> {
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct EorOp<K> {
@@ -467,10 +429,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for EorO
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct IncOp<K> {
@@ -481,16 +439,17 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Opera
     for IncOp<K>
 {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
-        let result = input.get(cpu).wrapping_add(1);
+        let old = input.get(cpu);
+        let result = old.wrapping_add(1);
         cpu.state.flags.set(Flags::Sign, (result & 0x80) != 0);
         cpu.state.flags.set(Flags::Zero, result == 0);
 
-        input.set(cpu, result);
+        input.modify(cpu, old, result);
         None
     }
 
-    fn get_cycles() -> u64 {
-        4
+    fn access() -> Access {
+        Access::ReadModifyWrite
     }
 }
 
@@ -507,10 +466,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T>> Operation<'a, K, T> for InxOp<K> {
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct InyOp<K> {
@@ -525,10 +480,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T>> Operation<'a, K, T> for InyOp<K> {
         cpu.state.flags.set(Flags::Zero, cpu.state.y == 0);
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct JmpOp<K> {
@@ -539,9 +490,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u16>> Operation<'a, K, T> for Jmp
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
         Some(MemoryPtr(input.get(cpu)))
     }
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct JsrOp<K> {
@@ -551,15 +499,13 @@ pub(super) struct JsrOp<K> {
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u16>> Operation<'a, K, T> for JsrOp<K> {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
         let return_point = (cpu.state.program_counter + K::bytes_read()).0;
+        cpu.stack_dummy_read();
 
         cpu.stack_push((return_point >> 8) as u8);
         cpu.stack_push((return_point & 0xff) as u8);
 
         Some(MemoryPtr(input.get(cpu)))
     }
-    fn get_cycles() -> u64 {
-        4
-    }
 }
 
 pub(super) struct LdaOp<K> {
@@ -575,10 +521,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for LdaO
         cpu.state.accumulator = value;
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct LdxOp<K> {
@@ -594,10 +536,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for LdxO
         cpu.state.x = value;
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct LdyOp<K> {
@@ -613,10 +551,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for LdyO
         cpu.state.y = value;
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct LsrOp<K> {
@@ -627,20 +561,21 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Opera
     for LsrOp<K>
 {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
-        let mut src = input.get(cpu);
+        let old = input.get(cpu);
+        let mut src = old;
         cpu.state
             .flags
             .set(Flags::Carry, if src & 0x01 != 0 { true } else { false });
         src >>= 1;
         cpu.state.flags.set(Flags::Zero, src == 0);
         cpu.state.flags.set(Flags::Sign, false);
-        input.set(cpu, src);
+        input.modify(cpu, old, src);
 
         None
     }
 
-    fn get_cycles() -> u64 {
-        2
+    fn access() -> Access {
+        Access::ReadModifyWrite
     }
 }
 
@@ -652,10 +587,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for NopO
     fn operation(_: &K, _: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct OraOp<K> {
@@ -672,10 +603,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for OraO
 
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct PhaOp<K> {
@@ -687,10 +614,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for PhaO
         cpu.stack_push(cpu.state.accumulator);
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct PhpOp<K> {
@@ -704,10 +627,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for PhpO
         cpu.stack_push(flags);
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct PlaOp<K> {
@@ -716,6 +635,7 @@ pub(super) struct PlaOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for PlaOp<K> {
     fn operation(_: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        cpu.stack_dummy_read();
         let value = cpu.stack_pop();
 
         cpu.state.flags.set(Flags::Sign, (value & (1 << 7)) != 0);
@@ -724,10 +644,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for PlaO
         cpu.state.accumulator = value;
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct PlpOp<K> {
@@ -736,6 +652,7 @@ pub(super) struct PlpOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for PlpOp<K> {
     fn operation(_: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        cpu.stack_dummy_read();
         let mut value = cpu.stack_pop();
 
         value.set(Flags::Unused, true);
@@ -744,10 +661,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for PlpO
         cpu.state.flags = value;
         None
     }
-
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct RolOp<K> {
@@ -758,7 +671,8 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Opera
     for RolOp<K>
 {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
-        let mut src = input.get(cpu) as i16;
+        let old = input.get(cpu);
+        let mut src = old as i16;
         src <<= 1;
 
         if cpu.state.flags.get(Flags::Carry) {
@@ -771,12 +685,12 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Opera
         cpu.state.flags.set(Flags::Zero, src == 0);
         cpu.state.flags.set(Flags::Sign, (src & (1 << 7)) != 0);
 
-        input.set(cpu, src as u8);
+        input.modify(cpu, old, src as u8);
         None
     }
 
-    fn get_cycles() -> u64 {
-        2
+    fn access() -> Access {
+        Access::ReadModifyWrite
     }
 }
 
@@ -788,7 +702,8 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Opera
     for RorOp<K>
 {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
-        let mut src = input.get(cpu) as u16;
+        let old = input.get(cpu);
+        let mut src = old as u16;
         if cpu.state.flags.get(Flags::Carry) {
             src |= 0x100;
         }
@@ -798,12 +713,12 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Opera
         cpu.state.flags.set(Flags::Zero, src == 0);
         cpu.state.flags.set(Flags::Sign, (src & (1 << 7)) != 0);
 
-        input.set(cpu, src as u8);
+        input.modify(cpu, old, src as u8);
         None
     }
 
-    fn get_cycles() -> u64 {
-        2
+    fn access() -> Access {
+        Access::ReadModifyWrite
     }
 }
 
@@ -813,17 +728,16 @@ pub(super) struct RtiOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for RtiOp<K> {
     fn operation(_: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        cpu.stack_dummy_read();
         let mut flags = cpu.stack_pop();
         flags.set(Flags::Unused, true);
+        flags.set(Flags::Break, false);
         cpu.state.flags = flags;
         
         let addr = (cpu.stack_pop() as u16) | ((cpu.stack_pop() as u16) << 8);
 
         Some(MemoryPtr(addr))
     }
-    fn get_cycles() -> u64 {
-        6
-    }
 }
 
 pub(super) struct RtsOp<K> {
@@ -832,13 +746,14 @@ pub(super) struct RtsOp<K> {
 
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for RtsOp<K> {
     fn operation(_: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
-        let addr = ((cpu.stack_pop() as u16) | ((cpu.stack_pop() as u16) << 8)).wrapping_add(1);
+        cpu.stack_dummy_read();
+        let addr = (cpu.stack_pop() as u16) | ((cpu.stack_pop() as u16) << 8);
+        //the pulled address points at the last byte of the jsr, it is read while it gets incremented
+        cpu.read(MemoryPtr(addr));
+        let addr = addr.wrapping_add(1);
 
         Some(MemoryPtr(addr))
     }
-    fn get_cycles() -> u64 {
-        6
-    }
 }
 
 pub(super) struct SbcOp<K> {
@@ -848,32 +763,32 @@ pub(super) struct SbcOp<K> {
 impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for SbcOp<K> {
     fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
         let src = input.get(cpu);
-        let mut tmp = u16::wrapping_add(
-            u16::from(cpu.state.accumulator),
-            u16::from(src).wrapping_neg(),
-        );
-
-        if !cpu.state.flags.get(Flags::Carry) {
-            tmp = tmp.wrapping_add((1 as u16).wrapping_neg());
-        }
-
-        cpu.state.flags.set(Flags::Zero, tmp == 0);
-        cpu.state.flags.set(Flags::Sign, (tmp & (1 << 7)) != 0);
-        cpu.state.flags.set(
-            Flags::Overflow,
-            (((cpu.state.accumulator ^ src) & 0x80) != 0)
-                && ((u16::from(cpu.state.accumulator) ^ tmp) & 0x80) != 0,
-        );
-        cpu.state.flags.set(Flags::Carry, tmp < 0x100);
-
-        cpu.state.accumulator = (tmp & 0xff) as u8;
+        sbc_implementation(src, cpu);
 
         None
     }
+}
 
-    fn get_cycles() -> u64 {
-        2
+fn sbc_implementation<T: CpuMemory>(src: u8, cpu: &mut CpuContext<T>) {
+    let mut tmp = u16::wrapping_add(
+        u16::from(cpu.state.accumulator),
+        u16::from(src).wrapping_neg(),
+    );
+
+    if !cpu.state.flags.get(Flags::Carry) {
+        tmp = tmp.wrapping_add((1 as u16).wrapping_neg());
     }
+
+    cpu.state.flags.set(Flags::Zero, tmp == 0);
+    cpu.state.flags.set(Flags::Sign, (tmp & (1 << 7)) != 0);
+    cpu.state.flags.set(
+        Flags::Overflow,
+        (((cpu.state.accumulator ^ src) & 0x80) != 0)
+            && ((u16::from(cpu.state.accumulator) ^ tmp) & 0x80) != 0,
+    );
+    cpu.state.flags.set(Flags::Carry, tmp < 0x100);
+
+    cpu.state.accumulator = (tmp & 0xff) as u8;
 }
 
 pub(super) struct SecOp<K> {
@@ -885,9 +800,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for SecO
         cpu.state.flags.set(Flags::Carry, true);
         None
     }
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct SedOp<K> {
@@ -899,9 +811,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for SedO
         cpu.state.flags.set(Flags::Decimal, true);
         None
     }
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct SeiOp<K> {
@@ -913,9 +822,6 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for SeiO
         cpu.state.flags.set(Flags::InterruptDisable, true);
         None
     }
-    fn get_cycles() -> u64 {
-        2
-    }
 }
 
 pub(super) struct StaOp<K> {
@@ -930,8 +836,8 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Opera
         None
     }
 
-    fn get_cycles() -> u64 {
-        2
+    fn access() -> Access {
+        Access::Write
     }
 }
 
@@ -947,8 +853,8 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Opera
         None
     }
 
-    fn get_cycles() -> u64 {
-        2
+    fn access() -> Access {
+        Access::Write
     }
 }
 
@@ -964,8 +870,8 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Opera
         None
     }
 
-    fn get_cycles() -> u64 {
-        2
+    fn access() -> Access {
+        Access::Write
     }
 }
 
@@ -983,10 +889,6 @@ macro_rules! move_register_op {
                 cpu.state.$destination = tmp;
                 None
             }
-
-            fn get_cycles() -> u64 {
-                2
-            }
         }
     };
 }
@@ -1006,27 +908,407 @@ impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for TxsO
         cpu.state.stack_pointer = cpu.state.x;
         None
     }
+}
+
+//unofficial opcodes, most of them do two official operations on the same operand
+
+pub(super) struct LaxOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for LaxOp<K> {
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let src = input.get(cpu);
+        cpu.state.flags.set(Flags::Zero, src == 0);
+        cpu.state.flags.set(Flags::Sign, (src & (1 << 7)) != 0);
+        cpu.state.accumulator = src;
+        cpu.state.x = src;
 
-    fn get_cycles() -> u64 {
-        2
+        None
     }
 }
 
-///////////////////////////////////////////////////
+pub(super) struct SaxOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Operation<'a, K, T>
+    for SaxOp<K>
+{
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let value = cpu.state.accumulator & cpu.state.x;
+        input.set(cpu, value);
+        None
+    }
+
+    fn access() -> Access {
+        Access::Write
+    }
+}
+
+pub(super) struct DcpOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Operation<'a, K, T>
+    for DcpOp<K>
+{
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let old = input.get(cpu);
+        let result = old.wrapping_sub(1);
+        input.modify(cpu, old, result);
+
+        let aux = cpu.state.accumulator.wrapping_sub(result);
+        cpu.state.flags.set(Flags::Carry, cpu.state.accumulator >= result);
+        cpu.state.flags.set(Flags::Sign, aux & 0x80 != 0);
+        cpu.state.flags.set(Flags::Zero, aux == 0);
+
+        None
+    }
+
+    fn access() -> Access {
+        Access::ReadModifyWrite
+    }
+}
+
+pub(super) struct IscOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Operation<'a, K, T>
+    for IscOp<K>
+{
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let old = input.get(cpu);
+        let result = old.wrapping_add(1);
+        input.modify(cpu, old, result);
+        sbc_implementation(result, cpu);
+
+        None
+    }
+
+    fn access() -> Access {
+        Access::ReadModifyWrite
+    }
+}
+
+pub(super) struct SloOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Operation<'a, K, T>
+    for SloOp<K>
+{
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let src = input.get(cpu);
+        cpu.state.flags.set(Flags::Carry, src & 0x80 != 0);
+        let shifted = src << 1;
+        input.modify(cpu, src, shifted);
+
+        let result = cpu.state.accumulator | shifted;
+        cpu.state.flags.set(Flags::Zero, result == 0);
+        cpu.state.flags.set(Flags::Sign, (result & (1 << 7)) != 0);
+        cpu.state.accumulator = result;
+
+        None
+    }
+
+    fn access() -> Access {
+        Access::ReadModifyWrite
+    }
+}
+
+pub(super) struct RlaOp<K> {
+    p: PhantomData<K>,
+}
 
-fn branch_implementation<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>>(
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Operation<'a, K, T>
+    for RlaOp<K>
+{
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let src = input.get(cpu);
+        let carry = cpu.state.flags.get(Flags::Carry) as u8;
+        cpu.state.flags.set(Flags::Carry, src & 0x80 != 0);
+        let rotated = (src << 1) | carry;
+        input.modify(cpu, src, rotated);
+
+        let result = cpu.state.accumulator & rotated;
+        cpu.state.flags.set(Flags::Zero, result == 0);
+        cpu.state.flags.set(Flags::Sign, (result & (1 << 7)) != 0);
+        cpu.state.accumulator = result;
+
+        None
+    }
+
+    fn access() -> Access {
+        Access::ReadModifyWrite
+    }
+}
+
+pub(super) struct SreOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Operation<'a, K, T>
+    for SreOp<K>
+{
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let src = input.get(cpu);
+        cpu.state.flags.set(Flags::Carry, src & 0x01 != 0);
+        let shifted = src >> 1;
+        input.modify(cpu, src, shifted);
+
+        let result = cpu.state.accumulator ^ shifted;
+        cpu.state.flags.set(Flags::Zero, result == 0);
+        cpu.state.flags.set(Flags::Sign, (result & (1 << 7)) != 0);
+        cpu.state.accumulator = result;
+
+        None
+    }
+
+    fn access() -> Access {
+        Access::ReadModifyWrite
+    }
+}
+
+pub(super) struct RraOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeWrite<'a, T>> Operation<'a, K, T>
+    for RraOp<K>
+{
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let src = input.get(cpu);
+        let carry = cpu.state.flags.get(Flags::Carry) as u8;
+        cpu.state.flags.set(Flags::Carry, src & 0x01 != 0);
+        let rotated = (src >> 1) | (carry << 7);
+        input.modify(cpu, src, rotated);
+
+        //the adc uses the carry shifted out by the rotation
+        adc_implementation(rotated, cpu);
+
+        None
+    }
+
+    fn access() -> Access {
+        Access::ReadModifyWrite
+    }
+}
+
+pub(super) struct AncOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for AncOp<K> {
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let result = input.get(cpu) & cpu.state.accumulator;
+        cpu.state.flags.set(Flags::Zero, result == 0);
+        cpu.state.flags.set(Flags::Sign, (result & (1 << 7)) != 0);
+        cpu.state.flags.set(Flags::Carry, (result & (1 << 7)) != 0);
+        cpu.state.accumulator = result;
+
+        None
+    }
+}
+
+pub(super) struct AlrOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for AlrOp<K> {
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let src = input.get(cpu) & cpu.state.accumulator;
+        cpu.state.flags.set(Flags::Carry, src & 0x01 != 0);
+        let result = src >> 1;
+        cpu.state.flags.set(Flags::Zero, result == 0);
+        cpu.state.flags.set(Flags::Sign, false);
+        cpu.state.accumulator = result;
+
+        None
+    }
+}
+
+pub(super) struct ArrOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for ArrOp<K> {
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let src = input.get(cpu) & cpu.state.accumulator;
+        let carry = cpu.state.flags.get(Flags::Carry) as u8;
+        let result = (src >> 1) | (carry << 7);
+
+        //carry and overflow come from bits 6 and 5 of the result, as if it went through the adder
+        cpu.state.flags.set(Flags::Zero, result == 0);
+        cpu.state.flags.set(Flags::Sign, (result & (1 << 7)) != 0);
+        cpu.state.flags.set(Flags::Carry, (result & (1 << 6)) != 0);
+        cpu.state.flags.set(Flags::Overflow, ((result >> 6) ^ (result >> 5)) & 0x01 != 0);
+        cpu.state.accumulator = result;
+
+        None
+    }
+}
+
+pub(super) struct AxsOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for AxsOp<K> {
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let src = input.get(cpu);
+        let value = cpu.state.accumulator & cpu.state.x;
+        let result = value.wrapping_sub(src);
+
+        cpu.state.flags.set(Flags::Carry, value >= src);
+        cpu.state.flags.set(Flags::Zero, result == 0);
+        cpu.state.flags.set(Flags::Sign, (result & (1 << 7)) != 0);
+        cpu.state.x = result;
+
+        None
+    }
+}
+
+pub(super) struct LasOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for LasOp<K> {
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let result = input.get(cpu) & cpu.state.stack_pointer;
+        cpu.state.flags.set(Flags::Zero, result == 0);
+        cpu.state.flags.set(Flags::Sign, (result & (1 << 7)) != 0);
+        cpu.state.accumulator = result;
+        cpu.state.x = result;
+        cpu.state.stack_pointer = result;
+
+        None
+    }
+}
+
+//xaa and lxa depend on analog effects that differ between chips, these are the values most consoles show
+const XAA_MAGIC: u8 = 0xee;
+const LXA_MAGIC: u8 = 0xff;
+
+pub(super) struct XaaOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for XaaOp<K> {
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let result = (cpu.state.accumulator | XAA_MAGIC) & cpu.state.x & input.get(cpu);
+        cpu.state.flags.set(Flags::Zero, result == 0);
+        cpu.state.flags.set(Flags::Sign, (result & (1 << 7)) != 0);
+        cpu.state.accumulator = result;
+
+        None
+    }
+}
+
+pub(super) struct LxaOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for LxaOp<K> {
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        let result = (cpu.state.accumulator | LXA_MAGIC) & input.get(cpu);
+        cpu.state.flags.set(Flags::Zero, result == 0);
+        cpu.state.flags.set(Flags::Sign, (result & (1 << 7)) != 0);
+        cpu.state.accumulator = result;
+        cpu.state.x = result;
+
+        None
+    }
+}
+
+macro_rules! unstable_store_op {
+    ($name:ident, |$state:ident| $value:expr) => {
+        pub(super) struct $name<K> {
+            p: PhantomData<K>,
+        }
+
+        impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8> + AddrModeIndexed<'a, T>> Operation<'a, K, T>
+            for $name<K>
+        {
+            fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+                let $state = &mut *cpu.state;
+                let value = $value;
+                unstable_store_implementation(input, cpu, value);
+                None
+            }
+
+            fn access() -> Access {
+                Access::Write
+            }
+        }
+    };
+}
+
+unstable_store_op!(ShaOp, |state| state.accumulator & state.x);
+unstable_store_op!(ShxOp, |state| state.x);
+unstable_store_op!(ShyOp, |state| state.y);
+unstable_store_op!(TasOp, |state| {
+    state.stack_pointer = state.accumulator & state.x;
+    state.stack_pointer
+});
+
+//the stored value is anded with the high byte of the base address plus one,
+//and when indexing crosses a page that value also replaces the high byte of the target
+fn unstable_store_implementation<'a, T: CpuMemory, K: AddrMode<'a, T> + AddrModeIndexed<'a, T>>(
     input: &K,
     cpu: &mut CpuContext<'a, T>,
-) -> MemoryPtr {
-    let destination = MemoryPtr(sum_u16_with_signed_u8(
-        cpu.state.program_counter.0 + 1 + K::bytes_read(),
-        input.get(cpu),
-    ));
+    value: u8,
+) {
+    let address = input.address().0;
+    let base = address.wrapping_sub(K::index(cpu) as u16);
+    let value = value & ((base >> 8) as u8).wrapping_add(1);
+
+    let address = if (base & 0xff00) != (address & 0xff00) {
+        ((value as u16) << 8) | (address & 0xff)
+    } else {
+        address
+    };
+
+    cpu.write(MemoryPtr(address), value);
+}
+
+//nops with an operand still read it, which matters for registers with read side effects
+pub(super) struct NopReadOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for NopReadOp<K> {
+    fn operation(input: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        input.get(cpu);
+        None
+    }
+}
+
+//kil/jam locks the cpu up until the console is reset
+pub(super) struct JamOp<K> {
+    p: PhantomData<K>,
+}
+
+impl<'a, T: CpuMemory, K: AddrMode<'a, T, Tp = u8>> Operation<'a, K, T> for JamOp<K> {
+    fn operation(_: &K, cpu: &mut CpuContext<'a, T>) -> Option<MemoryPtr> {
+        cpu.state.halted = true;
+        Some(cpu.state.program_counter)
+    }
+}
+
+///////////////////////////////////////////////////
+
+//the offset is fetched whether or not the branch is taken
+fn branch_implementation<T: CpuMemory>(offset: u8, cpu: &mut CpuContext<T>) -> MemoryPtr {
+    let next = cpu.state.program_counter.0.wrapping_add(2);
+    let destination = MemoryPtr(sum_u16_with_signed_u8(next, offset));
 
-    cpu.state.cycle_count += 1;
+    //a taken branch reads the next opcode while it adds the offset, and if that crosses
+    //into another page it also reads the address before the high byte is fixed
+    cpu.read(MemoryPtr(next));
 
-    if (cpu.state.program_counter.0 & 0xff00) != (destination.0 & 0xff00) {
-        cpu.state.cycle_count += 1;
+    if (next & 0xff00) != (destination.0 & 0xff00) {
+        cpu.read(MemoryPtr((next & 0xff00) | (destination.0 & 0xff)));
     }
 
     destination
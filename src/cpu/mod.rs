@@ -2,9 +2,8 @@ mod addressing_modes;
 mod instructions;
 use core::fmt;
 
-use log::debug;
-
 use super::memory_controller::MemoryPtr;
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
 
 pub trait CpuMemory {
     fn read(&mut self, addr: MemoryPtr, cpu: &mut Cpu) -> u8;
@@ -21,6 +20,14 @@ pub trait CpuMemoryWrite {
 
 type CpuFlags = u8;
 
+const NMI_VECTOR: u16 = 0xfffa;
+const IRQ_VECTOR: u16 = 0xfffe;
+
+//devices that can pull the irq line low, the cpu sees the line as long as any of them holds it
+pub const IRQ_FRAME_COUNTER: u8 = 1 << 0;
+pub const IRQ_DMC: u8 = 1 << 1;
+pub const IRQ_MAPPER: u8 = 1 << 2;
+
 #[derive(Clone, Copy)]
 enum Flags {
     Carry = 1 << 0,
@@ -59,9 +66,17 @@ pub struct Cpu {
     pub stack_pointer: u8,
     pub program_counter: MemoryPtr,
 
-    pub irq_requested: bool,
+    //one bit per irq source holding the line
+    pub irq_sources: u8,
+    //latched nmi edge, taken before the next instruction
+    pub nmi_pending: bool,
+    //the i flag as seen by the last irq poll
+    pub irq_poll_disabled: bool,
     pub cycle_count: u64,
-    pub last_instruction: u8
+    pub last_instruction: u8,
+
+    //set by the kil/jam opcodes, only a reset gets the cpu running again
+    pub halted: bool,
 }
 
 impl Cpu {
@@ -75,11 +90,26 @@ impl Cpu {
             program_counter: MemoryPtr(0),
             cycle_count: 7,
             last_instruction: 0,
-            irq_requested: false,
+            irq_sources: 0,
+            nmi_pending: false,
+            irq_poll_disabled: true,
+            halted: false,
         }
     }
 
-    pub fn context<T: CpuMemory>(&mut self, memory: T) -> CpuContext<T> {
+    pub fn set_irq(&mut self, source: u8, active: bool) {
+        if active {
+            self.irq_sources |= source;
+        } else {
+            self.irq_sources &= !source;
+        }
+    }
+
+    pub fn irq_requested(&self) -> bool {
+        self.irq_sources != 0
+    }
+
+    pub fn context<T: CpuMemory>(&mut self, memory: T) -> CpuContext<'_, T> {
         CpuContext {
             state: self,
             memory,
@@ -87,7 +117,7 @@ impl Cpu {
     }
 
     #[allow(dead_code)]
-    pub fn context_borrowed<'a, T: CpuMemory>(&'a mut self, memory: &'a mut T) -> CpuContext<BorrowedMemory<T>>  {
+    pub fn context_borrowed<'a, T: CpuMemory>(&'a mut self, memory: &'a mut T) -> CpuContext<'a, BorrowedMemory<'a, T>>  {
         CpuContext { 
             state: self, 
             memory: BorrowedMemory { mem: memory },
@@ -166,14 +196,36 @@ impl<'a, T: CpuMemory> CpuContext<'a, T> {
     pub fn reset(&mut self) {
         let entry_point = ((self.memory.read(MemoryPtr(0xfffd), self.state) as u16) << 8) | (self.memory.read(MemoryPtr(0xfffc), self.state) as u16);
         self.state.program_counter = MemoryPtr(entry_point);
+        self.state.halted = false;
+    }
+
+    //reset while running: the stack pointer moves down as if three bytes were pushed and interrupts get disabled
+    pub fn soft_reset(&mut self) {
+        self.state.stack_pointer = self.state.stack_pointer.wrapping_sub(3);
+        self.state.flags.set(Flags::InterruptDisable, true);
+        self.state.irq_poll_disabled = true;
+        self.reset();
     }
 
-    pub fn execute_next_instruction(&mut self) -> Result<(), ()> {
-        if self.state.irq_requested {
-            self.irq();
+    pub fn execute_next_instruction(&mut self) {
+        //a halted cpu doesn't fetch anything, time just keeps passing
+        if self.state.halted {
+            self.state.cycle_count += 2;
+            return;
         }
-        
-        let opcode = self.memory.read(self.state.program_counter, &mut self.state);
+
+        //interrupts are polled between instructions, an nmi is an edge and stays pending until taken.
+        //the irq line is a level and is ignored while the i flag was set at the last poll
+        if self.state.nmi_pending {
+            self.state.nmi_pending = false;
+            self.interrupt_sequence(NMI_VECTOR);
+        } else if self.state.irq_requested() && !self.state.irq_poll_disabled {
+            self.interrupt_sequence(IRQ_VECTOR);
+        }
+
+        let interrupt_disable = self.state.flags.get(Flags::InterruptDisable);
+
+        let opcode = self.read(self.state.program_counter);
 
         use addressing_modes::*;
         use instructions::*;
@@ -217,6 +269,8 @@ impl<'a, T: CpuMemory> CpuContext<'a, T> {
 
             0x10 => BplOp::<Immediate>::exec(self),
 
+            0x00 => BrkOp::<Implied>::exec(self),
+
             0x50 => BvcOp::<Immediate>::exec(self),
 
             0x70 => BvsOp::<Immediate>::exec(self),
@@ -376,50 +430,194 @@ impl<'a, T: CpuMemory> CpuContext<'a, T> {
             0x9a => TxsOp::<Accumulator>::exec(self),
             0x98 => TyaOp::<Accumulator>::exec(self),
 
-            _ => {
-                debug!(
-                    "unknown opcode {:X} at {:X}",
-                    opcode, self.state.program_counter.0
-                );
-                return Err(());
+            //unofficial opcodes
+            0xa7 => LaxOp::<ZeroPage>::exec(self),
+            0xb7 => LaxOp::<ZeroPageY>::exec(self),
+            0xaf => LaxOp::<Absolute>::exec(self),
+            0xbf => LaxOp::<AbsoluteY>::exec(self),
+            0xa3 => LaxOp::<IndirectX>::exec(self),
+            0xb3 => LaxOp::<IndirectY>::exec(self),
+
+            0x87 => SaxOp::<ZeroPage>::exec(self),
+            0x97 => SaxOp::<ZeroPageY>::exec(self),
+            0x8f => SaxOp::<Absolute>::exec(self),
+            0x83 => SaxOp::<IndirectX>::exec(self),
+
+            0xc7 => DcpOp::<ZeroPage>::exec(self),
+            0xd7 => DcpOp::<ZeroPageX>::exec(self),
+            0xcf => DcpOp::<Absolute>::exec(self),
+            0xdf => DcpOp::<AbsoluteX>::exec(self),
+            0xdb => DcpOp::<AbsoluteY>::exec(self),
+            0xc3 => DcpOp::<IndirectX>::exec(self),
+            0xd3 => DcpOp::<IndirectY>::exec(self),
+
+            0xe7 => IscOp::<ZeroPage>::exec(self),
+            0xf7 => IscOp::<ZeroPageX>::exec(self),
+            0xef => IscOp::<Absolute>::exec(self),
+            0xff => IscOp::<AbsoluteX>::exec(self),
+            0xfb => IscOp::<AbsoluteY>::exec(self),
+            0xe3 => IscOp::<IndirectX>::exec(self),
+            0xf3 => IscOp::<IndirectY>::exec(self),
+
+            0x07 => SloOp::<ZeroPage>::exec(self),
+            0x17 => SloOp::<ZeroPageX>::exec(self),
+            0x0f => SloOp::<Absolute>::exec(self),
+            0x1f => SloOp::<AbsoluteX>::exec(self),
+            0x1b => SloOp::<AbsoluteY>::exec(self),
+            0x03 => SloOp::<IndirectX>::exec(self),
+            0x13 => SloOp::<IndirectY>::exec(self),
+
+            0x27 => RlaOp::<ZeroPage>::exec(self),
+            0x37 => RlaOp::<ZeroPageX>::exec(self),
+            0x2f => RlaOp::<Absolute>::exec(self),
+            0x3f => RlaOp::<AbsoluteX>::exec(self),
+            0x3b => RlaOp::<AbsoluteY>::exec(self),
+            0x23 => RlaOp::<IndirectX>::exec(self),
+            0x33 => RlaOp::<IndirectY>::exec(self),
+
+            0x47 => SreOp::<ZeroPage>::exec(self),
+            0x57 => SreOp::<ZeroPageX>::exec(self),
+            0x4f => SreOp::<Absolute>::exec(self),
+            0x5f => SreOp::<AbsoluteX>::exec(self),
+            0x5b => SreOp::<AbsoluteY>::exec(self),
+            0x43 => SreOp::<IndirectX>::exec(self),
+            0x53 => SreOp::<IndirectY>::exec(self),
+
+            0x67 => RraOp::<ZeroPage>::exec(self),
+            0x77 => RraOp::<ZeroPageX>::exec(self),
+            0x6f => RraOp::<Absolute>::exec(self),
+            0x7f => RraOp::<AbsoluteX>::exec(self),
+            0x7b => RraOp::<AbsoluteY>::exec(self),
+            0x63 => RraOp::<IndirectX>::exec(self),
+            0x73 => RraOp::<IndirectY>::exec(self),
+
+            0x0b | 0x2b => AncOp::<Immediate>::exec(self),
+            0x4b => AlrOp::<Immediate>::exec(self),
+            0x6b => ArrOp::<Immediate>::exec(self),
+            0xcb => AxsOp::<Immediate>::exec(self),
+            0xeb => SbcOp::<Immediate>::exec(self),
+
+            0x1a | 0x3a | 0x5a | 0x7a | 0xda | 0xfa => NopOp::<Implied>::exec(self),
+            0x80 | 0x82 | 0x89 | 0xc2 | 0xe2 => NopReadOp::<Immediate>::exec(self),
+            0x04 | 0x44 | 0x64 => NopReadOp::<ZeroPage>::exec(self),
+            0x14 | 0x34 | 0x54 | 0x74 | 0xd4 | 0xf4 => NopReadOp::<ZeroPageX>::exec(self),
+            0x0c => NopReadOp::<Absolute>::exec(self),
+            0x1c | 0x3c | 0x5c | 0x7c | 0xdc | 0xfc => NopReadOp::<AbsoluteX>::exec(self),
+
+            0x93 => ShaOp::<IndirectY>::exec(self),
+            0x9f => ShaOp::<AbsoluteY>::exec(self),
+            0x9e => ShxOp::<AbsoluteY>::exec(self),
+            0x9c => ShyOp::<AbsoluteX>::exec(self),
+            0x9b => TasOp::<AbsoluteY>::exec(self),
+            0xbb => LasOp::<AbsoluteY>::exec(self),
+            0x8b => XaaOp::<Immediate>::exec(self),
+            0xab => LxaOp::<Immediate>::exec(self),
+
+            0x02 | 0x12 | 0x22 | 0x32 | 0x42 | 0x52 | 0x62 | 0x72 | 0x92 | 0xb2 | 0xd2 | 0xf2 => {
+                JamOp::<Implied>::exec(self)
             }
         };
 
+        //cli, sei and plp change the i flag after the poll of the next interrupt, so they take effect one instruction late
+        self.state.irq_poll_disabled = match opcode {
+            0x58 | 0x78 | 0x28 => interrupt_disable,
+            _ => self.state.flags.get(Flags::InterruptDisable),
+        };
+
         self.state.last_instruction = opcode;
-        Ok(())
+    }
+
+    //every bus access takes one cpu cycle, the memory sees the cycle the access happens on
+    fn read(&mut self, addr: MemoryPtr) -> u8 {
+        let v = self.memory.read(addr, self.state);
+        self.state.cycle_count += 1;
+        v
+    }
+
+    fn write(&mut self, addr: MemoryPtr, v: u8) {
+        self.memory.write(addr, v, self.state);
+        self.state.cycle_count += 1;
     }
 
     fn stack_push(&mut self, v: u8) {
-        self.memory
-            .write(MemoryPtr(self.state.stack_pointer as u16 + 0x0100), v, self.state);
+        self.write(MemoryPtr(self.state.stack_pointer as u16 + 0x0100), v);
         self.state.stack_pointer = self.state.stack_pointer.wrapping_sub(1);
     }
 
     fn stack_pop(&mut self) -> u8 {
         self.state.stack_pointer = self.state.stack_pointer.wrapping_add(1);
 
-        self.memory
-            .read(MemoryPtr(self.state.stack_pointer as u16 + 0x0100), self.state)
+        self.read(MemoryPtr(self.state.stack_pointer as u16 + 0x0100))
     }
 
-    pub fn nmi(&mut self) {
-        self.stack_push((self.state.program_counter.0  >> 8) as u8);
-        self.stack_push((self.state.program_counter.0 & 0xff) as u8);
-        self.stack_push(self.state.flags);
+    //pulls spend a cycle reading the stack before the stack pointer is incremented
+    fn stack_dummy_read(&mut self) {
+        self.read(MemoryPtr(self.state.stack_pointer as u16 + 0x0100));
+    }
 
-        self.state.program_counter = MemoryPtr(((self.memory.read(MemoryPtr(0xfffb), self.state) as u16) << 8) | (self.memory.read(MemoryPtr(0xfffa), self.state) as u16));
+    //nmi and irq read the next opcode twice without using it, then push and jump like brk
+    fn interrupt_sequence(&mut self, vector: u16) {
+        self.read(self.state.program_counter);
+        self.read(self.state.program_counter);
+        self.interrupt(self.state.program_counter, vector, false);
+    }
 
+    //pushes the return address and the flags and jumps through a vector, shared by nmi, irq and brk.
+    //the break flag only exists on the stack: it is set when pushed by brk and clear for a real interrupt.
+    //an nmi that is pending by the time the vector is read takes over an irq or brk
+    fn interrupt(&mut self, return_address: MemoryPtr, vector: u16, brk: bool) {
+        self.stack_push((return_address.0 >> 8) as u8);
+        self.stack_push((return_address.0 & 0xff) as u8);
+
+        let mut flags = self.state.flags;
+        flags.set(Flags::Break, brk);
+        flags.set(Flags::Unused, true);
+        self.stack_push(flags);
+        self.state.flags.set(Flags::InterruptDisable, true);
+
+        let vector = if self.state.nmi_pending {
+            self.state.nmi_pending = false;
+            NMI_VECTOR
+        } else {
+            vector
+        };
+
+        let low = self.read(MemoryPtr(vector)) as u16;
+        let high = self.read(MemoryPtr(vector + 1)) as u16;
+        self.state.program_counter = MemoryPtr((high << 8) | low);
     }
+}
 
-    fn irq(&mut self) {
-        if self.state.flags.get(Flags::InterruptDisable) {
-            return;
-        }
-        self.stack_push((self.state.program_counter.0  >> 8) as u8);
-        self.stack_push((self.state.program_counter.0 & 0xff) as u8);
-        self.stack_push(self.state.flags);
+impl SaveState for Cpu {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.accumulator);
+        w.u8(self.x);
+        w.u8(self.y);
+        w.u8(self.flags);
+        w.u8(self.stack_pointer);
+        w.u16(self.program_counter.0);
+        w.u8(self.irq_sources);
+        w.bool(self.nmi_pending);
+        w.bool(self.irq_poll_disabled);
+        w.u64(self.cycle_count);
+        w.u8(self.last_instruction);
+        w.bool(self.halted);
+    }
 
-        self.state.program_counter = MemoryPtr(((self.memory.read(MemoryPtr(0xffff), self.state) as u16) << 8) | (self.memory.read(MemoryPtr(0xfffe), self.state) as u16));
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.accumulator = r.u8()?;
+        self.x = r.u8()?;
+        self.y = r.u8()?;
+        self.flags = r.u8()?;
+        self.stack_pointer = r.u8()?;
+        self.program_counter = MemoryPtr(r.u16()?);
+        self.irq_sources = r.u8()?;
+        self.nmi_pending = r.bool()?;
+        self.irq_poll_disabled = r.bool()?;
+        self.cycle_count = r.u64()?;
+        self.last_instruction = r.u8()?;
+        self.halted = r.bool()?;
+        Ok(())
     }
 }
 
@@ -587,6 +587,751 @@ fn test_jsr_instruction() {
 }
 
 
+#[test]
+fn test_lax_instruction() {
+    process_testcase(
+        RelevantState {
+            accumulator: 0,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0xa7, 3, 0, 0x80]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0x80,
+            flags: new_flags(&[Flags::Sign]),
+            x: 0x80,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0xa7, 3, 0, 0x80]),
+            instructions_to_execute: 1,
+        },
+    );
+}
+
+#[test]
+fn test_sax_instruction() {
+    process_testcase(
+        RelevantState {
+            accumulator: 0xf0,
+            flags: 0,
+            x: 0x3c,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x87, 4]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0xf0,
+            flags: 0,
+            x: 0x3c,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0x87, 4, 0, 0, 0x30]),
+            instructions_to_execute: 1,
+        },
+    );
+}
+
+#[test]
+fn test_dcp_instruction() {
+    process_testcase(
+        RelevantState {
+            accumulator: 0x10,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0xcf, 4, 0, 0, 0x11]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0x10,
+            flags: new_flags(&[Flags::Zero, Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(3),
+            ram: pad_ram(&[0xcf, 4, 0, 0, 0x10]),
+            instructions_to_execute: 1,
+        },
+    );
+}
+
+#[test]
+fn test_isc_instruction() {
+    process_testcase(
+        RelevantState {
+            accumulator: 0x10,
+            flags: new_flags(&[Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0xe7, 2, 0x0f]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0,
+            flags: new_flags(&[Flags::Zero, Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0xe7, 2, 0x10]),
+            instructions_to_execute: 1,
+        },
+    );
+}
+
+#[test]
+fn test_read_modify_write_combinations() {
+    //slo: asl then ora
+    process_testcase(
+        RelevantState {
+            accumulator: 0x01,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x07, 2, 0x81]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0x03,
+            flags: new_flags(&[Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0x07, 2, 0x02]),
+            instructions_to_execute: 1,
+        },
+    );
+
+    //rla: rol then and
+    process_testcase(
+        RelevantState {
+            accumulator: 0x0f,
+            flags: new_flags(&[Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x27, 2, 0x42]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0x05,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0x27, 2, 0x85]),
+            instructions_to_execute: 1,
+        },
+    );
+
+    //sre: lsr then eor
+    process_testcase(
+        RelevantState {
+            accumulator: 0xff,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x47, 2, 0x03]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0xfe,
+            flags: new_flags(&[Flags::Sign, Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0x47, 2, 0x01]),
+            instructions_to_execute: 1,
+        },
+    );
+
+    //rra: ror then adc with the carry shifted out
+    process_testcase(
+        RelevantState {
+            accumulator: 0x10,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x67, 2, 0x03]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0x12,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0x67, 2, 0x01]),
+            instructions_to_execute: 1,
+        },
+    );
+}
+
+#[test]
+fn test_immediate_combinations() {
+    //anc copies the sign to the carry
+    process_testcase(
+        RelevantState {
+            accumulator: 0xff,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x0b, 0x80]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0x80,
+            flags: new_flags(&[Flags::Sign, Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0x0b, 0x80]),
+            instructions_to_execute: 1,
+        },
+    );
+
+    //alr
+    process_testcase(
+        RelevantState {
+            accumulator: 0xff,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x4b, 0x03]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0x01,
+            flags: new_flags(&[Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0x4b, 0x03]),
+            instructions_to_execute: 1,
+        },
+    );
+
+    //arr takes carry and overflow from bits 6 and 5
+    process_testcase(
+        RelevantState {
+            accumulator: 0xff,
+            flags: new_flags(&[Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x6b, 0xbf]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0xdf,
+            flags: new_flags(&[Flags::Sign, Flags::Carry, Flags::Overflow]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0x6b, 0xbf]),
+            instructions_to_execute: 1,
+        },
+    );
+
+    //axs
+    process_testcase(
+        RelevantState {
+            accumulator: 0x0f,
+            flags: 0,
+            x: 0xf3,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0xcb, 0x01]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0x0f,
+            flags: new_flags(&[Flags::Carry]),
+            x: 0x02,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0xcb, 0x01]),
+            instructions_to_execute: 1,
+        },
+    );
+
+    //sbc $eb is the same as $e9
+    process_testcase(
+        RelevantState {
+            accumulator: 0x10,
+            flags: new_flags(&[Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0xeb, 0x01]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0x0f,
+            flags: new_flags(&[Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(2),
+            ram: pad_ram(&[0xeb, 0x01]),
+            instructions_to_execute: 1,
+        },
+    );
+}
+
+#[test]
+fn test_unofficial_nops() {
+    process_testcase(
+        RelevantState {
+            accumulator: 0,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x04, 0x10, 0x1c, 0, 0, 0x80, 0x55, 0xfa, 0x14, 0x10, 0x0c, 0, 0]),
+            instructions_to_execute: 6,
+        },
+        RelevantState {
+            accumulator: 0,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(13),
+            ram: pad_ram(&[0x04, 0x10, 0x1c, 0, 0, 0x80, 0x55, 0xfa, 0x14, 0x10, 0x0c, 0, 0]),
+            instructions_to_execute: 6,
+        },
+    );
+}
+
+#[test]
+fn test_shx_instruction() {
+    let mut expectedram = pad_ram(&[0x9e, 0x10, 0x03]);
+    expectedram[0x0311] = 0x04;
+
+    //the value is anded with the high byte of the address plus one
+    process_testcase(
+        RelevantState {
+            accumulator: 0,
+            flags: 0,
+            x: 0xff,
+            y: 0x01,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x9e, 0x10, 0x03]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0,
+            flags: 0,
+            x: 0xff,
+            y: 0x01,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(3),
+            ram: expectedram,
+            instructions_to_execute: 1,
+        },
+    );
+
+    let mut expectedram = pad_ram(&[0x9e, 0xff, 0x02]);
+    expectedram[0x0101] = 0x01;
+
+    //crossing a page also replaces the high byte of the address
+    process_testcase(
+        RelevantState {
+            accumulator: 0,
+            flags: 0,
+            x: 0x05,
+            y: 0x02,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x9e, 0xff, 0x02]),
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0,
+            flags: 0,
+            x: 0x05,
+            y: 0x02,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(3),
+            ram: expectedram,
+            instructions_to_execute: 1,
+        },
+    );
+}
+
+#[test]
+fn test_jam_instruction() {
+    //the cpu stops on the jam and never runs the inx
+    process_testcase(
+        RelevantState {
+            accumulator: 0,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x02, 0xe8]),
+            instructions_to_execute: 3,
+        },
+        RelevantState {
+            accumulator: 0,
+            flags: 0,
+            x: 0,
+            y: 0,
+            stack_pointer: 0,
+            program_counter: MemoryPtr(0),
+            ram: pad_ram(&[0x02, 0xe8]),
+            instructions_to_execute: 3,
+        },
+    );
+}
+
+#[test]
+fn test_brk_instruction() {
+    let mut testram = pad_ram(&[0x00, 0xff]);
+    testram[0x07fe] = 0x34;
+    testram[0x07ff] = 0x12;
+
+    //the pushed flags have the break flag set and the return address skips the padding byte
+    let mut expectedram = testram;
+    expectedram[0x01ff] = 0x00;
+    expectedram[0x01fe] = 0x02;
+    expectedram[0x01fd] = new_flags(&[Flags::Break, Flags::Unused, Flags::Carry]);
+
+    process_testcase(
+        RelevantState {
+            accumulator: 0,
+            flags: new_flags(&[Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0xff,
+            program_counter: MemoryPtr(0),
+            ram: testram,
+            instructions_to_execute: 1,
+        },
+        RelevantState {
+            accumulator: 0,
+            flags: new_flags(&[Flags::Carry, Flags::InterruptDisable]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0xfc,
+            program_counter: MemoryPtr(0x1234),
+            ram: expectedram,
+            instructions_to_execute: 1,
+        },
+    );
+}
+
+#[test]
+fn test_rti_clears_break_flag() {
+    //brk into a handler at $0300 that only returns, the break flag pushed by brk is not restored
+    let mut testram = pad_ram(&[0x00, 0xff]);
+    testram[0x07fe] = 0x00;
+    testram[0x07ff] = 0x03;
+    testram[0x0300] = 0x40;
+
+    let mut expectedram = testram;
+    expectedram[0x01ff] = 0x00;
+    expectedram[0x01fe] = 0x02;
+    expectedram[0x01fd] = new_flags(&[Flags::Break, Flags::Unused, Flags::Carry]);
+
+    process_testcase(
+        RelevantState {
+            accumulator: 0,
+            flags: new_flags(&[Flags::Carry]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0xff,
+            program_counter: MemoryPtr(0),
+            ram: testram,
+            instructions_to_execute: 2,
+        },
+        RelevantState {
+            accumulator: 0,
+            flags: new_flags(&[Flags::Carry, Flags::Unused]),
+            x: 0,
+            y: 0,
+            stack_pointer: 0xff,
+            program_counter: MemoryPtr(0x0002),
+            ram: expectedram,
+            instructions_to_execute: 2,
+        },
+    );
+}
+
+fn interrupt_test_ram(program: &[u8]) -> Ram {
+    let mut state = pad_ram(program);
+    //nmi handler at $0300, irq handler at $0400, both start with a nop
+    state[0x07fa] = 0x00;
+    state[0x07fb] = 0x03;
+    state[0x07fe] = 0x00;
+    state[0x07ff] = 0x04;
+    state[0x0300] = 0xea;
+    state[0x0400] = 0xea;
+
+    let mut ram = Ram::new();
+    ram.set_ram_state(state);
+    ram
+}
+
+#[test]
+fn test_irq_entry() {
+    let mut ram = interrupt_test_ram(&[0xea]);
+    let mut cpu = Cpu::new();
+    cpu.flags = new_flags(&[Flags::Unused, Flags::Carry]);
+    cpu.irq_poll_disabled = false;
+    cpu.set_irq(IRQ_MAPPER, true);
+
+    cpu.context_borrowed(&mut ram).execute_next_instruction();
+
+    //the handler's first instruction ran after the seven cycles of the interrupt
+    assert_eq!(cpu.program_counter, MemoryPtr(0x0401));
+    assert_eq!(cpu.cycle_count, 7 + 7 + 2);
+    assert!(cpu.flags.get(Flags::InterruptDisable));
+    assert_eq!(cpu.stack_pointer, 0xfa);
+    let stack = ram.dump_ram();
+    assert_eq!(stack[0x01fd], 0x00);
+    assert_eq!(stack[0x01fc], 0x00);
+    assert_eq!(stack[0x01fb], new_flags(&[Flags::Unused, Flags::Carry]));
+}
+
+#[test]
+fn test_irq_poll_delay() {
+    //cli, nop: the irq waits until the cli is one instruction behind
+    let mut ram = interrupt_test_ram(&[0x58, 0xea]);
+    let mut cpu = Cpu::new();
+    cpu.set_irq(IRQ_MAPPER, true);
+
+    cpu.context_borrowed(&mut ram).execute_next_instruction();
+    cpu.context_borrowed(&mut ram).execute_next_instruction();
+    assert_eq!(cpu.program_counter, MemoryPtr(2));
+
+    cpu.context_borrowed(&mut ram).execute_next_instruction();
+    assert_eq!(cpu.program_counter, MemoryPtr(0x0401));
+
+    //sei: an irq right after it still gets through
+    let mut ram = interrupt_test_ram(&[0x78, 0xea]);
+    let mut cpu = Cpu::new();
+    cpu.flags = new_flags(&[Flags::Unused]);
+    cpu.irq_poll_disabled = false;
+
+    cpu.context_borrowed(&mut ram).execute_next_instruction();
+    cpu.set_irq(IRQ_MAPPER, true);
+    cpu.context_borrowed(&mut ram).execute_next_instruction();
+    assert_eq!(cpu.program_counter, MemoryPtr(0x0401));
+
+    //and is blocked from then on
+    let mut ram = interrupt_test_ram(&[0x78, 0xea, 0xea]);
+    let mut cpu = Cpu::new();
+    cpu.flags = new_flags(&[Flags::Unused]);
+    cpu.irq_poll_disabled = false;
+
+    cpu.context_borrowed(&mut ram).execute_next_instruction();
+    cpu.context_borrowed(&mut ram).execute_next_instruction();
+    cpu.set_irq(IRQ_MAPPER, true);
+    cpu.context_borrowed(&mut ram).execute_next_instruction();
+    assert_eq!(cpu.program_counter, MemoryPtr(3));
+}
+
+#[test]
+fn test_nmi_entry() {
+    let mut ram = interrupt_test_ram(&[0xea]);
+    let mut cpu = Cpu::new();
+    cpu.nmi_pending = true;
+
+    //the nmi ignores the i flag
+    cpu.context_borrowed(&mut ram).execute_next_instruction();
+    assert_eq!(cpu.program_counter, MemoryPtr(0x0301));
+    assert!(!cpu.nmi_pending);
+    assert_eq!(ram.dump_ram()[0x01fb], new_flags(&[Flags::Unused, Flags::InterruptDisable]));
+}
+
+//raises an nmi while something is pushed to the stack
+struct NmiOnPush {
+    ram: Ram,
+}
+
+impl CpuMemory for NmiOnPush {
+    fn read(&mut self, addr: MemoryPtr, cpu: &mut Cpu) -> u8 {
+        self.ram.read(addr, cpu)
+    }
+    fn write(&mut self, addr: MemoryPtr, value: u8, cpu: &mut Cpu) {
+        if (0x0100..0x0200).contains(&addr.0) {
+            cpu.nmi_pending = true;
+        }
+        self.ram.write(addr, value, cpu)
+    }
+}
+
+#[test]
+fn test_nmi_hijacks_brk() {
+    let mut memory = NmiOnPush { ram: interrupt_test_ram(&[0x00, 0xff]) };
+    let mut cpu = Cpu::new();
+
+    cpu.context_borrowed(&mut memory).execute_next_instruction();
+
+    //the brk goes to the nmi handler but still pushes the break flag
+    assert_eq!(cpu.program_counter, MemoryPtr(0x0300));
+    assert!(!cpu.nmi_pending);
+    let stack = memory.ram.dump_ram();
+    assert_eq!(stack[0x01fc], 0x02);
+    assert_eq!(stack[0x01fb], new_flags(&[Flags::Unused, Flags::Break, Flags::InterruptDisable]));
+}
+
+//cycles taken by the instruction at the start of `program`, with the given index registers
+fn instruction_cycles(program: &[u8], x: u8, y: u8) -> u64 {
+    let mut ram = Ram::new();
+    ram.set_ram_state(pad_ram(program));
+
+    let mut cpu = Cpu::new();
+    cpu.x = x;
+    cpu.y = y;
+    cpu.cycle_count = 0;
+
+    cpu.context_borrowed(&mut ram).execute_next_instruction();
+    cpu.cycle_count
+}
+
+#[test]
+fn test_instruction_cycles() {
+    let cases: &[(&[u8], u8, u8, u64)] = &[
+        //reads only pay for crossing a page
+        (&[0xbd, 0x10, 0x02], 1, 0, 4),
+        (&[0xbd, 0xff, 0x02], 1, 0, 5),
+        (&[0xb9, 0xff, 0x02], 0, 1, 5),
+        (&[0xb1, 0x02, 0xff, 0x02], 0, 0, 5),
+        (&[0xb1, 0x02, 0xff, 0x02], 0, 1, 6),
+        (&[0x1c, 0xff, 0x02], 1, 0, 5),
+        (&[0xbb, 0xff, 0x02], 0, 1, 5),
+        //stores always do
+        (&[0x9d, 0x10, 0x02], 1, 0, 5),
+        (&[0x99, 0x10, 0x02], 0, 1, 5),
+        (&[0x91, 0x02, 0x00, 0x03], 0, 0, 6),
+        (&[0x81, 0x02, 0x00, 0x03], 0, 0, 6),
+        (&[0x9e, 0x10, 0x02], 0, 1, 5),
+        //read-modify-write
+        (&[0x0a], 0, 0, 2),
+        (&[0x06, 0x10], 0, 0, 5),
+        (&[0x16, 0x10], 0, 0, 6),
+        (&[0x0e, 0x10, 0x02], 0, 0, 6),
+        (&[0x1e, 0x10, 0x02], 1, 0, 7),
+        (&[0xfe, 0x10, 0x02], 1, 0, 7),
+        (&[0xc6, 0x10], 0, 0, 5),
+        (&[0x1b, 0x10, 0x02], 0, 1, 7),
+        (&[0xe3, 0x02, 0x00, 0x03], 0, 0, 8),
+        (&[0xd3, 0x02, 0x00, 0x03], 0, 0, 8),
+        //jumps and the stack
+        (&[0x4c, 0x00, 0x03], 0, 0, 3),
+        (&[0x6c, 0x10, 0x02], 0, 0, 5),
+        (&[0x20, 0x00, 0x03], 0, 0, 6),
+        (&[0x60], 0, 0, 6),
+        (&[0x40], 0, 0, 6),
+        (&[0x00, 0x00], 0, 0, 7),
+        (&[0x48], 0, 0, 3),
+        (&[0x08], 0, 0, 3),
+        (&[0x68], 0, 0, 4),
+        (&[0x28], 0, 0, 4),
+        //branches: not taken, taken, and taken to another page
+        (&[0xf0, 0x10], 0, 0, 2),
+        (&[0xd0, 0x10], 0, 0, 3),
+        (&[0xd0, 0x80], 0, 0, 4),
+    ];
+
+    for (program, x, y, cycles) in cases {
+        assert_eq!(instruction_cycles(program, *x, *y), *cycles, "opcode {:02X} with x={} y={}", program[0], x, y);
+    }
+}
+
+//records every bus access with the cycle it happens on
+struct BusLog {
+    ram: Ram,
+    accesses: Vec<(u64, u16, Option<u8>)>,
+}
+
+impl CpuMemory for BusLog {
+    fn read(&mut self, addr: MemoryPtr, cpu: &mut Cpu) -> u8 {
+        self.accesses.push((cpu.cycle_count, addr.0, None));
+        self.ram.read(addr, cpu)
+    }
+    fn write(&mut self, addr: MemoryPtr, value: u8, cpu: &mut Cpu) {
+        self.accesses.push((cpu.cycle_count, addr.0, Some(value)));
+        self.ram.write(addr, value, cpu)
+    }
+}
+
+//the addresses the instruction at the start of `program` touches, one per cycle
+fn bus_accesses(program: &[u8], x: u8) -> Vec<(u16, Option<u8>)> {
+    let mut ram = Ram::new();
+    ram.set_ram_state(pad_ram(program));
+    let mut memory = BusLog { ram, accesses: Vec::new() };
+
+    let mut cpu = Cpu::new();
+    cpu.x = x;
+    cpu.cycle_count = 0;
+    cpu.context_borrowed(&mut memory).execute_next_instruction();
+
+    for (i, (cycle, _, _)) in memory.accesses.iter().enumerate() {
+        assert_eq!(*cycle, i as u64);
+    }
+    assert_eq!(cpu.cycle_count, memory.accesses.len() as u64);
+    memory.accesses.iter().map(|(_, addr, value)| (*addr, *value)).collect()
+}
+
+#[test]
+fn test_dummy_accesses() {
+    //an indexed read that crosses a page first reads from the wrong page
+    assert_eq!(bus_accesses(&[0xbd, 0xff, 0x02], 1), [(0, None), (1, None), (2, None), (0x0200, None), (0x0300, None)]);
+
+    //stores always do the extra read, even without crossing
+    assert_eq!(bus_accesses(&[0x9d, 0x10, 0x02], 1), [(0, None), (1, None), (2, None), (0x0211, None), (0x0211, Some(0))]);
+
+    //read-modify-write writes the old value back before the new one
+    assert_eq!(bus_accesses(&[0xe6, 0x10], 0), [(0, None), (1, None), (0x10, None), (0x10, Some(0)), (0x10, Some(1))]);
+
+    //zero page indexing reads the unindexed address while adding
+    assert_eq!(bus_accesses(&[0xb5, 0x10], 2), [(0, None), (1, None), (0x10, None), (0x12, None)]);
+
+    //implied instructions read the byte after the opcode
+    assert_eq!(bus_accesses(&[0xe8], 0), [(0, None), (1, None)]);
+}
+
 fn pad_ram(data: &[u8]) -> [u8; 2048] {
     let mut ram_state: [u8; 2048] = [0; 2048];
     ram_state[..data.len()].copy_from_slice(&data);
@@ -647,14 +1392,14 @@ fn process_testcase(initial: RelevantState, expected: RelevantState) {
         stack_pointer: initial.stack_pointer,
         cycle_count: 0,
         last_instruction: 0,
-        irq_requested: false,
+        irq_sources: 0,
+        nmi_pending: false,
+        irq_poll_disabled: true,
+        halted: false,
     };
 
     for _ in 0..initial.instructions_to_execute {
-        match cpu.context_borrowed(&mut ram).execute_next_instruction() {
-            Err(()) => panic!("error executing instruction"),
-            _ => (),
-        }
+        cpu.context_borrowed(&mut ram).execute_next_instruction();
     }
 
     let got = RelevantState {
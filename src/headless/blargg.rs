@@ -0,0 +1,93 @@
+use std::fmt;
+
+use crate::Nes;
+
+//status protocol of blargg's test roms: $6000 holds the status, $6001-$6003 the signature
+//and $6004 on a zero terminated text with the results
+const STATUS: u16 = 0x6000;
+const SIGNATURE: [u8; 3] = [0xde, 0xb0, 0x61];
+const TEXT: u16 = 0x6004;
+
+const RUNNING: u8 = 0x80;
+const RESET_REQUESTED: u8 = 0x81;
+
+//the roms want the reset button pressed at least 100 ms after they ask for it
+const RESET_DELAY_FRAMES: u64 = 10;
+
+pub struct TestResult {
+    //0 means passed, anything else is a rom specific error code
+    pub code: u8,
+    pub text: String,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.code == 0
+    }
+}
+
+#[derive(Debug)]
+pub enum TestRomError {
+    //the rom never wrote the signature, it probably doesn't use the protocol
+    NoSignature,
+    //still running after the frame limit, with the text written so far
+    Timeout(String),
+}
+
+impl fmt::Display for TestRomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TestRomError::NoSignature => write!(f, "the rom doesn't report results at $6000"),
+            TestRomError::Timeout(text) => write!(f, "the test didn't finish in time:\n{}", text),
+        }
+    }
+}
+
+//runs a test rom until it reports a result, pressing reset when it asks for it
+pub fn run_test_rom(console: &mut Nes, max_frames: u64) -> Result<TestResult, TestRomError> {
+    let mut started = false;
+    let mut reset_frame = None;
+
+    for frame in 0..max_frames {
+        console.run_frame();
+        if !has_signature(console) {
+            continue;
+        }
+        started = true;
+
+        if reset_frame == Some(frame) {
+            console.reset();
+            reset_frame = None;
+            continue;
+        }
+
+        match console.peek(STATUS) {
+            RUNNING => {},
+            RESET_REQUESTED => {
+                reset_frame.get_or_insert(frame + RESET_DELAY_FRAMES);
+            },
+            code => return Ok(TestResult { code, text: read_text(console) }),
+        }
+    }
+
+    if started {
+        Err(TestRomError::Timeout(read_text(console)))
+    } else {
+        Err(TestRomError::NoSignature)
+    }
+}
+
+fn has_signature(console: &mut Nes) -> bool {
+    (0..3).all(|i| console.peek(STATUS + 1 + i) == SIGNATURE[i as usize])
+}
+
+pub fn read_text(console: &mut Nes) -> String {
+    let mut text = Vec::new();
+    for addr in TEXT..=0x7fff {
+        match console.peek(addr) {
+            0 => break,
+            v => text.push(v),
+        }
+    }
+    String::from_utf8_lossy(&text).trim_end().to_string()
+}
@@ -0,0 +1,80 @@
+use std::io::{self, Write};
+
+//writes 0x00rrggbb pixels as a binary ppm
+pub fn write_ppm<W: Write>(w: &mut W, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    write!(w, "P6\n{} {}\n255\n", width, height)?;
+    w.write_all(&rgb_bytes(pixels))
+}
+
+//writes 0x00rrggbb pixels as a png. the image data is stored without compression,
+//which keeps the writer small and the files are still readable by anything
+pub fn write_png<W: Write>(w: &mut W, width: usize, height: usize, pixels: &[u32]) -> io::Result<()> {
+    w.write_all(b"\x89PNG\r\n\x1a\n")?;
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    //8 bits per channel, rgb, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+    write_chunk(w, b"IHDR", &header)?;
+
+    //every row starts with filter type 0 (none)
+    let mut raw = Vec::with_capacity(height * (1 + width * 3));
+    for row in pixels.chunks(width) {
+        raw.push(0);
+        raw.extend_from_slice(&rgb_bytes(row));
+    }
+    write_chunk(w, b"IDAT", &zlib_stored(&raw))?;
+    write_chunk(w, b"IEND", &[])
+}
+
+fn rgb_bytes(pixels: &[u32]) -> Vec<u8> {
+    pixels.iter().flat_map(|p| [(p >> 16) as u8, (p >> 8) as u8, *p as u8]).collect()
+}
+
+fn write_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    let crc = crc32(&[kind.as_slice(), data].concat());
+    w.write_all(&crc.to_be_bytes())
+}
+
+//zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let len = block.len() as u16;
+        out.push(last as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+pub(super) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffffffffu32;
+    for v in data {
+        crc ^= *v as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub(super) fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for v in data {
+        a = (a + *v as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
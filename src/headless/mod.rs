@@ -0,0 +1,60 @@
+pub mod blargg;
+pub mod image;
+pub mod script;
+
+use std::{fmt, num::ParseIntError};
+
+//stop condition of a headless run, checked after every frame: `6000=80` or `6000!=80`, in hex
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Condition {
+    pub addr: u16,
+    pub value: u8,
+    pub equal: bool,
+}
+
+#[derive(Debug)]
+pub struct ParseConditionError(String);
+
+impl fmt::Display for ParseConditionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid condition {:?}, expected <addr>=<value> or <addr>!=<value> in hex", self.0)
+    }
+}
+
+impl Condition {
+    pub fn parse(s: &str) -> Result<Condition, ParseConditionError> {
+        let error = |_: ParseIntError| ParseConditionError(s.to_string());
+        let (addr, value, equal) = match s.split_once("!=") {
+            Some((addr, value)) => (addr, value, false),
+            None => match s.split_once('=') {
+                Some((addr, value)) => (addr, value, true),
+                None => return Err(ParseConditionError(s.to_string())),
+            },
+        };
+
+        Ok(Condition {
+            addr: parse_hex(addr).map_err(error)?,
+            value: parse_hex(value).map_err(error)?.try_into().map_err(|_| ParseConditionError(s.to_string()))?,
+            equal,
+        })
+    }
+
+    pub fn check(&self, value: u8) -> bool {
+        (value == self.value) == self.equal
+    }
+}
+
+//accepts an optional $ or 0x prefix
+fn parse_hex(s: &str) -> Result<u16, ParseIntError> {
+    let s = s.trim();
+    let s = s.strip_prefix('$').or_else(|| s.strip_prefix("0x")).unwrap_or(s);
+    u16::from_str_radix(s, 16)
+}
+
+//64-bit fnv-1a, used to compare framebuffers between runs
+pub fn hash(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, v| (hash ^ *v as u64).wrapping_mul(0x100000001b3))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,103 @@
+use std::fmt;
+
+use crate::{Button, Nes, Player};
+
+//inputs for a headless run, one change per line:
+//  <frame> <player> <buttons>
+//buttons are a comma separated list of a, b, select, start, up, down, left and right, or `none`.
+//they stay pressed until the next line for the same player. `#` starts a comment
+pub struct InputScript {
+    //sorted by frame
+    changes: Vec<InputChange>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct InputChange {
+    pub frame: u64,
+    pub player: Player,
+    pub buttons: [bool; 8],
+}
+
+#[derive(Debug)]
+pub struct ParseScriptError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+const BUTTON_NAMES: [(&str, Button); 8] = [
+    ("a", Button::A),
+    ("b", Button::B),
+    ("select", Button::SELECT),
+    ("start", Button::START),
+    ("up", Button::UP),
+    ("down", Button::DOWN),
+    ("left", Button::LEFT),
+    ("right", Button::RIGHT),
+];
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<InputScript, ParseScriptError> {
+        let mut changes = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| ParseScriptError { line: i + 1, message };
+
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [frame, player, buttons] = fields[..] else {
+                return Err(error("expected <frame> <player> <buttons>".to_string()));
+            };
+
+            let frame = frame.parse().map_err(|_| error(format!("invalid frame {:?}", frame)))?;
+            let player = match player {
+                "1" => Player::One,
+                "2" => Player::Two,
+                _ => return Err(error(format!("invalid player {:?}, expected 1 or 2", player))),
+            };
+
+            let mut pressed = [false; 8];
+            if buttons != "none" {
+                for name in buttons.split(',') {
+                    let Some((_, button)) = BUTTON_NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)) else {
+                        return Err(error(format!("unknown button {:?}", name)));
+                    };
+                    pressed[*button as usize] = true;
+                }
+            }
+
+            changes.push(InputChange { frame, player, buttons: pressed });
+        }
+
+        //stable, so later lines for the same frame win
+        changes.sort_by_key(|c| c.frame);
+        Ok(InputScript { changes })
+    }
+
+    pub fn changes(&self) -> &[InputChange] {
+        &self.changes
+    }
+
+    //the changes that happen at the start of `frame`
+    pub fn changes_at(&self, frame: u64) -> impl Iterator<Item = &InputChange> {
+        self.changes.iter().filter(move |c| c.frame == frame)
+    }
+
+    //sets the buttons for the frame that is about to run
+    pub fn apply(&self, frame: u64, console: &mut Nes) {
+        for change in self.changes_at(frame) {
+            for (_, button) in BUTTON_NAMES.iter() {
+                console.set_button(change.player, *button, change.buttons[*button as usize]);
+            }
+        }
+    }
+}
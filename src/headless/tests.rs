@@ -0,0 +1,160 @@
+use super::{
+    blargg::{run_test_rom, TestRomError},
+    image::{adler32, crc32, write_png, write_ppm},
+    script::InputScript,
+    Condition,
+};
+use crate::{mappers::{chr::ChrMemory, nrom}, Nes, Player};
+
+//speaks the $6000 protocol: asks for a reset on the first run, then reports "OK" with code 3
+const STATUS_PROGRAM: [u8; 56] = [
+    0xa9, 0xde, 0x8d, 0x01, 0x60, //lda #$de, sta $6001
+    0xa9, 0xb0, 0x8d, 0x02, 0x60, //lda #$b0, sta $6002
+    0xa9, 0x61, 0x8d, 0x03, 0x60, //lda #$61, sta $6003
+    0xee, 0x00, 0x03, //inc $0300
+    0xad, 0x00, 0x03, //lda $0300
+    0xc9, 0x01, //cmp #$01
+    0xd0, 0x08, //bne done
+    0xa9, 0x81, 0x8d, 0x00, 0x60, //lda #$81, sta $6000
+    0x4c, 0x1e, 0x80, //jmp $801e
+    //done:
+    0xa9, 0x4f, 0x8d, 0x04, 0x60, //lda #'O', sta $6004
+    0xa9, 0x4b, 0x8d, 0x05, 0x60, //lda #'K', sta $6005
+    0xa9, 0x00, 0x8d, 0x06, 0x60, //lda #$00, sta $6006
+    0xa9, 0x03, 0x8d, 0x00, 0x60, //lda #$03, sta $6000
+    0x4c, 0x35, 0x80, //jmp $8035
+];
+
+fn program_console(program: &[u8]) -> Nes {
+    let mut prg_rom = [0; 16384];
+    prg_rom[..program.len()].copy_from_slice(program);
+    //reset vector
+    prg_rom[0x3ffc] = 0x00;
+    prg_rom[0x3ffd] = 0x80;
+
+    let cartridge = nrom::Nrom::new(&vec![prg_rom], ChrMemory::ram(8192), nrom::Mirroring::Horizontal).ok().unwrap();
+    Nes::new(Box::new(cartridge))
+}
+
+#[test]
+fn test_blargg_protocol() {
+    let mut console = program_console(&STATUS_PROGRAM);
+    let result = run_test_rom(&mut console, 60).unwrap();
+    assert_eq!(result.code, 3);
+    assert_eq!(result.text, "OK");
+    assert!(!result.passed());
+    //one reset happened
+    assert_eq!(console.peek(0x0300), 2);
+
+    //a rom that never answers
+    let mut console = program_console(&[0x4c, 0x00, 0x80]);
+    assert!(matches!(run_test_rom(&mut console, 5), Err(TestRomError::NoSignature)));
+
+    //stops right after the reset request
+    let mut console = program_console(&STATUS_PROGRAM[..33]);
+    assert!(matches!(run_test_rom(&mut console, 5), Err(TestRomError::Timeout(_))));
+}
+
+#[test]
+fn test_condition() {
+    assert_eq!(Condition::parse("6000=80").unwrap(), Condition { addr: 0x6000, value: 0x80, equal: true });
+    assert_eq!(Condition::parse("$00ff!=0").unwrap(), Condition { addr: 0xff, value: 0, equal: false });
+    assert_eq!(Condition::parse("0x6000=0x81").unwrap(), Condition { addr: 0x6000, value: 0x81, equal: true });
+
+    assert!(Condition::parse("6000").is_err());
+    assert!(Condition::parse("6000=100").is_err());
+    assert!(Condition::parse("zz=1").is_err());
+
+    let c = Condition::parse("6000!=80").unwrap();
+    assert!(c.check(0));
+    assert!(!c.check(0x80));
+}
+
+#[test]
+fn test_script() {
+    let script = InputScript::parse("# intro\n\n30 1 start\n10 2 a,Right # hold\n40 1 none\n").unwrap();
+    let changes = script.changes();
+    assert_eq!(changes.len(), 3);
+
+    assert_eq!((changes[0].frame, changes[0].player), (10, Player::Two));
+    assert_eq!(changes[0].buttons, [true, false, false, false, false, false, false, true]);
+    assert_eq!((changes[1].frame, changes[1].player), (30, Player::One));
+    assert_eq!(changes[1].buttons, [false, false, false, true, false, false, false, false]);
+    assert_eq!(changes[2].buttons, [false; 8]);
+
+    assert_eq!(script.changes_at(30).count(), 1);
+    assert_eq!(script.changes_at(31).count(), 0);
+}
+
+#[test]
+fn test_script_errors() {
+    let line = |text: &str| InputScript::parse(text).err().unwrap().line;
+    assert_eq!(line("1 1 a\n2 1 jump\n"), 2);
+    assert_eq!(line("x 1 a\n"), 1);
+    assert_eq!(line("1 3 a\n"), 1);
+    assert_eq!(line("\n\n1 1\n"), 3);
+}
+
+#[test]
+fn test_checksums() {
+    assert_eq!(crc32(b"IEND"), 0xae426082);
+    assert_eq!(crc32(b"123456789"), 0xcbf43926);
+    assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+}
+
+#[test]
+fn test_ppm() {
+    let mut out = Vec::new();
+    write_ppm(&mut out, 2, 1, &[0x102030, 0xffffff]).unwrap();
+    assert_eq!(out, b"P6\n2 1\n255\n\x10\x20\x30\xff\xff\xff");
+}
+
+#[test]
+fn test_png() {
+    let width = 300;
+    let height = 250;
+    let pixels: Vec<u32> = (0..width * height).map(|i| (i as u32).wrapping_mul(0x9e3779b1) & 0xffffff).collect();
+    let mut out = Vec::new();
+    write_png(&mut out, width, height, &pixels).unwrap();
+
+    assert_eq!(&out[..8], b"\x89PNG\r\n\x1a\n");
+
+    //walk the chunks, checking their crc and collecting the image data
+    let mut chunks = Vec::new();
+    let mut idat = Vec::new();
+    let mut pos = 8;
+    while pos < out.len() {
+        let len = u32::from_be_bytes(out[pos..pos + 4].try_into().unwrap()) as usize;
+        let body = &out[pos + 4..pos + 8 + len];
+        let crc = u32::from_be_bytes(out[pos + 8 + len..pos + 12 + len].try_into().unwrap());
+        assert_eq!(crc32(body), crc);
+        chunks.push(body[..4].to_vec());
+        if &body[..4] == b"IDAT" {
+            idat.extend_from_slice(&body[4..]);
+        }
+        pos += 12 + len;
+    }
+    assert_eq!(chunks, [b"IHDR".to_vec(), b"IDAT".to_vec(), b"IEND".to_vec()]);
+    assert_eq!(&out[16..24], [0, 0, 1, 44, 0, 0, 0, 250]);
+
+    //undo the stored deflate blocks
+    let mut raw = Vec::new();
+    let mut pos = 2;
+    loop {
+        let last = idat[pos] & 1 != 0;
+        let len = u16::from_le_bytes([idat[pos + 1], idat[pos + 2]]) as usize;
+        assert_eq!(u16::from_le_bytes([idat[pos + 3], idat[pos + 4]]), !(len as u16));
+        raw.extend_from_slice(&idat[pos + 5..pos + 5 + len]);
+        pos += 5 + len;
+        if last {
+            break;
+        }
+    }
+    assert_eq!(u32::from_be_bytes(idat[pos..pos + 4].try_into().unwrap()), adler32(&raw));
+
+    assert_eq!(raw.len(), height * (1 + width * 3));
+    let row = &raw[(1 + width * 3) * 7..];
+    assert_eq!(row[0], 0);
+    let p = pixels[7 * width];
+    assert_eq!(&row[1..4], [(p >> 16) as u8, (p >> 8) as u8, p as u8]);
+}
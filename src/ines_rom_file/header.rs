@@ -0,0 +1,171 @@
+use super::OpenRomError;
+
+const MAGIC: [u8; 4] = [0x4e, 0x45, 0x53, 0x1a];
+
+const FLAG6_MIRRORING: u8 = 0x01;
+const FLAG6_BATTERY: u8 = 0x02;
+const FLAG6_TRAINER: u8 = 0x04;
+const FLAG6_FOUR_SCREEN: u8 = 0x08;
+const FLAG7_CONSOLE_TYPE: u8 = 0x03;
+const FLAG7_NES2_MASK: u8 = 0x0c;
+const FLAG7_NES2: u8 = 0x08;
+
+const PRG_ROM_UNIT: u64 = 16384;
+const CHR_ROM_UNIT: u64 = 8192;
+const INES_PRG_RAM_UNIT: usize = 8192;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderFormat {
+    INes,
+    Nes2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    Playchoice10,
+    //nes 2.0 extended console type, see byte 13
+    Extended(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimingRegion {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+//sizes are in bytes
+#[derive(Debug, Clone)]
+pub struct RomHeader {
+    #[allow(dead_code)]
+    pub format: HeaderFormat,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub prg_rom_size: u64,
+    pub chr_rom_size: u64,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub mirroring: Mirroring,
+    pub four_screen: bool,
+    pub battery: bool,
+    pub trainer: bool,
+    pub console_type: ConsoleType,
+    pub timing: TimingRegion,
+    pub expansion_device: u8,
+    pub misc_roms: u8,
+}
+
+impl RomHeader {
+    pub fn parse(raw: &[u8; 16]) -> Result<RomHeader, OpenRomError> {
+        //format description: https://www.nesdev.org/wiki/INES and https://www.nesdev.org/wiki/NES_2.0
+        if raw[..4] != MAGIC {
+            return Err(OpenRomError::InvalidMagicConstant);
+        }
+
+        let format = if raw[7] & FLAG7_NES2_MASK == FLAG7_NES2 { HeaderFormat::Nes2 } else { HeaderFormat::INes };
+
+        //dumping tools used to write their name over bytes 7-15 ("DiskDude!"), an ines header with
+        //anything in bytes 12-15 is read as if all of them were zero
+        let mut raw = *raw;
+        if format == HeaderFormat::INes && raw[12..16].iter().any(|v| *v != 0) {
+            raw[7..].fill(0);
+        }
+
+        let mut header = RomHeader {
+            format,
+            mapper: ((raw[7] & 0xf0) | (raw[6] >> 4)) as u16,
+            submapper: 0,
+            prg_rom_size: raw[4] as u64 * PRG_ROM_UNIT,
+            chr_rom_size: raw[5] as u64 * CHR_ROM_UNIT,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            mirroring: if raw[6] & FLAG6_MIRRORING != 0 { Mirroring::Vertical } else { Mirroring::Horizontal },
+            four_screen: raw[6] & FLAG6_FOUR_SCREEN != 0,
+            battery: raw[6] & FLAG6_BATTERY != 0,
+            trainer: raw[6] & FLAG6_TRAINER != 0,
+            console_type: ConsoleType::Nes,
+            timing: TimingRegion::Ntsc,
+            expansion_device: 0,
+            misc_roms: 0,
+        };
+
+        match format {
+            HeaderFormat::INes => {
+                header.console_type = console_type(raw[7], 0);
+
+                //0 means 8K for compatibility
+                let prg_ram = INES_PRG_RAM_UNIT * (raw[8].max(1) as usize);
+                if header.battery {
+                    header.prg_nvram_size = prg_ram;
+                } else {
+                    header.prg_ram_size = prg_ram;
+                }
+                if raw[5] == 0 {
+                    header.chr_ram_size = 8192;
+                }
+                if raw[9] & 0x01 != 0 {
+                    header.timing = TimingRegion::Pal;
+                }
+            },
+            HeaderFormat::Nes2 => {
+                header.mapper |= ((raw[8] & 0x0f) as u16) << 8;
+                header.submapper = raw[8] >> 4;
+                header.prg_rom_size = rom_size(raw[4], raw[9] & 0x0f, PRG_ROM_UNIT);
+                header.chr_rom_size = rom_size(raw[5], raw[9] >> 4, CHR_ROM_UNIT);
+                header.prg_ram_size = ram_size(raw[10] & 0x0f);
+                header.prg_nvram_size = ram_size(raw[10] >> 4);
+                header.chr_ram_size = ram_size(raw[11] & 0x0f);
+                header.chr_nvram_size = ram_size(raw[11] >> 4);
+                header.console_type = console_type(raw[7], raw[13]);
+                header.timing = match raw[12] & 0x03 {
+                    0 => TimingRegion::Ntsc,
+                    1 => TimingRegion::Pal,
+                    2 => TimingRegion::MultiRegion,
+                    _ => TimingRegion::Dendy,
+                };
+                header.misc_roms = raw[14] & 0x03;
+                header.expansion_device = raw[15] & 0x3f;
+            },
+        }
+
+        Ok(header)
+    }
+}
+
+fn console_type(flags_7: u8, extended: u8) -> ConsoleType {
+    match flags_7 & FLAG7_CONSOLE_TYPE {
+        0 => ConsoleType::Nes,
+        1 => ConsoleType::VsSystem,
+        2 => ConsoleType::Playchoice10,
+        _ => ConsoleType::Extended(extended & 0x0f),
+    }
+}
+
+//an msb nibble of $f switches to exponent-multiplier notation: 2^E * (MM*2+1) with the lsb being EEEEEEMM
+fn rom_size(lsb: u8, msb: u8, unit: u64) -> u64 {
+    if msb == 0x0f {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as u64 * 2 + 1;
+        1u64.checked_shl(exponent).unwrap_or(u64::MAX).saturating_mul(multiplier)
+    } else {
+        ((msb as u64) << 8 | lsb as u64) * unit
+    }
+}
+
+//ram sizes are stored as a shift count, 0 means no ram
+fn ram_size(shift: u8) -> usize {
+    if shift == 0 { 0 } else { 64 << shift }
+}
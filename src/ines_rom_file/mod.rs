@@ -0,0 +1,256 @@
+use std::{fs::{File}, path::Path, io::{self, Read}, fmt};
+
+use log::{debug, warn};
+
+use crate::{mappers::{chr::ChrMemory, nrom::BaseMapperError, Cartridge}};
+use crate::mappers::{nrom, uxrom, cnrom, axrom};
+use crate::mappers::mmc1;
+use crate::mappers::mmc3;
+
+mod header;
+pub use header::{ConsoleType, Mirroring, RomHeader, TimingRegion};
+
+#[derive(Debug)]
+pub struct Rom {
+    pub header: RomHeader,
+    pub prg_rom: Vec<[u8; 16384]>,
+    pub chr_rom: Vec<[u8; 8192]>,
+    pub trainer: Option<[u8; TRAINER_SIZE]>,
+    //emulate bus conflicts on the discrete mapper boards, not every board has them
+    pub bus_conflicts: bool,
+}
+
+#[derive(Debug)]
+pub enum OpenRomError {
+    IOError(std::io::Error),
+    InvalidMagicConstant,
+    //the file is smaller than the header says
+    Truncated { expected: u64, actual: u64 },
+    UnsupportedConsoleType(ConsoleType),
+}
+
+impl fmt::Display for OpenRomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenRomError::IOError(e) => write!(f, "{}", e),
+            OpenRomError::InvalidMagicConstant => write!(f, "not an iNES rom file"),
+            OpenRomError::Truncated { expected, actual } => {
+                write!(f, "rom file is truncated, the header declares {} bytes but the file has {}", expected, actual)
+            },
+            OpenRomError::UnsupportedConsoleType(console_type) => write!(f, "{:?} roms are not supported", console_type),
+        }
+    }
+}
+
+impl From<std::io::Error> for OpenRomError {
+    fn from(v: std::io::Error) -> Self {
+        OpenRomError::IOError(v)
+    }
+}
+
+#[derive(Debug)]
+pub enum GetCpuMapperError {
+    UnimplementedMapper,
+    MapperError
+}
+
+impl fmt::Display for GetCpuMapperError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GetCpuMapperError::UnimplementedMapper => write!(f, "mapper is not supported"),
+            GetCpuMapperError::MapperError => write!(f, "the rom does not fit the mapper"),
+        }
+    }
+}
+
+impl From<BaseMapperError> for GetCpuMapperError {
+    fn from(_: BaseMapperError) -> Self {
+        GetCpuMapperError::MapperError
+    }
+}
+
+impl From<uxrom::UxromMapperError> for GetCpuMapperError {
+    fn from(_: uxrom::UxromMapperError) -> Self {
+        GetCpuMapperError::MapperError
+    }
+}
+
+impl From<cnrom::CnromMapperError> for GetCpuMapperError {
+    fn from(_: cnrom::CnromMapperError) -> Self {
+        GetCpuMapperError::MapperError
+    }
+}
+
+impl From<axrom::AxromMapperError> for GetCpuMapperError {
+    fn from(_: axrom::AxromMapperError) -> Self {
+        GetCpuMapperError::MapperError
+    }
+}
+
+impl From<mmc1::MMC1MapperError> for GetCpuMapperError {
+    fn from(_: mmc1::MMC1MapperError) -> Self {
+        GetCpuMapperError::MapperError
+    }
+}
+
+impl From<mmc3::MMC3MapperError> for GetCpuMapperError {
+    fn from(_: mmc3::MMC3MapperError) -> Self {
+        GetCpuMapperError::MapperError
+    }
+}
+
+const HEADER_SIZE: u64 = 16;
+const TRAINER_SIZE: usize = 512;
+
+//the playchoice 10 instruction rom and prom come after the chr rom
+const PLAYCHOICE_DATA_SIZE: u64 = 8192 + 32;
+
+//used when a board has no chr rom but the header doesn't give a chr ram size
+const DEFAULT_CHR_RAM_SIZE: usize = 8192;
+
+//the discrete boards that list bus conflicts as submapper 2
+const BUS_CONFLICT_SUBMAPPER: u8 = 2;
+
+impl Rom {
+    pub fn new<P: AsRef<Path>>(p: P) -> Result<Rom, OpenRomError> {
+        //format description: https://www.nesdev.org/wiki/INES
+        debug!("reading rom file {}", p.as_ref().to_str().unwrap());
+        let mut f =  File::open(p)?;
+        let file_size = f.metadata()?.len();
+
+        if file_size < HEADER_SIZE {
+            return Err(OpenRomError::Truncated { expected: HEADER_SIZE, actual: file_size });
+        }
+
+        let mut raw_header: [u8; 16]= [0; 16];
+        f.read_exact(&mut raw_header)?;
+
+        let header = RomHeader::parse(&raw_header)?;
+        debug!("{:?}", header);
+
+        if !matches!(header.console_type, ConsoleType::Nes | ConsoleType::Playchoice10) {
+            return Err(OpenRomError::UnsupportedConsoleType(header.console_type));
+        }
+
+        let trainer_size = if header.trainer { TRAINER_SIZE as u64 } else { 0 };
+        let expected = HEADER_SIZE
+            .saturating_add(trainer_size)
+            .saturating_add(header.prg_rom_size)
+            .saturating_add(header.chr_rom_size);
+        if file_size < expected {
+            return Err(OpenRomError::Truncated { expected, actual: file_size });
+        }
+
+        //misc roms have no size in the header, they take up the rest of the file
+        let has_extra_data = header.misc_roms > 0 || header.console_type == ConsoleType::Playchoice10;
+        //plenty of dumps in circulation have junk appended, it is not worth refusing them for it
+        if file_size > expected && !has_extra_data {
+            warn!("rom file has trailing data, the header declares {} bytes but the file has {}", expected, file_size);
+        }
+        if header.console_type == ConsoleType::Playchoice10 && file_size != expected + PLAYCHOICE_DATA_SIZE {
+            debug!("unexpected size of the playchoice data");
+        }
+
+        let trainer = if header.trainer {
+            let mut buffer = [0; TRAINER_SIZE];
+            f.read_exact(&mut buffer)?;
+            Some(buffer)
+        } else {
+            None
+        };
+
+        let prg_rom = read_pages(&mut f, header.prg_rom_size)?;
+        let chr_rom = read_pages(&mut f, header.chr_rom_size)?;
+
+        let bus_conflicts = matches!(header.mapper, 2 | 3 | 7) && header.submapper == BUS_CONFLICT_SUBMAPPER;
+
+        Ok(Rom {
+            header,
+            prg_rom,
+            chr_rom,
+            trainer,
+            bus_conflicts,
+        })
+    }
+
+    pub fn get_cpu_mapper(&self) -> Result<Box<dyn Cartridge>, GetCpuMapperError> {
+        let mut cartridge: Box<dyn Cartridge> = match self.header.mapper {
+            0 => {
+                Box::new(nrom::Nrom::new(&self.prg_rom, self.chr_memory(), self.nrom_mirroring())?)
+            }
+            1 => {
+                Box::new(mmc1::Mmc1::new(&self.prg_rom, self.chr_memory(), self.prg_ram_size())?)
+            },
+            2 => {
+                Box::new(uxrom::Uxrom::new(&self.prg_rom, self.chr_memory(), self.nrom_mirroring(), self.bus_conflicts)?)
+            },
+            3 => {
+                Box::new(cnrom::Cnrom::new(&self.prg_rom, self.chr_memory(), self.nrom_mirroring(), self.bus_conflicts)?)
+            },
+            4 => {
+                let m = match (self.header.four_screen, self.header.mirroring) {
+                    (true, _) => mmc3::Mirroring::Hardwired,
+                    (false, Mirroring::Horizontal) => mmc3::Mirroring::Horizontal,
+                    (false, Mirroring::Vertical) => mmc3::Mirroring::Vertical,
+                };
+                Box::new(mmc3::Mmc3::new(&self.prg_rom, self.chr_memory(), m)?)
+            },
+            7 => {
+                Box::new(axrom::Axrom::new(&self.prg_rom, self.chr_memory(), self.bus_conflicts)?)
+            },
+            _ => {
+                return Err(GetCpuMapperError::UnimplementedMapper);
+            }
+        };
+
+        if let Some(trainer) = &self.trainer {
+            if !cartridge.load_trainer(trainer) {
+                warn!("the mapper has no prg ram, ignoring the trainer");
+            }
+        }
+
+        Ok(cartridge)
+    }
+
+    //boards without chr rom have chr ram instead
+    fn chr_memory(&self) -> ChrMemory {
+        let ram_size = match self.header.chr_ram_size + self.header.chr_nvram_size {
+            0 => DEFAULT_CHR_RAM_SIZE,
+            size => size,
+        };
+        ChrMemory::new(&self.chr_rom, ram_size)
+    }
+
+    //volatile and battery backed prg ram share the $6000-$7fff window
+    fn prg_ram_size(&self) -> usize {
+        self.header.prg_ram_size + self.header.prg_nvram_size
+    }
+
+    fn nrom_mirroring(&self) -> nrom::Mirroring {
+        match self.header.mirroring {
+            Mirroring::Horizontal => nrom::Mirroring::Horizontal,
+            Mirroring::Vertical => nrom::Mirroring::Vertical,
+        }
+    }
+}
+
+//reads size bytes, padding the last page with zeros
+fn read_pages<const N: usize>(f: &mut File, size: u64) -> io::Result<Vec<[u8; N]>> {
+    let mut data = Vec::new();
+    f.take(size).read_to_end(&mut data)?;
+    if (data.len() as u64) < size {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(data
+        .chunks(N)
+        .map(|chunk| {
+            let mut page = [0; N];
+            page[..chunk.len()].copy_from_slice(chunk);
+            page
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests;
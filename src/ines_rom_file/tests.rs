@@ -0,0 +1,209 @@
+use std::{env, fs, path::PathBuf};
+
+use crate::{cpu::Cpu, memory_controller::MemoryPtr};
+
+use super::{header::HeaderFormat, ConsoleType, Mirroring, OpenRomError, Rom, RomHeader, TimingRegion};
+
+fn write_rom_file(name: &str, header: [u8; 16], prg_size: usize, chr_size: usize) -> PathBuf {
+    let path = env::temp_dir().join(name);
+    let mut data = header.to_vec();
+    data.resize(16 + prg_size + chr_size, 0);
+    fs::write(&path, data).unwrap();
+    path
+}
+
+#[test]
+fn test_ines_header() {
+    let header = RomHeader::parse(&[0x4e, 0x45, 0x53, 0x1a, 2, 1, 0x13, 0x40, 0, 1, 0, 0, 0, 0, 0, 0]).ok().unwrap();
+
+    assert_eq!(header.format, HeaderFormat::INes);
+    assert_eq!(header.mapper, 0x41);
+    assert_eq!(header.prg_rom_size, 32768);
+    assert_eq!(header.chr_rom_size, 8192);
+    assert_eq!(header.mirroring, Mirroring::Vertical);
+    assert!(header.battery);
+    assert!(!header.trainer);
+    assert_eq!(header.prg_ram_size, 0);
+    assert_eq!(header.prg_nvram_size, 8192);
+    assert_eq!(header.chr_ram_size, 0);
+    assert_eq!(header.timing, TimingRegion::Pal);
+    assert_eq!(header.console_type, ConsoleType::Nes);
+}
+
+#[test]
+fn test_ines_header_with_garbage() {
+    //"DiskDude!" in bytes 7-15
+    let header = RomHeader::parse(b"NES\x1a\x02\x01\x40DiskDude!").ok().unwrap();
+    assert_eq!(header.format, HeaderFormat::INes);
+    assert_eq!(header.mapper, 4);
+    //'i' and 's' in bytes 8 and 9 would mean 840K of prg ram and a pal rom
+    assert_eq!(header.prg_ram_size, 8192);
+    assert_eq!(header.timing, TimingRegion::Ntsc);
+    assert_eq!(header.console_type, ConsoleType::Nes);
+}
+
+#[test]
+fn test_nes2_header() {
+    let header = RomHeader::parse(&[
+        0x4e, 0x45, 0x53, 0x1a,
+        0x02, 0x00, //prg and chr rom size lsb
+        0x1a, 0x0a, //four screen, battery, mapper 1, nes 2.0, playchoice
+        0x21, //submapper 2, mapper 1xx
+        0x10, //prg rom size msb 0, chr rom size msb 1
+        0x70, //8K prg nvram
+        0x07, //8K chr ram
+        0x03, //dendy
+        0x00,
+        0x00,
+        0x23, //expansion device
+    ]).ok().unwrap();
+
+    assert_eq!(header.format, HeaderFormat::Nes2);
+    assert_eq!(header.mapper, 0x101);
+    assert_eq!(header.submapper, 2);
+    assert_eq!(header.prg_rom_size, 2 * 16384);
+    assert_eq!(header.chr_rom_size, 256 * 8192);
+    assert_eq!(header.prg_ram_size, 0);
+    assert_eq!(header.prg_nvram_size, 8192);
+    assert_eq!(header.chr_ram_size, 8192);
+    assert_eq!(header.chr_nvram_size, 0);
+    assert!(header.four_screen);
+    assert!(header.battery);
+    assert_eq!(header.console_type, ConsoleType::Playchoice10);
+    assert_eq!(header.timing, TimingRegion::Dendy);
+    assert_eq!(header.expansion_device, 0x23);
+}
+
+#[test]
+fn test_nes2_exponent_sizes() {
+    //prg: 2^14 * 3, chr: 2^10 * 1
+    let header = RomHeader::parse(&[0x4e, 0x45, 0x53, 0x1a, 0x39, 0x28, 0, 0x08, 0, 0xff, 0, 0, 0, 0x05, 0, 0]).ok().unwrap();
+
+    assert_eq!(header.prg_rom_size, 3 * 16384);
+    assert_eq!(header.chr_rom_size, 1024);
+    assert_eq!(header.console_type, ConsoleType::Nes);
+}
+
+#[test]
+fn test_invalid_magic() {
+    assert!(RomHeader::parse(&[0; 16]).is_err());
+}
+
+#[test]
+fn test_rom_without_chr_uses_ram() {
+    let path = write_rom_file(
+        "nesmu_test_chr_ram.nes",
+        [0x4e, 0x45, 0x53, 0x1a, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        16384,
+        0,
+    );
+    let rom = Rom::new(&path).ok().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(rom.header.chr_ram_size, 8192);
+
+    let mut mapper = rom.get_cpu_mapper().ok().unwrap();
+    mapper.ppu_write(0x1234, 0x56);
+    assert_eq!(mapper.ppu_read(0x1234), 0x56);
+}
+
+#[test]
+fn test_rom_nes2_sizes() {
+    //mapper 2 submapper 2 with 32K of chr ram and 3 * 16K of prg rom in exponent notation
+    let path = write_rom_file(
+        "nesmu_test_nes2_sizes.nes",
+        [0x4e, 0x45, 0x53, 0x1a, 0x39, 0, 0x20, 0x08, 0x20, 0x0f, 0, 0x09, 0, 0, 0, 0],
+        3 * 16384,
+        0,
+    );
+    let rom = Rom::new(&path).ok().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(rom.header.chr_ram_size, 32768);
+    assert_eq!(rom.prg_rom.len(), 3);
+    assert!(rom.bus_conflicts);
+    assert!(rom.get_cpu_mapper().is_ok());
+}
+
+fn open_rom_file(name: &str, header: [u8; 16], data: &[u8]) -> Result<Rom, OpenRomError> {
+    let path = env::temp_dir().join(name);
+    let mut file = header.to_vec();
+    file.extend_from_slice(data);
+    fs::write(&path, file).unwrap();
+    let result = Rom::new(&path);
+    fs::remove_file(&path).unwrap();
+    result
+}
+
+#[test]
+fn test_trainer() {
+    //mmc1 with a trainer, the prg rom starts with 1
+    let mut data = vec![0xaa; 512];
+    data.extend(vec![1; 2 * 16384]);
+    let rom = open_rom_file("nesmu_test_trainer.nes", [0x4e, 0x45, 0x53, 0x1a, 2, 0, 0x14, 0, 0, 0, 0, 0, 0, 0, 0, 0], &data)
+        .ok()
+        .unwrap();
+
+    assert_eq!(rom.trainer, Some([0xaa; 512]));
+    assert_eq!(rom.prg_rom[0][0], 1);
+
+    let mut mapper = rom.get_cpu_mapper().ok().unwrap();
+    let mut cpu = Cpu::new();
+    assert_eq!(mapper.read(MemoryPtr(0x6fff), &mut cpu), 0);
+    assert_eq!(mapper.read(MemoryPtr(0x7000), &mut cpu), 0xaa);
+    assert_eq!(mapper.read(MemoryPtr(0x71ff), &mut cpu), 0xaa);
+    assert_eq!(mapper.read(MemoryPtr(0x7200), &mut cpu), 0);
+
+    //nrom has prg ram too
+    let mut data = vec![0xbb; 512];
+    data.extend(vec![1; 16384 + 8192]);
+    let rom = open_rom_file("nesmu_test_nrom_trainer.nes", [0x4e, 0x45, 0x53, 0x1a, 1, 1, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0], &data)
+        .ok()
+        .unwrap();
+    let mut mapper = rom.get_cpu_mapper().ok().unwrap();
+    assert_eq!(mapper.read(MemoryPtr(0x7000), &mut cpu), 0xbb);
+    assert_eq!(mapper.read(MemoryPtr(0x71ff), &mut cpu), 0xbb);
+    assert_eq!(mapper.read(MemoryPtr(0x7200), &mut cpu), 0);
+}
+
+#[test]
+fn test_truncated_rom() {
+    let header = [0x4e, 0x45, 0x53, 0x1a, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    match open_rom_file("nesmu_test_truncated.nes", header, &[0; 32768]) {
+        Err(OpenRomError::Truncated { expected, actual }) => {
+            assert_eq!(expected, 16 + 32768 + 8192);
+            assert_eq!(actual, 16 + 32768);
+        },
+        _ => panic!("expected a truncated rom error"),
+    }
+
+    //not even a full header
+    let path = env::temp_dir().join("nesmu_test_truncated_header.nes");
+    fs::write(&path, &header[..8]).unwrap();
+    let result = Rom::new(&path);
+    fs::remove_file(&path).unwrap();
+    match result {
+        Err(OpenRomError::Truncated { expected, actual }) => assert_eq!((expected, actual), (16, 8)),
+        _ => panic!("expected a truncated rom error"),
+    }
+}
+
+#[test]
+fn test_trailing_data() {
+    //only logged, the rom still loads
+    let header = [0x4e, 0x45, 0x53, 0x1a, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let rom = open_rom_file("nesmu_test_trailing.nes", header, &[0; 16384 + 8192 + 128]).ok().unwrap();
+    assert_eq!(rom.prg_rom.len(), 1);
+
+    //nes 2.0 misc roms fill the rest of the file
+    let header = [0x4e, 0x45, 0x53, 0x1a, 1, 1, 0, 0x08, 0, 0, 0, 0, 0, 0, 1, 0];
+    assert!(open_rom_file("nesmu_test_misc_rom.nes", header, &[0; 16384 + 8192 + 128]).is_ok());
+}
+
+#[test]
+fn test_unsupported_console_type() {
+    let header = [0x4e, 0x45, 0x53, 0x1a, 1, 1, 0, 0x01, 0, 0, 0, 0, 0, 0, 0, 0];
+    match open_rom_file("nesmu_test_vs.nes", header, &[0; 16384 + 8192]) {
+        Err(OpenRomError::UnsupportedConsoleType(ConsoleType::VsSystem)) => {},
+        _ => panic!("expected an unsupported console type error"),
+    }
+}
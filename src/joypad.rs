@@ -1,4 +1,5 @@
 use crate::memory_controller::MemoryPtr;
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
 
 #[derive(Clone, Copy)]
 pub enum Button {
@@ -12,6 +13,13 @@ pub enum Button {
     RIGHT=7
 }
 
+//the controller ports on the front of the console
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Player {
+    One=0,
+    Two=1,
+}
+
 pub struct Joypad {
     strobe: bool,
     state: [bool; 8],
@@ -19,6 +27,12 @@ pub struct Joypad {
 }
 
 
+impl Default for Joypad {
+    fn default() -> Self {
+        Joypad::new()
+    }
+}
+
 impl Joypad {
     pub fn new() -> Joypad {
         Joypad { 
@@ -37,6 +51,10 @@ impl Joypad {
         if self.strobe {
             return if self.state[0] {1} else {0};
         }
+        //a standard controller reports 1 once all 8 buttons have been read
+        if self.current_button >= self.state.len() {
+            return 1;
+        }
         let result = if self.state[self.current_button] {1} else {0};
         self.current_button += 1;
         return result;
@@ -51,3 +69,24 @@ impl Joypad {
     }
 }
 
+impl SaveState for Joypad {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.strobe);
+        for pressed in self.state.iter() {
+            w.bool(*pressed);
+        }
+        w.u32(self.current_button as u32);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.strobe = r.bool()?;
+        for pressed in self.state.iter_mut() {
+            *pressed = r.bool()?;
+        }
+        self.current_button = r.u32()? as usize;
+        if self.current_button > self.state.len() {
+            return Err(StateError::InvalidData);
+        }
+        Ok(())
+    }
+}
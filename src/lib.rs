@@ -0,0 +1,464 @@
+mod apu;
+pub mod audio;
+pub mod battery;
+mod cpu;
+pub mod headless;
+pub mod ines_rom_file;
+pub mod joypad;
+pub mod mappers;
+mod memory_controller;
+mod ppu;
+pub mod rewind;
+pub mod savestate;
+
+use std::{cmp::Ordering, fmt, path::Path};
+
+use apu::Apu;
+use cpu::{Cpu, CpuContext, CpuMemory};
+use ines_rom_file::{GetCpuMapperError, OpenRomError, Rom};
+use joypad::Joypad;
+use mappers::{Cartridge, SystemMemoryMapper};
+use memory_controller::{MemoryPtr, Ram};
+use ppu::{PPUDrawingContext, PPU};
+use savestate::{SaveState, StateError, StateReader, StateWriter};
+
+pub use apu::DEFAULT_SAMPLE_RATE;
+pub use joypad::{Button, Player};
+pub use ppu::Renderer;
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+//ppu dots in one ntsc frame
+const FRAME_DOTS: u64 = 89342;
+
+#[derive(Debug)]
+pub enum LoadRomError {
+    Open(OpenRomError),
+    Mapper(u16, GetCpuMapperError),
+}
+
+impl fmt::Display for LoadRomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadRomError::Open(e) => write!(f, "{}", e),
+            LoadRomError::Mapper(mapper, e) => write!(f, "mapper {}: {}", mapper, e),
+        }
+    }
+}
+
+fn convert_components_to_pixel(components: (u8, u8, u8)) -> u32 {
+    return (u32::from(components.0) << 16)
+        | (u32::from(components.1) << 8)
+        | (u32::from(components.2));
+}
+
+const PALLETE: [(u8, u8, u8); 64] = [
+    (124, 124, 124),
+    (0, 0, 252),
+    (0, 0, 188),
+    (68, 40, 188),
+    (148, 0, 132),
+    (168, 0, 32),
+    (168, 16, 0),
+    (136, 20, 0),
+    (80, 48, 0),
+    (0, 120, 0),
+    (0, 104, 0),
+    (0, 88, 0),
+    (0, 64, 88),
+    (0, 0, 0),
+    (0, 0, 0),
+    (0, 0, 0),
+    (188, 188, 188),
+    (0, 120, 248),
+    (0, 88, 248),
+    (104, 68, 252),
+    (216, 0, 204),
+    (228, 0, 88),
+    (248, 56, 0),
+    (228, 92, 16),
+    (172, 124, 0),
+    (0, 184, 0),
+    (0, 168, 0),
+    (0, 168, 68),
+    (0, 136, 136),
+    (0, 0, 0),
+    (0, 0, 0),
+    (0, 0, 0),
+    (248, 248, 248),
+    (60, 188, 252),
+    (104, 136, 252),
+    (152, 120, 248),
+    (248, 120, 248),
+    (248, 88, 152),
+    (248, 120, 88),
+    (252, 160, 68),
+    (248, 184, 0),
+    (184, 248, 24),
+    (88, 216, 84),
+    (88, 248, 152),
+    (0, 232, 216),
+    (120, 120, 120),
+    (0, 0, 0),
+    (0, 0, 0),
+    (252, 252, 252),
+    (164, 228, 252),
+    (184, 184, 248),
+    (216, 184, 248),
+    (248, 184, 248),
+    (248, 164, 192),
+    (240, 208, 176),
+    (252, 224, 168),
+    (248, 216, 120),
+    (216, 248, 120),
+    (184, 248, 184),
+    (184, 248, 216),
+    (0, 252, 252),
+    (248, 216, 248),
+    (0, 0, 0),
+    (0, 0, 0),
+];
+
+//the whole console. frontends drive it a frame or an instruction at a time
+pub struct Nes {
+    pub(crate) ram: Ram,
+    pub(crate) cpu: Cpu,
+    pub(crate) ppu: PPU,
+    pub(crate) apu: Apu,
+    pub(crate) gamepads: [Joypad; 2],
+    pub(crate) cartridge: Box<dyn Cartridge>,
+    pub(crate) events: EventList,
+    pub(crate) framebuffer_nes: [u8; WIDTH*HEIGHT],
+
+    //cpu cycle the current frame started at, None between frames
+    frame_start: Option<u64>,
+}
+
+impl Nes {
+    pub fn new(game: Box<dyn Cartridge>) -> Nes {
+
+        let mut ret = Nes {
+            ram: Ram::new(),
+            cpu: Cpu::new(),
+            ppu: PPU::new(),
+            apu: Apu::new(DEFAULT_SAMPLE_RATE),
+            gamepads: [Joypad::new(), Joypad::new()],
+            cartridge: game,
+            events: EventList::new(),
+            framebuffer_nes: [0; WIDTH*HEIGHT],
+            frame_start: None,
+        };
+
+        ret.cpu_context().reset();
+        ret
+    }
+
+    pub fn load_rom<P: AsRef<Path>>(path: P) -> Result<Nes, LoadRomError> {
+        let rom = Rom::new(path).map_err(LoadRomError::Open)?;
+        Nes::from_rom(&rom)
+    }
+
+    pub fn from_rom(rom: &Rom) -> Result<Nes, LoadRomError> {
+        match rom.get_cpu_mapper() {
+            Ok(cartridge) => Ok(Nes::new(cartridge)),
+            Err(e) => Err(LoadRomError::Mapper(rom.header.mapper, e)),
+        }
+    }
+
+    //the reset button: ram and the cartridge keep their contents
+    pub fn reset(&mut self) {
+        self.events.clear();
+        self.frame_start = None;
+        self.ppu.reset();
+        self.apu.reset(&mut self.cpu);
+        self.cpu_context().soft_reset();
+    }
+
+    fn ppu_drawing_context(&mut self) -> PPUDrawingContext<'_> {
+        self.ppu.drawing_context(self.cartridge.as_mut().get_ppu_memory(), &mut self.events, &mut self.framebuffer_nes)
+    }
+
+    pub(crate) fn cpu_context(&mut self) -> CpuContext<'_, SystemMemoryMapper<'_>> {
+        self.cpu.context(SystemMemoryMapper::new(
+            &mut self.ram,
+            self.cartridge.as_mut(),
+            &mut self.ppu,
+            &mut self.apu,
+            &mut self.gamepads,
+            &mut self.events,
+            &mut self.framebuffer_nes,
+            self.frame_start,
+        ))
+    }
+
+    //runs the apu, ppu and mapper up to the end of an instruction, whose last cycles don't always touch the bus
+    fn catch_up(&mut self) {
+        let mut memory = SystemMemoryMapper::new(
+            &mut self.ram,
+            self.cartridge.as_mut(),
+            &mut self.ppu,
+            &mut self.apu,
+            &mut self.gamepads,
+            &mut self.events,
+            &mut self.framebuffer_nes,
+            self.frame_start,
+        );
+        memory.catch_up_apu(&mut self.cpu);
+        memory.catch_up(&mut self.cpu);
+    }
+
+    //runs until the end of the current frame
+    pub fn run_frame(&mut self) {
+        while !self.step_instruction() {}
+    }
+
+    //executes one cpu instruction, returns true if it finished a frame
+    pub fn step_instruction(&mut self) -> bool {
+        let start_of_frame_cycle = match self.frame_start {
+            Some(cycle) => cycle,
+            None => self.start_frame(),
+        };
+
+        //the memory mapper runs the apu, ppu and mapper up to every bus access of the instruction
+        self.cpu_context().execute_next_instruction();
+        self.catch_up();
+
+        if 3 * (self.cpu.cycle_count - start_of_frame_cycle) < FRAME_DOTS {
+            return false;
+        }
+
+        self.frame_start = None;
+        self.events.clear();
+        self.apu.end_frame();
+        true
+    }
+
+    fn start_frame(&mut self) -> u64 {
+        let start_of_frame_cycle = self.cpu.cycle_count;
+        self.frame_start = Some(start_of_frame_cycle);
+        self.ppu_drawing_context().set_vblank_flag(start_of_frame_cycle);
+        self.cartridge.start_of_frame(&mut self.events, self.cpu.cycle_count);
+
+        if self.ppu.nmi_active() {
+            self.cpu.nmi_pending = true;
+        }
+        start_of_frame_cycle
+    }
+
+    pub fn set_button(&mut self, player: Player, button: Button, pressed: bool) {
+        self.gamepads[player as usize].set_state(button, pressed);
+    }
+
+    //the last finished frame as indices into the nes palette
+    pub fn framebuffer(&self) -> &[u8; WIDTH*HEIGHT] {
+        &self.framebuffer_nes
+    }
+
+    //the last finished frame as 0x00rrggbb pixels
+    pub fn framebuffer_rgb(&self) -> Vec<u32> {
+        self.framebuffer_nes.iter()
+            .map(|pixel| convert_components_to_pixel(PALLETE[*pixel as usize]))
+            .collect()
+    }
+
+    //samples produced by the last finished frame
+    pub fn audio_samples(&self) -> &[i16] {
+        self.apu.samples()
+    }
+
+    //reads ram or cartridge memory as the cpu would see it, other addresses read as 0
+    //since reading the ppu and apu registers has side effects
+    pub fn peek(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1fff => self.ram.read(MemoryPtr(addr), &mut self.cpu),
+            0x4020..=0xffff => self.cartridge.read(MemoryPtr(addr), &mut self.cpu),
+            _ => 0,
+        }
+    }
+
+    //a frame in progress is finished with the renderer it was started with
+    pub fn set_renderer(&mut self, renderer: Renderer) {
+        self.ppu.renderer = renderer;
+    }
+
+    pub fn renderer(&self) -> Renderer {
+        self.ppu.renderer
+    }
+
+    //an enhancement that shows every sprite instead of only 8 per line, which removes the flicker
+    //of many games but also shows objects that games hide on purpose
+    pub fn set_unlimited_sprites(&mut self, enabled: bool) {
+        self.ppu.unlimited_sprites = enabled;
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.apu.sample_rate()
+    }
+
+    //drops any audio of the current frame
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.apu.set_sample_rate(sample_rate);
+    }
+
+    //slightly speeds up or slows down the audio, see audio::rate_adjustment
+    pub fn set_audio_rate_adjustment(&mut self, ratio: f64) {
+        self.apu.set_rate_adjustment(ratio);
+    }
+
+    pub fn cartridge(&self) -> &dyn Cartridge {
+        self.cartridge.as_ref()
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut dyn Cartridge {
+        self.cartridge.as_mut()
+    }
+
+    //snapshot of the whole console
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        self.cpu.save_state(&mut w);
+        self.ram.save_state(&mut w);
+        self.ppu.save_state(&mut w);
+        self.apu.save_state(&mut w);
+        for gamepad in self.gamepads.iter() {
+            gamepad.save_state(&mut w);
+        }
+        self.events.save_state(&mut w);
+        self.cartridge.save_state(&mut w);
+        w.bytes(&self.framebuffer_nes);
+        w.bool(self.frame_start.is_some());
+        w.u64(self.frame_start.unwrap_or(0));
+        w.into_inner()
+    }
+
+    //the console is left untouched if the state can't be loaded
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data)?;
+        let backup = self.save_state();
+
+        let result = self.load_components(&mut r);
+        if result.is_err() {
+            self.load_components(&mut StateReader::new(&backup)?)?;
+        }
+        result
+    }
+
+    fn load_components(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.cpu.load_state(r)?;
+        self.ram.load_state(r)?;
+        self.ppu.load_state(r)?;
+        self.apu.load_state(r)?;
+        for gamepad in self.gamepads.iter_mut() {
+            gamepad.load_state(r)?;
+        }
+        self.events.load_state(r)?;
+        self.cartridge.load_state(r)?;
+        r.bytes(&mut self.framebuffer_nes)?;
+        let in_frame = r.bool()?;
+        let frame_start = r.u64()?;
+        self.frame_start = in_frame.then_some(frame_start);
+        if !r.is_empty() {
+            return Err(StateError::InvalidData);
+        }
+        Ok(())
+    }
+}
+
+
+pub struct EventList {
+    next_events: Vec<FutureEvent>
+}
+
+impl EventList {
+    fn new() -> EventList {
+        EventList { next_events: Vec::new() }
+    }
+    fn add_event(&mut self, e: FutureEvent) {
+        self.next_events.push(e);
+
+        self.next_events.sort_by(|b, a| {
+            if a.cycle < b.cycle {
+                Ordering::Less
+            } else if a.cycle > b.cycle {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+
+        });
+    }
+
+    fn pop_next_event(&mut self, cyc: u64) -> Option<FutureEvent> {
+        let item = match self.next_events.last() {
+            Some(x) => x,
+            _ => {
+                return None;
+            }
+        };
+
+        if item.cycle <= cyc {
+            return self.next_events.pop();
+        }
+
+        None
+    }
+
+    fn clear(&mut self) {
+        self.next_events.clear();
+    }
+}
+
+impl SaveState for EventList {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u32(self.next_events.len() as u32);
+        for e in self.next_events.iter() {
+            w.u64(e.cycle);
+            match e.tp {
+                FutureEventType::PPU(id) => {
+                    w.u8(0);
+                    w.u32(id);
+                },
+                FutureEventType::Cartridge(id) => {
+                    w.u8(1);
+                    w.u32(id);
+                },
+            }
+        }
+    }
+
+    //events are stored already sorted, so they are restored as they are
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.next_events.clear();
+        for _ in 0..r.u32()? {
+            let cycle = r.u64()?;
+            let tp = match r.u8()? {
+                0 => FutureEventType::PPU(r.u32()?),
+                1 => FutureEventType::Cartridge(r.u32()?),
+                _ => return Err(StateError::InvalidData),
+            };
+            self.next_events.push(FutureEvent { cycle, tp });
+        }
+        Ok(())
+    }
+}
+
+
+
+struct FutureEvent {
+    cycle: u64,
+    tp: FutureEventType
+}
+
+
+enum FutureEventType {
+    PPU(u32),
+    Cartridge(u32),
+}
+
+#[cfg(test)]
+mod nestest;
+#[cfg(test)]
+mod test_roms;
+#[cfg(test)]
+mod tests;
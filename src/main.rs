@@ -1,34 +1,24 @@
 extern crate minifb;
 
-mod cpu;
-mod ines_rom_file;
-mod joypad;
-mod mappers;
-mod memory_controller;
-use joypad::Button;
-use mappers::Cartridge;
-use ppu::{PPUDrawingContext};
-
-use std::{
-    cmp::Ordering, env,
-};
+use std::{env, io::{Seek, Write}};
 
-use cpu::Cpu;
 use env_logger::{Builder, Target};
-use memory_controller::Ram;
-use minifb::{Key, Window, WindowOptions};
-
-use crate::{
-    cpu::{CpuContext},
-    joypad::Joypad,
-    mappers::SystemMemoryMapper,
-    ppu::PPU,
+use minifb::{Key, KeyRepeat, Window, WindowOptions};
+use nesmu::{
+    audio::{self, wav::WavWriter, AudioSink, FrameLimiter},
+    battery::BatterySave,
+    ines_rom_file::{self, TimingRegion},
+    rewind::RewindBuffer,
+    savestate, Button, Nes, Player, Renderer, HEIGHT, WIDTH,
 };
 
-const WIDTH: usize = 256;
-const HEIGHT: usize = 240;
+//flush battery backed ram about every 5 seconds
+const SAVE_INTERVAL_FRAMES: u64 = 300;
 
-mod ppu;
+//a snapshot every other frame, 20 seconds of rewind
+const REWIND_INTERVAL_FRAMES: u32 = 2;
+const REWIND_SNAPSHOTS: usize = 600;
+const REWIND_KEY: Key = Key::R;
 
 const KEY_CONFIG: [(Key, Button); 8] = [
     (Key::A, Button::A),
@@ -41,6 +31,12 @@ const KEY_CONFIG: [(Key, Button); 8] = [
     (Key::Right, Button::RIGHT),
 ];
 
+//F1-F10 load a save state slot, with shift held they save it
+const SLOT_KEYS: [Key; 10] = [
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5,
+    Key::F6, Key::F7, Key::F8, Key::F9, Key::F10,
+];
+
 fn main() {
     let mut builder = Builder::new();
     builder.target(Target::Stdout);
@@ -51,16 +47,74 @@ fn main() {
     println!("{:?}", args);
 
     if args.len() <= 1 {
-        println!("Usage: {:} <rom filename>", args[0]);
+        println!("Usage: {:} <rom filename> [--audio=device|null|wav:<filename>] [--record-audio <filename>] [--bus-conflicts] [--dot-ppu] [--no-sprite-limit]", args[0]);
         println!("mmc3 is partially supported");
         return;
     }
 
-    let x = ines_rom_file::Rom::new(args[1].clone()).unwrap();
+    let mut audio_option = "device";
+    let mut bus_conflicts = false;
+    let mut dot_ppu = false;
+    let mut unlimited_sprites = false;
+    let mut record_audio = None;
+    let mut options = args[2..].iter();
+    while let Some(arg) = options.next() {
+        match arg.as_str() {
+            "--record-audio" => record_audio = options.next(),
+            "--bus-conflicts" => bus_conflicts = true,
+            "--dot-ppu" => dot_ppu = true,
+            "--no-sprite-limit" => unlimited_sprites = true,
+            _ => match arg.strip_prefix("--audio=") {
+                Some(v) => audio_option = v,
+                None => println!("unknown option {:}", arg),
+            },
+        }
+    }
 
-    let k = x.get_cpu_mapper().unwrap();
+    let mut x = match ines_rom_file::Rom::new(args[1].clone()) {
+        Ok(x) => x,
+        Err(e) => {
+            println!("could not open {:}: {:}", args[1], e);
+            std::process::exit(1);
+        }
+    };
+    x.bus_conflicts |= bus_conflicts;
 
-    let mut console = Nes::new(k);
+    match x.header.timing {
+        TimingRegion::Ntsc | TimingRegion::MultiRegion => {},
+        region => println!("{:?} timing is not supported, running with NTSC timing", region),
+    }
+
+    let mut console = match Nes::from_rom(&x) {
+        Ok(console) => console,
+        Err(e) => {
+            println!("could not load {:}: {:}", args[1], e);
+            std::process::exit(1);
+        }
+    };
+    if dot_ppu {
+        console.set_renderer(Renderer::Dot);
+    }
+    console.set_unlimited_sprites(unlimited_sprites);
+
+    let mut battery = x.header.battery.then(|| BatterySave::new(&args[1]));
+    if let Some(battery) = battery.as_mut() {
+        if let Err(e) = battery.load(console.cartridge_mut()) {
+            println!("could not load {:}: {:}", battery.path().display(), e);
+        }
+    }
+
+    let mut sink = open_audio_sink(audio_option);
+    let mut limiter = FrameLimiter::new();
+    console.set_sample_rate(sink.sample_rate());
+
+    let mut recorder = record_audio.and_then(|filename| match WavWriter::create(filename, sink.sample_rate()) {
+        Ok(recorder) => Some(recorder),
+        Err(e) => {
+            println!("could not create {:}: {:}, audio is not recorded", filename, e);
+            None
+        }
+    });
 
     let mut window = Window::new(
         "Nes Emulator",
@@ -72,223 +126,126 @@ fn main() {
         panic!("{}", e);
     });
 
-    // Limit to max ~60 fps update rate
-    window.limit_update_rate(Some(std::time::Duration::from_micros(16600)));
+    // frame pacing is done by the audio sink or the frame limiter
+    window.limit_update_rate(None);
 
+    let mut rewind = RewindBuffer::new(REWIND_INTERVAL_FRAMES, REWIND_SNAPSHOTS);
+    //the snapshot being rewound through and how many frames after it are still shown
+    let mut rewind_base: Option<Vec<u8>> = None;
+    let mut rewind_frames = 0;
+    let mut frame_count: u64 = 0;
     while window.is_open() && !window.is_key_down(Key::Escape) {
         for v in KEY_CONFIG.iter() {
-            console.gamepad.set_state(v.1, window.is_key_down(v.0));
+            console.set_button(Player::One, v.1, window.is_key_down(v.0));
         }
 
-        let frame =console.frame();
-        // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
-        window.update_with_buffer(&frame, WIDTH, HEIGHT).unwrap();
-    }
-}
-
-fn convert_components_to_pixel(components: (u8, u8, u8)) -> u32 {
-    return (u32::from(components.0) << 16)
-        | (u32::from(components.1) << 8)
-        | (u32::from(components.2));
-}
-
-const PALLETE: [(u8, u8, u8); 64] = [
-    (124, 124, 124),
-    (0, 0, 252),
-    (0, 0, 188),
-    (68, 40, 188),
-    (148, 0, 132),
-    (168, 0, 32),
-    (168, 16, 0),
-    (136, 20, 0),
-    (80, 48, 0),
-    (0, 120, 0),
-    (0, 104, 0),
-    (0, 88, 0),
-    (0, 64, 88),
-    (0, 0, 0),
-    (0, 0, 0),
-    (0, 0, 0),
-    (188, 188, 188),
-    (0, 120, 248),
-    (0, 88, 248),
-    (104, 68, 252),
-    (216, 0, 204),
-    (228, 0, 88),
-    (248, 56, 0),
-    (228, 92, 16),
-    (172, 124, 0),
-    (0, 184, 0),
-    (0, 168, 0),
-    (0, 168, 68),
-    (0, 136, 136),
-    (0, 0, 0),
-    (0, 0, 0),
-    (0, 0, 0),
-    (248, 248, 248),
-    (60, 188, 252),
-    (104, 136, 252),
-    (152, 120, 248),
-    (248, 120, 248),
-    (248, 88, 152),
-    (248, 120, 88),
-    (252, 160, 68),
-    (248, 184, 0),
-    (184, 248, 24),
-    (88, 216, 84),
-    (88, 248, 152),
-    (0, 232, 216),
-    (120, 120, 120),
-    (0, 0, 0),
-    (0, 0, 0),
-    (252, 252, 252),
-    (164, 228, 252),
-    (184, 184, 248),
-    (216, 184, 248),
-    (248, 184, 248),
-    (248, 164, 192),
-    (240, 208, 176),
-    (252, 224, 168),
-    (248, 216, 120),
-    (216, 248, 120),
-    (184, 248, 184),
-    (184, 248, 216),
-    (0, 252, 252),
-    (248, 216, 248),
-    (0, 0, 0),
-    (0, 0, 0),
-];
-
-struct Nes {
-    pub ram: Ram,
-    pub cpu: Cpu,
-    pub ppu: PPU,
-    pub gamepad: Joypad,
-    pub cartridge: Box<dyn Cartridge>,
-    pub events: EventList,
-    pub framebuffer_nes: [u8; 240*256]
-}
-
-impl Nes {
-    fn new(game: Box<dyn Cartridge>) -> Nes {
-
-        let mut ret = Nes {
-            ram: Ram::new(),
-            cpu: Cpu::new(),
-            ppu: PPU::new(),
-            gamepad: Joypad::new(),
-            cartridge: game,
-            events: EventList::new(),
-            framebuffer_nes: [0; 240*256]
-        };
-
-        ret.cpu_context().reset();
-        ret
-    }
-
-    fn ppu_drawing_context(&mut self) -> PPUDrawingContext {
-        self.ppu.drawing_context(self.cartridge.as_mut().get_ppu_memory(), &mut self.events, &mut self.framebuffer_nes)
-    }
+        let shift = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+        for (i, key) in SLOT_KEYS.iter().enumerate() {
+            if window.is_key_pressed(*key, KeyRepeat::No) {
+                handle_slot_key(&mut console, &args[1], i as u8 + 1, shift);
+            }
+        }
 
-    fn cpu_context<'a>(&'a mut self) -> CpuContext<'a, SystemMemoryMapper> {
-        self.cpu.context(SystemMemoryMapper::new(&mut self.ram, self.cartridge.as_mut(), &mut self.ppu, &mut self.gamepad))
-    }
+        //while rewinding every displayed frame steps back by one frame, with no sound. snapshots are
+        //only taken every few frames, so the frames in between are replayed from the older snapshot
+        if window.is_key_down(REWIND_KEY) {
+            if rewind_frames == 0 {
+                if let Some(state) = rewind.pop() {
+                    rewind_base = Some(state);
+                    rewind_frames = REWIND_INTERVAL_FRAMES;
+                }
+            }
+            if let Some(state) = rewind_base.as_ref() {
+                rewind_frames = rewind_frames.saturating_sub(1);
+                match console.load_state(state) {
+                    Ok(()) => (0..rewind_frames).for_each(|_| console.run_frame()),
+                    Err(e) => println!("could not rewind: {:}", e),
+                }
+            }
+            limiter.wait();
+            window.update_with_buffer(&console.framebuffer_rgb(), WIDTH, HEIGHT).unwrap();
+            continue;
+        }
+        rewind_base = None;
+        rewind_frames = 0;
 
-    fn frame(&mut self) -> [u32; 240*256] {
-        let start_of_frame_cycle = self.cpu.cycle_count;
-        self.ppu_drawing_context().set_vblank_flag(start_of_frame_cycle);
-        self.cartridge.start_of_frame(&mut self.events, self.cpu.cycle_count);
+        rewind.frame(|| console.save_state());
+        console.run_frame();
 
-        if self.ppu.nmi_active() {
-            self.cpu_context().nmi();
+        sink.push_samples(console.audio_samples());
+        if let Some(Err(e)) = recorder.as_mut().map(|r| r.write_samples(console.audio_samples())) {
+            println!("could not record audio: {:}, recording stopped", e);
+            finish_recording(recorder.take());
         }
-
-        //execute until the end of frame
-        while 3 * (self.cpu.cycle_count - start_of_frame_cycle) < 89342 {
-            while let Some(x) = self.events.pop_next_event(3 * (self.cpu.cycle_count - start_of_frame_cycle)) {
-                match x.tp {
-                    FutureEventType::PPU(event_id) => {
-                        let cyc = self.cpu.cycle_count;
-                        self.ppu_drawing_context().handle_event(event_id, cyc);
-                    },
-                    FutureEventType::Cartridge(event_id) => {
-                        self.cartridge.on_event(&mut self.cpu, event_id, &mut self.ppu);
-                    },
-                };
-            };
-
-            self.cpu_context().execute_next_instruction().unwrap();
+        match sink.buffer_level() {
+            Some(level) => console.set_audio_rate_adjustment(audio::rate_adjustment(level)),
+            None => limiter.wait(),
         }
 
-        self.events.clear();
-        let mut buffer: [u32; 240*256] = [0; 240*256];
+        // We unwrap here as we want this code to exit if it fails. Real applications may want to handle this in a different way
+        window.update_with_buffer(&console.framebuffer_rgb(), WIDTH, HEIGHT).unwrap();
 
-        for (i, pixel) in self.framebuffer_nes.iter().enumerate() {
-            buffer[i] = convert_components_to_pixel(PALLETE[(*pixel & 0x3f) as usize]);
+        frame_count += 1;
+        if frame_count.is_multiple_of(SAVE_INTERVAL_FRAMES) {
+            flush_battery(&mut battery, &console);
         }
-
-        buffer
     }
-    
 
+    flush_battery(&mut battery, &console);
+    finish_recording(recorder);
 }
 
-
-pub struct EventList {
-    next_events: Vec<FutureEvent>
-}
-
-impl EventList {
-    fn new() -> EventList {
-        EventList { next_events: Vec::new() }
+//writes the final sizes into the wav header, so whatever was recorded stays playable
+fn finish_recording<W: Write + Seek>(recorder: Option<WavWriter<W>>) {
+    if let Some(Err(e)) = recorder.map(|r| r.finish()) {
+        println!("could not finish the audio recording: {:}", e);
     }
-    fn add_event(&mut self, e: FutureEvent) {
-        self.next_events.push(e);
-
-        self.next_events.sort_by(|b, a| {
-            if a.cycle < b.cycle {
-                Ordering::Less
-            } else if a.cycle > b.cycle {
-                Ordering::Greater
-            } else {
-                Ordering::Equal
-            }
+}
 
-        });
+fn flush_battery(battery: &mut Option<BatterySave>, console: &Nes) {
+    if let Some(battery) = battery.as_mut() {
+        if let Err(e) = battery.flush(console.cartridge()) {
+            println!("could not write {:}: {:}", battery.path().display(), e);
+        }
     }
+}
 
-    fn pop_next_event(&mut self, cyc: u64) -> Option<FutureEvent> {
-        let item = match self.next_events.last() {
-            Some(x) => x,
-            _ => {
-                return None;
-            }
-        };
-
-        if item.cycle <= cyc {
-            return self.next_events.pop();
+fn handle_slot_key(console: &mut Nes, rom_path: &str, slot: u8, save: bool) {
+    let path = savestate::slot_path(rom_path, slot);
+    if save {
+        match std::fs::write(&path, console.save_state()) {
+            Ok(()) => println!("saved state to {:}", path.display()),
+            Err(e) => println!("could not write {:}: {:}", path.display(), e),
         }
-
-        None
+        return;
     }
 
-    fn clear(&mut self) {
-        self.next_events.clear();
+    match std::fs::read(&path) {
+        Ok(data) => match console.load_state(&data) {
+            Ok(()) => println!("loaded state from {:}", path.display()),
+            Err(e) => println!("could not load {:}: {:}", path.display(), e),
+        },
+        Err(e) => println!("could not read {:}: {:}", path.display(), e),
     }
 }
 
+fn open_audio_sink(option: &str) -> Box<dyn AudioSink> {
+    if let Some(filename) = option.strip_prefix("wav:") {
+        match audio::wav::WavSink::create(filename, nesmu::DEFAULT_SAMPLE_RATE) {
+            Ok(sink) => return Box::new(sink),
+            Err(e) => println!("could not create {:}: {:}", filename, e),
+        }
+    } else if option == "device" {
+        #[cfg(feature = "audio-device")]
+        match audio::device::DeviceSink::new() {
+            Ok(sink) => return Box::new(sink),
+            Err(e) => println!("could not open audio device: {:}", e),
+        }
+        #[cfg(not(feature = "audio-device"))]
+        println!("built without the audio-device feature, audio is disabled");
+    } else if option != "null" {
+        println!("unknown audio output {:}", option);
+    }
 
-
-struct FutureEvent {
-    cycle: u64,
-    tp: FutureEventType
-}
-
-
-enum FutureEventType {
-    PPU(u32),
-    Cartridge(u32),
+    Box::new(audio::null::NullSink::new(nesmu::DEFAULT_SAMPLE_RATE))
 }
-
-#[cfg(test)]
-mod nestest;
@@ -0,0 +1,117 @@
+use crate::{cpu::{CpuMemory, Cpu}, memory_controller::MemoryPtr, ppu::{PPUMemorySpace, PPU}, EventList};
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+use super::{chr::ChrMemory, Cartridge};
+
+const BANK_SELECT_PRG: u8 = 0x07;
+const BANK_SELECT_NAMETABLE: u8 = 0x10;
+
+//mapper 7: switchable 32K prg bank and one-screen mirroring selected by the same register
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    nametables: [[u8; 0x400]; 2],
+    bus_conflicts: bool,
+    bank_select: u8,
+}
+
+#[derive(Debug)]
+pub enum AxromMapperError {
+    NoPrgRomPages,
+}
+
+impl Axrom {
+    pub fn new(
+        prg_rom: &[[u8; 16384]],
+        chr: ChrMemory,
+        bus_conflicts: bool,
+    ) -> Result<Axrom, AxromMapperError> {
+        if prg_rom.is_empty() {
+            return Err(AxromMapperError::NoPrgRomPages);
+        }
+
+        Ok(Axrom {
+            prg_rom: prg_rom.concat(),
+            chr,
+            nametables: [[0; 0x400]; 2],
+            bus_conflicts,
+            bank_select: 0,
+        })
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        ((self.bank_select & BANK_SELECT_PRG) as usize * 0x8000 + (addr as usize & 0x7fff)) % self.prg_rom.len()
+    }
+
+    fn nametable(&self) -> usize {
+        if self.bank_select & BANK_SELECT_NAMETABLE != 0 { 1 } else { 0 }
+    }
+}
+
+impl Cartridge for Axrom {
+    fn get_ppu_memory(&mut self) -> &mut dyn PPUMemorySpace {
+        self
+    }
+    fn on_event(&mut self, _: &mut Cpu, _: u32, _: &mut PPU) {
+
+    }
+    fn start_of_frame(&mut self, _: &mut EventList, _: u64) {
+
+    }
+}
+
+impl CpuMemory for Axrom {
+    fn read(&mut self, addr: MemoryPtr, _: &mut Cpu) -> u8 {
+        if addr.0 < 0x8000 {
+            return 0;
+        }
+        self.prg_rom[self.prg_offset(addr.0)]
+    }
+    fn write(&mut self, addr: MemoryPtr, v: u8, _: &mut Cpu) {
+        if addr.0 < 0x8000 {
+            return;
+        }
+        self.bank_select = if self.bus_conflicts { v & self.prg_rom[self.prg_offset(addr.0)] } else { v };
+    }
+}
+
+impl PPUMemorySpace for Axrom {
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            return self.chr.read(addr as usize);
+        }
+
+        self.nametables[self.nametable()][(addr & 0x3ff) as usize]
+    }
+    fn ppu_write(&mut self, addr: u16, v: u8) {
+        if addr < 0x2000 {
+            self.chr.write(addr as usize, v);
+            return;
+        }
+        if addr >= 0x3000 {
+            return;
+        }
+
+        let table = self.nametable();
+        self.nametables[table][(addr & 0x3ff) as usize] = v;
+    }
+}
+
+impl SaveState for Axrom {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.chr.save_state(w);
+        for table in self.nametables.iter() {
+            w.bytes(table);
+        }
+        w.u8(self.bank_select);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(r)?;
+        for table in self.nametables.iter_mut() {
+            r.bytes(table)?;
+        }
+        self.bank_select = r.u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+//pattern table storage of a cartridge. boards without chr rom have ram in its place
+pub struct ChrMemory {
+    data: Vec<u8>,
+    writable: bool,
+}
+
+impl ChrMemory {
+    pub fn rom(pages: &[[u8; 8192]]) -> ChrMemory {
+        ChrMemory {
+            data: pages.concat(),
+            writable: false,
+        }
+    }
+
+    pub fn ram(size: usize) -> ChrMemory {
+        ChrMemory {
+            data: vec![0; size],
+            writable: true,
+        }
+    }
+
+    //uses ram of the given size when the rom has no chr pages
+    pub fn new(pages: &[[u8; 8192]], ram_size: usize) -> ChrMemory {
+        if pages.is_empty() {
+            ChrMemory::ram(ram_size)
+        } else {
+            ChrMemory::rom(pages)
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn is_ram(&self) -> bool {
+        self.writable
+    }
+
+    //offsets past the end wrap around, like the unconnected high address lines of smaller chips
+    pub fn read(&self, offset: usize) -> u8 {
+        self.data[offset % self.data.len()]
+    }
+
+    pub fn write(&mut self, offset: usize, v: u8) {
+        if self.writable {
+            let len = self.data.len();
+            self.data[offset % len] = v;
+        }
+    }
+}
+
+//rom contents come from the cartridge file, so only ram is part of a save state
+impl SaveState for ChrMemory {
+    fn save_state(&self, w: &mut StateWriter) {
+        if self.writable {
+            w.vec(&self.data);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        if self.writable {
+            r.vec_exact(&mut self.data)?;
+        }
+        Ok(())
+    }
+}
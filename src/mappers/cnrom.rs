@@ -0,0 +1,128 @@
+use crate::{cpu::{CpuMemory, Cpu}, memory_controller::MemoryPtr, ppu::{PPUMemorySpace, PPU}, EventList};
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+use super::{chr::ChrMemory, nrom::Mirroring, Cartridge};
+
+//mapper 3: fixed 16K or 32K of prg rom and a switchable 8K chr bank
+pub struct Cnrom {
+    prg_rom: [u8; 32768],
+    chr: ChrMemory,
+    nametables: [[u8; 0x400]; 2],
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    chr_bank: u8,
+}
+
+#[derive(Debug)]
+pub enum CnromMapperError {
+    NoPrgRomPages,
+    TooManyPrgRomPages,
+}
+
+impl Cnrom {
+    pub fn new(
+        prg_rom: &[[u8; 16384]],
+        chr: ChrMemory,
+        mirroring: Mirroring,
+        bus_conflicts: bool,
+    ) -> Result<Cnrom, CnromMapperError> {
+        if prg_rom.is_empty() {
+            return Err(CnromMapperError::NoPrgRomPages);
+        }
+        if prg_rom.len() > 2 {
+            return Err(CnromMapperError::TooManyPrgRomPages);
+        }
+
+        let mut result_prg_rom: [u8; 32768] = [0; 32768];
+        for i in 0..2 {
+            result_prg_rom[(i * 16384)..((i + 1) * 16384)]
+                .copy_from_slice(&prg_rom[i % prg_rom.len()]);
+        }
+
+        Ok(Cnrom {
+            prg_rom: result_prg_rom,
+            chr,
+            nametables: [[0; 0x400]; 2],
+            mirroring,
+            bus_conflicts,
+            chr_bank: 0,
+        })
+    }
+
+    fn nametable_index(&self, addr: u16) -> (usize, usize) {
+        let table = match self.mirroring {
+            Mirroring::Vertical => (addr >> 10) & 0x1,
+            Mirroring::Horizontal => (addr >> 11) & 0x1,
+        };
+        (table as usize, (addr & 0x3ff) as usize)
+    }
+}
+
+impl Cartridge for Cnrom {
+    fn get_ppu_memory(&mut self) -> &mut dyn PPUMemorySpace {
+        self
+    }
+    fn on_event(&mut self, _: &mut Cpu, _: u32, _: &mut PPU) {
+
+    }
+    fn start_of_frame(&mut self, _: &mut EventList, _: u64) {
+
+    }
+}
+
+impl CpuMemory for Cnrom {
+    fn read(&mut self, addr: MemoryPtr, _: &mut Cpu) -> u8 {
+        if addr.0 < 0x8000 {
+            return 0;
+        }
+        self.prg_rom[(addr.0 & 0x7fff) as usize]
+    }
+    fn write(&mut self, addr: MemoryPtr, v: u8, _: &mut Cpu) {
+        if addr.0 < 0x8000 {
+            return;
+        }
+        self.chr_bank = if self.bus_conflicts { v & self.prg_rom[(addr.0 & 0x7fff) as usize] } else { v };
+    }
+}
+
+impl PPUMemorySpace for Cnrom {
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            return self.chr.read(self.chr_bank as usize * 0x2000 + addr as usize);
+        }
+
+        let (table, offset) = self.nametable_index(addr);
+        self.nametables[table][offset]
+    }
+    fn ppu_write(&mut self, addr: u16, v: u8) {
+        if addr < 0x2000 {
+            self.chr.write(self.chr_bank as usize * 0x2000 + addr as usize, v);
+            return;
+        }
+        if addr >= 0x3000 {
+            return;
+        }
+
+        let (table, offset) = self.nametable_index(addr);
+        self.nametables[table][offset] = v;
+    }
+}
+
+impl SaveState for Cnrom {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.chr.save_state(w);
+        for table in self.nametables.iter() {
+            w.bytes(table);
+        }
+        w.u8(self.chr_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(r)?;
+        for table in self.nametables.iter_mut() {
+            r.bytes(table)?;
+        }
+        self.chr_bank = r.u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,259 @@
+use crate::{cpu::{CpuMemory, Cpu}, memory_controller::MemoryPtr, ppu::{PPUMemorySpace, PPU}, EventList};
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+use super::{chr::ChrMemory, Cartridge};
+
+//control register bits
+const CONTROL_MIRRORING: u8 = 0x03;
+const CONTROL_PRG_MODE: u8 = 0x0c;
+const CONTROL_CHR_4K: u8 = 0x10;
+
+const PRG_BANK_RAM_DISABLE: u8 = 0x10;
+
+//games with more than 256K of prg rom (SUROM/SXROM) use bit 4 of the chr register to select the 256K half
+const PRG_OUTER_BANK_SIZE: usize = 0x40000;
+
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    prg_ram: Vec<u8>,
+    nametables: [[u8; 0x400]; 2],
+
+    shift_register: u8,
+    shift_count: u8,
+    last_write_cycle: Option<u64>,
+
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+}
+
+#[derive(Debug)]
+pub enum MMC1MapperError {
+    NoPrgRomPages,
+    TooManyPrgRomPages,
+}
+
+impl Mmc1 {
+    pub fn new(
+        prg_rom: &[[u8; 16384]],
+        chr: ChrMemory,
+        prg_ram_size: usize,
+    ) -> Result<Mmc1, MMC1MapperError> {
+        if prg_rom.is_empty() {
+            return Err(MMC1MapperError::NoPrgRomPages);
+        }
+        if prg_rom.len() > 32 {
+            return Err(MMC1MapperError::TooManyPrgRomPages);
+        }
+
+        Ok(Mmc1 {
+            prg_rom: prg_rom.concat(),
+            chr,
+            //boards always have 8K, 16K or 32K of prg ram
+            prg_ram: vec![0; prg_ram_size.clamp(0x2000, 0x8000).next_power_of_two()],
+            nametables: [[0; 0x400]; 2],
+            shift_register: 0,
+            shift_count: 0,
+            last_write_cycle: None,
+            control: CONTROL_PRG_MODE,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        })
+    }
+
+    fn write_register(&mut self, addr: u16, v: u8) {
+        match addr {
+            0x8000..=0x9fff => self.control = v,
+            0xa000..=0xbfff => self.chr_bank_0 = v,
+            0xc000..=0xdfff => self.chr_bank_1 = v,
+            _ => self.prg_bank = v,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let outer = if self.prg_rom.len() > PRG_OUTER_BANK_SIZE {
+            (self.chr_bank_0 as usize & 0x10) >> 4
+        } else {
+            0
+        };
+        let banks_16k = (self.prg_rom.len() / 0x4000).min(16);
+        let bank = self.prg_bank as usize & 0x0f;
+
+        let bank_16k = match ((self.control & CONTROL_PRG_MODE) >> 2, addr) {
+            //32K mode ignores the low bit of the bank number
+            (0 | 1, 0x8000..=0xbfff) => bank & !1,
+            (0 | 1, _) => bank | 1,
+            (2, 0x8000..=0xbfff) => 0,
+            (2, _) => bank,
+            (_, 0x8000..=0xbfff) => bank,
+            (_, _) => banks_16k - 1,
+        };
+
+        (outer * PRG_OUTER_BANK_SIZE + (bank_16k % banks_16k) * 0x4000 + (addr as usize & 0x3fff)) % self.prg_rom.len()
+    }
+
+    fn prg_ram_offset(&self, addr: u16) -> usize {
+        //SOROM and SXROM select the 8K ram page with the chr register
+        let page = match self.prg_ram.len() {
+            0x4000 => (self.chr_bank_0 as usize >> 3) & 1,
+            0x8000 => (self.chr_bank_0 as usize >> 2) & 3,
+            _ => 0,
+        };
+        page * 0x2000 + (addr as usize & 0x1fff)
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & PRG_BANK_RAM_DISABLE == 0
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        if self.control & CONTROL_CHR_4K != 0 {
+            let bank = if addr < 0x1000 { self.chr_bank_0 } else { self.chr_bank_1 };
+            bank as usize * 0x1000 + (addr as usize & 0xfff)
+        } else {
+            (self.chr_bank_0 as usize & !1) * 0x1000 + (addr as usize & 0x1fff)
+        }
+    }
+
+    fn nametable_index(&self, addr: u16) -> (usize, usize) {
+        let table = match self.control & CONTROL_MIRRORING {
+            0 => 0,
+            1 => 1,
+            2 => (addr >> 10) & 0x1,
+            _ => (addr >> 11) & 0x1,
+        };
+        (table as usize, (addr & 0x3ff) as usize)
+    }
+}
+
+impl Cartridge for Mmc1 {
+    fn get_ppu_memory(&mut self) -> &mut dyn PPUMemorySpace {
+        self
+    }
+    fn on_event(&mut self, _: &mut Cpu, _: u32, _: &mut PPU) {
+
+    }
+    fn start_of_frame(&mut self, _: &mut EventList, _: u64) {
+
+    }
+    fn load_trainer(&mut self, trainer: &[u8; 512]) -> bool {
+        self.prg_ram[0x1000..0x1200].copy_from_slice(trainer);
+        true
+    }
+    fn save_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+    fn load_save_ram(&mut self, data: &[u8]) -> bool {
+        if data.len() != self.prg_ram.len() {
+            return false;
+        }
+        self.prg_ram.copy_from_slice(data);
+        true
+    }
+}
+
+impl CpuMemory for Mmc1 {
+    fn read(&mut self, addr: MemoryPtr, _: &mut Cpu) -> u8 {
+        match addr.0 {
+            0x8000..=0xffff => self.prg_rom[self.prg_offset(addr.0)],
+            0x6000..=0x7fff if self.prg_ram_enabled() => self.prg_ram[self.prg_ram_offset(addr.0)],
+            _ => 0,
+        }
+    }
+    fn write(&mut self, addr: MemoryPtr, v: u8, c: &mut Cpu) {
+        match addr.0 {
+            0x8000..=0xffff => {
+                //the serial port ignores writes on consecutive cycles, like the second write of a
+                //read-modify-write instruction
+                let consecutive = matches!(self.last_write_cycle, Some(x) if c.cycle_count.abs_diff(x) <= 1);
+                self.last_write_cycle = Some(c.cycle_count);
+                if consecutive {
+                    return;
+                }
+
+                if v & 0x80 != 0 {
+                    self.shift_register = 0;
+                    self.shift_count = 0;
+                    self.control |= CONTROL_PRG_MODE;
+                    return;
+                }
+
+                self.shift_register |= (v & 0x1) << self.shift_count;
+                self.shift_count += 1;
+
+                if self.shift_count == 5 {
+                    self.write_register(addr.0, self.shift_register);
+                    self.shift_register = 0;
+                    self.shift_count = 0;
+                }
+            },
+            0x6000..=0x7fff if self.prg_ram_enabled() => {
+                let offset = self.prg_ram_offset(addr.0);
+                self.prg_ram[offset] = v;
+            },
+            _ => {}
+        }
+    }
+}
+
+impl PPUMemorySpace for Mmc1 {
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            return self.chr.read(self.chr_offset(addr));
+        }
+
+        let (table, offset) = self.nametable_index(addr);
+        self.nametables[table][offset]
+    }
+    fn ppu_write(&mut self, addr: u16, v: u8) {
+        if addr < 0x2000 {
+            self.chr.write(self.chr_offset(addr), v);
+            return;
+        }
+        if addr >= 0x3000 {
+            return;
+        }
+
+        let (table, offset) = self.nametable_index(addr);
+        self.nametables[table][offset] = v;
+    }
+}
+
+impl SaveState for Mmc1 {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.chr.save_state(w);
+        for table in self.nametables.iter() {
+            w.bytes(table);
+        }
+        w.vec(&self.prg_ram);
+        w.u8(self.shift_register);
+        w.u8(self.shift_count);
+        w.bool(self.last_write_cycle.is_some());
+        w.u64(self.last_write_cycle.unwrap_or(0));
+        w.u8(self.control);
+        w.u8(self.chr_bank_0);
+        w.u8(self.chr_bank_1);
+        w.u8(self.prg_bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(r)?;
+        for table in self.nametables.iter_mut() {
+            r.bytes(table)?;
+        }
+        r.vec_exact(&mut self.prg_ram)?;
+        self.shift_register = r.u8()?;
+        self.shift_count = r.u8()?;
+        let has_last_write = r.bool()?;
+        let last_write_cycle = r.u64()?;
+        self.last_write_cycle = has_last_write.then_some(last_write_cycle);
+        self.control = r.u8()?;
+        self.chr_bank_0 = r.u8()?;
+        self.chr_bank_1 = r.u8()?;
+        self.prg_bank = r.u8()?;
+        Ok(())
+    }
+}
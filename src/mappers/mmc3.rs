@@ -1,6 +1,7 @@
-use crate::{cpu::{CpuMemory, Cpu}, memory_controller::MemoryPtr, ppu::{PPUMemorySpace, PPU, PPUMASK_SHOW_BACKGROUND, PPUMASK_SHOW_SPRITE}, EventList, FutureEvent, FutureEventType};
+use crate::{cpu::{CpuMemory, Cpu, IRQ_MAPPER}, memory_controller::MemoryPtr, ppu::{PPUMemorySpace, PPU, PPUMASK_SHOW_BACKGROUND, PPUMASK_SHOW_SPRITE}, EventList, FutureEvent, FutureEventType};
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
 
-use super::Cartridge;
+use super::{chr::ChrMemory, Cartridge};
 
 pub enum Mirroring {
     Horizontal,
@@ -12,7 +13,7 @@ pub enum Mirroring {
 pub struct Mmc3 {
     prg_ram: [u8; 8192],
     prg_rom: Vec<u8>,
-    chr_rom: Vec<u8>,
+    chr: ChrMemory,
     registers: [u8; 8],
     nametables: [[u8; 0x400]; 4],
     next_register_update: u8,
@@ -20,6 +21,8 @@ pub struct Mmc3 {
     prg_bank_mode: bool,
     chr_bank_mode: bool,
     enable_interrupt: bool,
+    prg_ram_enabled: bool,
+    prg_ram_write_protect: bool,
 
     irq_latch: u8,
     irq_value: u8,
@@ -32,12 +35,12 @@ pub enum MMC3MapperError {
 impl Mmc3 {
     pub fn new(
         prg_rom: &Vec<[u8; 16384]>,
-        chr_rom: &Vec<[u8; 8192]>,
+        chr: ChrMemory,
         mirroring: Mirroring,
     ) -> Result<Mmc3, MMC3MapperError> {
         Ok(Mmc3 {
             prg_rom: prg_rom.concat(),
-            chr_rom: chr_rom.concat(),
+            chr,
             mirroring: mirroring,
             registers: [0; 8],
             next_register_update: 0,
@@ -45,6 +48,9 @@ impl Mmc3 {
             chr_bank_mode: false,
             nametables: [[0; 0x400]; 4],
             enable_interrupt: false,
+            //the power up state is unknown, but some games never enable the ram
+            prg_ram_enabled: true,
+            prg_ram_write_protect: false,
             irq_latch: 0,
             irq_value: 0,
             prg_ram: [0; 8192],
@@ -69,16 +75,16 @@ impl Mmc3 {
         }
     }
 
-    fn read_big_chr_bank(&self, addr: u16) -> u8 {
+    fn big_chr_bank_offset(&self, addr: u16) -> usize {
         let register_number = match addr & 0xfff {
             0x000..=0x7ff => 0,
             0x800..=0xfff => 1,
             _ => unreachable!()
         };
-        self.chr_rom[((self.registers[register_number] & !0x1) as usize) * 0x400 + ((addr as usize) & 0x7ff)]
+        ((self.registers[register_number] & !0x1) as usize) * 0x400 + ((addr as usize) & 0x7ff)
     }
 
-    fn read_small_chr_bank(&self, addr: u16) -> u8 {
+    fn small_chr_bank_offset(&self, addr: u16) -> usize {
         let register_number = match addr & 0xfff {
             0x000..=0x3ff => 2,
             0x400..=0x7ff => 3,
@@ -87,7 +93,24 @@ impl Mmc3 {
             _ => unreachable!()
         };
 
-        self.chr_rom[(self.registers[register_number] as usize) * 0x400 + ((addr as usize) & 0x3ff)]
+        (self.registers[register_number] as usize) * 0x400 + ((addr as usize) & 0x3ff)
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        match (addr, self.chr_bank_mode) {
+            (0x0000..=0x0fff, false) => {
+                self.big_chr_bank_offset(addr)
+            },
+            (0x0000..=0x0fff, true) => {
+                self.small_chr_bank_offset(addr)
+            },
+            (0x1000..=0x1fff, false) => {
+                self.small_chr_bank_offset(addr)
+            },
+            _ => {
+                self.big_chr_bank_offset(addr)
+            },
+        }
     }
 
 }
@@ -109,11 +132,25 @@ impl Cartridge for Mmc3 {
             self.irq_value -= 1;
 
             if self.irq_value == 0 && self.enable_interrupt {
-                cpu.irq_requested = true;
+                cpu.set_irq(IRQ_MAPPER, true);
                 self.irq_value = self.irq_latch;
             }
         }
     }
+    fn load_trainer(&mut self, trainer: &[u8; 512]) -> bool {
+        self.prg_ram[0x1000..0x1200].copy_from_slice(trainer);
+        true
+    }
+    fn save_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+    fn load_save_ram(&mut self, data: &[u8]) -> bool {
+        if data.len() != self.prg_ram.len() {
+            return false;
+        }
+        self.prg_ram.copy_from_slice(data);
+        true
+    }
     fn start_of_frame(&mut self, event_list: &mut EventList, _: u64) {
         for i in 0..241 {
             event_list.add_event(FutureEvent { 
@@ -148,7 +185,7 @@ impl CpuMemory for Mmc3 {
             0xe000..=0xffff => {
                 self.read_prg_bank(-1, addr.0)
             },
-            (0x6000..=0x7fff) => {
+            (0x6000..=0x7fff) if self.prg_ram_enabled => {
                 self.prg_ram[addr.0 as usize & 0x1fff]
             },
             _ => {
@@ -174,7 +211,9 @@ impl CpuMemory for Mmc3 {
                 self.mirroring = if (v & 0x1) != 0 {Mirroring::Horizontal} else {Mirroring::Vertical};
             },
             (0xa000..=0xbfff, false) => {
-                //prg-ram protect; not implemented
+                //prg-ram protect
+                self.prg_ram_enabled = (v & 0x80) != 0;
+                self.prg_ram_write_protect = (v & 0x40) != 0;
             },
             (0xc000..=0xdfff, true) => {
                 self.irq_latch = v;
@@ -186,13 +225,13 @@ impl CpuMemory for Mmc3 {
             (0xe000..=0xffff, true) => {
                 //irq disable
                 self.enable_interrupt = false;
-                c.irq_requested = false;
+                c.set_irq(IRQ_MAPPER, false);
             },
             (0xe000..=0xffff, false) => {
                 //irq enable
                 self.enable_interrupt = true;
             }
-            (0x6000..=0x7fff, _) => {
+            (0x6000..=0x7fff, _) if self.prg_ram_enabled && !self.prg_ram_write_protect => {
                 self.prg_ram[addr.0 as usize & 0x1fff] = v;
             },
             _ => {
@@ -205,36 +244,24 @@ impl CpuMemory for Mmc3 {
 
 impl PPUMemorySpace for Mmc3 {
     fn ppu_read(&self, addr: u16) -> u8 {
-        match (addr, self.chr_bank_mode) {
-            (0x0000..=0x0fff, false) => {
-                self.read_big_chr_bank(addr)
-            },
-            (0x0000..=0x0fff, true) => {
-                self.read_small_chr_bank(addr)
-            },
-            (0x1000..=0x1fff, false) => {
-                self.read_small_chr_bank(addr)
+        if addr < 0x2000 {
+            return self.chr.read(self.chr_offset(addr));
+        }
+        match self.mirroring {
+            Mirroring::Vertical => {
+                self.nametables[((addr >> 10) & 0x1) as usize][(addr & 0x3ff) as usize]
             },
-            (0x1000..=0x1fff, true) => {
-                self.read_big_chr_bank(addr)
+            Mirroring::Horizontal => {
+                self.nametables[((addr >> 11) & 0x1) as usize][(addr & 0x3ff) as usize]
             },
-            _ => {
-                match self.mirroring {
-                    Mirroring::Vertical => {
-                        self.nametables[((addr >> 10) & 0x1) as usize][(addr & 0x3ff) as usize]
-                    },
-                    Mirroring::Horizontal => {
-                        self.nametables[((addr >> 11) & 0x1) as usize][(addr & 0x3ff) as usize]
-                    },
-                    Mirroring::Hardwired => {
-                        self.nametables[((addr >> 10) & 0x3) as usize][(addr & 0x3ff) as usize]
-                    }
-                }
+            Mirroring::Hardwired => {
+                self.nametables[((addr >> 10) & 0x3) as usize][(addr & 0x3ff) as usize]
             }
         }
     }
     fn ppu_write(&mut self, addr: u16, v: u8) {
         if addr < 0x2000 {
+            self.chr.write(self.chr_offset(addr), v);
             return;
         }
         match self.mirroring {
@@ -252,3 +279,55 @@ impl PPUMemorySpace for Mmc3 {
 
 }
 
+impl SaveState for Mmc3 {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.chr.save_state(w);
+        for table in self.nametables.iter() {
+            w.bytes(table);
+        }
+        w.bytes(&self.prg_ram);
+        w.bytes(&self.registers);
+        w.u8(self.next_register_update);
+        w.u8(match self.mirroring {
+            Mirroring::Horizontal => 0,
+            Mirroring::Vertical => 1,
+            Mirroring::Hardwired => 2,
+        });
+        w.bool(self.prg_bank_mode);
+        w.bool(self.chr_bank_mode);
+        w.bool(self.enable_interrupt);
+        w.bool(self.prg_ram_enabled);
+        w.bool(self.prg_ram_write_protect);
+        w.u8(self.irq_latch);
+        w.u8(self.irq_value);
+        w.bool(self.reload);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(r)?;
+        for table in self.nametables.iter_mut() {
+            r.bytes(table)?;
+        }
+        r.bytes(&mut self.prg_ram)?;
+        r.bytes(&mut self.registers)?;
+        self.next_register_update = r.u8()?;
+        if self.next_register_update > 7 {
+            return Err(StateError::InvalidData);
+        }
+        self.mirroring = match r.u8()? {
+            0 => Mirroring::Horizontal,
+            1 => Mirroring::Vertical,
+            2 => Mirroring::Hardwired,
+            _ => return Err(StateError::InvalidData),
+        };
+        self.prg_bank_mode = r.bool()?;
+        self.chr_bank_mode = r.bool()?;
+        self.enable_interrupt = r.bool()?;
+        self.prg_ram_enabled = r.bool()?;
+        self.prg_ram_write_protect = r.bool()?;
+        self.irq_latch = r.u8()?;
+        self.irq_value = r.u8()?;
+        self.reload = r.bool()?;
+        Ok(())
+    }
+}
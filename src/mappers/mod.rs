@@ -1,31 +1,39 @@
-use log::error;
-
-use crate::EventList;
+use crate::{EventList, FutureEventType};
+use crate::apu::{Apu, DMC_STALL_CYCLES};
 use crate::cpu::Cpu;
 use crate::joypad::{Joypad};
-use crate::ppu::{PPUMemorySpace, PPU, DmaTransferSource};
+use crate::ppu::{PPUMemorySpace, PPU};
 use crate::{cpu::CpuMemory, memory_controller::Ram};
 
 use crate::memory_controller::MemoryPtr;
+use crate::savestate::SaveState;
+pub mod chr;
 pub mod nrom;
+pub mod uxrom;
+pub mod cnrom;
+pub mod axrom;
+pub mod mmc1;
 pub mod mmc3;
 
-pub trait Cartridge: PPUMemorySpace + CpuMemory {
+//SaveState covers everything a game can change: registers, irq counters and ram
+pub trait Cartridge: PPUMemorySpace + CpuMemory + SaveState {
     fn get_ppu_memory(&mut self) -> &mut dyn PPUMemorySpace;
     fn start_of_frame(&mut self, event_list: &mut EventList, cyc: u64);
     fn on_event(&mut self, cpu: &mut Cpu, event_id: u32, ppu: &mut PPU);
-}
 
-impl<T: Cartridge + ?Sized> DmaTransferSource for T {
-    fn read_page_for_oam(&mut self, page: u8, cpu: &mut Cpu) -> [u8; 256] {
-        let start = (page as u16) << 8;
+    //copies the 512 byte trainer to $7000-$71ff, returns false if there is no prg ram there
+    fn load_trainer(&mut self, _trainer: &[u8; 512]) -> bool {
+        false
+    }
 
-        let mut result = [0u8; 256];
-        for i in 0u16..=0xff {
-            result[i as usize] = self.read(MemoryPtr(start + i), cpu);
-        }
+    //prg ram that is kept by a battery on boards that have one, None if the mapper has no ram
+    fn save_ram(&self) -> Option<&[u8]> {
+        None
+    }
 
-        result
+    //restores the ram returned by save_ram, data of the wrong size is ignored
+    fn load_save_ram(&mut self, _data: &[u8]) -> bool {
+        false
     }
 }
 
@@ -33,27 +41,105 @@ pub struct SystemMemoryMapper<'a> {
     ram: &'a mut Ram,
     cartridge: &'a mut dyn Cartridge,
     ppu: &'a mut PPU,
-    gamepad: &'a mut Joypad,
+    apu: &'a mut Apu,
+    gamepads: &'a mut [Joypad; 2],
+    events: &'a mut EventList,
+    framebuffer: &'a mut [u8; 240*256],
+    frame_start: Option<u64>,
 }
 
 impl<'a> SystemMemoryMapper<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         ram: &'a mut Ram,
         cartridge: &'a mut dyn Cartridge,
         ppu: &'a mut PPU,
-        gamepad: &'a mut Joypad
+        apu: &'a mut Apu,
+        gamepads: &'a mut [Joypad; 2],
+        events: &'a mut EventList,
+        framebuffer: &'a mut [u8; 240*256],
+        frame_start: Option<u64>,
     ) -> SystemMemoryMapper<'a> {
         SystemMemoryMapper {
             ram,
             cartridge,
             ppu,
-            gamepad,
+            apu,
+            gamepads,
+            events,
+            framebuffer,
+            frame_start,
         }
     }
+
+    //runs the ppu and mapper events that are due by the current cpu cycle, and the dot renderer
+    //up to it, so every access sees the ppu at the dot it happens on. outside of a frame nothing runs
+    pub(crate) fn catch_up(&mut self, cpu: &mut Cpu) {
+        let start_of_frame_cycle = match self.frame_start {
+            Some(cycle) => cycle,
+            None => return,
+        };
+
+        let frame_dot = 3 * (cpu.cycle_count - start_of_frame_cycle);
+        while let Some(x) = self.events.pop_next_event(frame_dot) {
+            self.ppu
+                .drawing_context(self.cartridge.get_ppu_memory(), self.events, self.framebuffer)
+                .run_dots(x.cycle);
+
+            match x.tp {
+                FutureEventType::PPU(event_id) => {
+                    self.ppu
+                        .drawing_context(self.cartridge.get_ppu_memory(), self.events, self.framebuffer)
+                        .handle_event(event_id, cpu.cycle_count);
+                },
+                FutureEventType::Cartridge(event_id) => {
+                    self.cartridge.on_event(cpu, event_id, self.ppu);
+                },
+            };
+        }
+
+        self.ppu
+            .drawing_context(self.cartridge.get_ppu_memory(), self.events, self.framebuffer)
+            .run_dots(frame_dot);
+    }
+
+    //runs the apu up to the current cpu cycle. a sample fetch of the dmc halts the cpu for a few cycles
+    //and reads the byte over the bus, so the ppu and mapper keep running through the stall
+    pub(crate) fn catch_up_apu(&mut self, cpu: &mut Cpu) {
+        while let Some(addr) = self.apu.catch_up(cpu) {
+            for _ in 1..DMC_STALL_CYCLES {
+                self.catch_up(cpu);
+                cpu.cycle_count += 1;
+            }
+            let v = self.read(addr, cpu);
+            cpu.cycle_count += 1;
+            self.apu.load_dmc_sample(v);
+        }
+    }
+
+    //the cpu is halted for a cycle, one more if the next cycle is not a read cycle, and then the page
+    //is copied to $2004 one read and one write per cycle. the cpu adds the cycle of the $4014 write itself
+    fn oam_dma(&mut self, page: u8, cpu: &mut Cpu) {
+        cpu.cycle_count += 2;
+        if cpu.cycle_count % 2 == 1 {
+            cpu.cycle_count += 1;
+        }
+
+        for i in 0..=0xff {
+            let v = self.read(MemoryPtr((page as u16) << 8 | i), cpu);
+            cpu.cycle_count += 1;
+            self.write(MemoryPtr(0x2004), v, cpu);
+            cpu.cycle_count += 1;
+        }
+        cpu.cycle_count -= 1;
+    }
 }
 
 impl<'a> CpuMemory for SystemMemoryMapper<'a> {
     fn read(&mut self, addr: MemoryPtr, c: &mut Cpu) -> u8 {
+        self.catch_up_apu(c);
+        self.catch_up(c);
+
         if addr.0 < 0x2000 {
             return self.ram.read(addr, c);
         }
@@ -62,13 +148,24 @@ impl<'a> CpuMemory for SystemMemoryMapper<'a> {
             return self.ppu.context(self.cartridge.get_ppu_memory()).read(addr);
         }
 
+        if addr.0 == 0x4015 {
+            return self.apu.read(addr, c);
+        }
+
         if addr.0 == 0x4016 {
-            return self.gamepad.read(addr);
+            return self.gamepads[0].read(addr);
+        }
+
+        if addr.0 == 0x4017 {
+            return self.gamepads[1].read(addr);
         }
 
         return self.cartridge.read(addr, c);
     }
     fn write(&mut self, addr: MemoryPtr, value: u8, c: &mut Cpu) {
+        self.catch_up_apu(c);
+        self.catch_up(c);
+
         if addr.0 < 0x2000 {
             self.ram.write(addr, value, c);
             return;
@@ -82,21 +179,25 @@ impl<'a> CpuMemory for SystemMemoryMapper<'a> {
         }
 
         if addr.0 == 0x4014 {
-            if value < 0x20 {
-                self.ppu.dma_transfer(value, self.ram, c);
-            } else if value >= 40 {
-                self.ppu.dma_transfer(value, self.cartridge, c);
-            } else {
-                error!("tried dma page of ppu registers");
-            }
-
+            self.oam_dma(value, c);
             return;
         }
 
+        //the strobe line is shared by both controller ports
         if addr.0 == 0x4016 {
-            return self.gamepad.write(addr, value);
+            for gamepad in self.gamepads.iter_mut() {
+                gamepad.write(addr, value);
+            }
+            return;
+        }
+
+        if (addr.0 >= 0x4000 && addr.0 <= 0x4013) || addr.0 == 0x4015 || addr.0 == 0x4017 {
+            return self.apu.write(addr, value, c);
         }
 
         return self.cartridge.write(addr, value, c);
     }
 }
+
+#[cfg(test)]
+mod tests;
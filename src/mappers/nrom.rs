@@ -1,6 +1,7 @@
 use crate::{cpu::{CpuMemory, Cpu}, memory_controller::MemoryPtr, ppu::{PPUMemorySpace, PPU}, EventList};
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
 
-use super::Cartridge;
+use super::{chr::ChrMemory, Cartridge};
 
 pub enum Mirroring {
     Horizontal,
@@ -9,7 +10,9 @@ pub enum Mirroring {
 
 pub struct Nrom {
     prg_rom: [u8; 32768],
-    chr_rom: [u8; 8192],
+    //family basic has 8K of ram at $6000, emulators give it to every nrom game and test roms rely on it
+    prg_ram: [u8; 8192],
+    chr: ChrMemory,
     nametables: [[u8; 0x400]; 2],
     mirroring: Mirroring,
 }
@@ -22,7 +25,7 @@ pub enum BaseMapperError {
 impl Nrom {
     pub fn new(
         prg_rom: &Vec<[u8; 16384]>,
-        chr_rom: [u8; 8192],
+        chr: ChrMemory,
         mirror: Mirroring,
     ) -> Result<Nrom, BaseMapperError> {
         if prg_rom.len() == 0 {
@@ -41,7 +44,8 @@ impl Nrom {
 
         Ok(Nrom {
             prg_rom: result_prg_rom,
-            chr_rom: chr_rom,
+            prg_ram: [0; 8192],
+            chr,
             nametables: [[0; 0x400]; 2],
             mirroring: mirror,
         })
@@ -58,20 +62,41 @@ impl Cartridge for Nrom {
     fn start_of_frame(&mut self, _: &mut EventList, _: u64) {
         
     }
+    fn load_trainer(&mut self, trainer: &[u8; 512]) -> bool {
+        self.prg_ram[0x1000..0x1200].copy_from_slice(trainer);
+        true
+    }
+    fn save_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+    fn load_save_ram(&mut self, data: &[u8]) -> bool {
+        if data.len() != self.prg_ram.len() {
+            return false;
+        }
+        self.prg_ram.copy_from_slice(data);
+        true
+    }
 }
 
 impl CpuMemory for Nrom {
     fn read(&mut self, addr: MemoryPtr, _: &mut Cpu) -> u8 {
-        self.prg_rom[(addr.0 & 0x7fff) as usize]
+        match addr.0 {
+            0x8000..=0xffff => self.prg_rom[(addr.0 & 0x7fff) as usize],
+            0x6000..=0x7fff => self.prg_ram[(addr.0 & 0x1fff) as usize],
+            _ => 0,
+        }
     }
-    fn write(&mut self, _: MemoryPtr, _: u8, _: &mut Cpu) {
+    fn write(&mut self, addr: MemoryPtr, v: u8, _: &mut Cpu) {
+        if (0x6000..=0x7fff).contains(&addr.0) {
+            self.prg_ram[(addr.0 & 0x1fff) as usize] = v;
+        }
     }
 }
 
 impl PPUMemorySpace for Nrom {
     fn ppu_read(&self, addr: u16) -> u8 {
         if addr < 0x2000 {
-            return self.chr_rom[addr as usize];
+            return self.chr.read(addr as usize);
         }
 
         match self.mirroring {
@@ -84,7 +109,11 @@ impl PPUMemorySpace for Nrom {
         }
     }
     fn ppu_write(&mut self, addr: u16, v: u8) {
-        if addr < 0x2000 || addr >= 0x3000 {
+        if addr < 0x2000 {
+            self.chr.write(addr as usize, v);
+            return;
+        }
+        if addr >= 0x3000 {
             return;
         }
         match self.mirroring {
@@ -97,3 +126,22 @@ impl PPUMemorySpace for Nrom {
         }
     }
 }
+
+impl SaveState for Nrom {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.prg_ram);
+        self.chr.save_state(w);
+        for table in self.nametables.iter() {
+            w.bytes(table);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes(&mut self.prg_ram)?;
+        self.chr.load_state(r)?;
+        for table in self.nametables.iter_mut() {
+            r.bytes(table)?;
+        }
+        Ok(())
+    }
+}
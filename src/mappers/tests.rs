@@ -0,0 +1,365 @@
+use crate::{apu::{Apu, DEFAULT_SAMPLE_RATE}, cpu::{Cpu, CpuMemory}, memory_controller::MemoryPtr, ppu::{PPUMemorySpace, PPU, PPUMASK_SHOW_BACKGROUND}};
+
+use std::{env, fs};
+
+use crate::battery::BatterySave;
+
+use super::{axrom::Axrom, chr::ChrMemory, cnrom::Cnrom, mmc1::Mmc1, mmc3::{self, Mmc3}, nrom::{Mirroring, Nrom}, uxrom::Uxrom, Cartridge};
+
+//every 16K prg page is filled with its own index, every 4K of chr with its index
+fn numbered_prg(pages: usize) -> Vec<[u8; 16384]> {
+    (0..pages).map(|i| [i as u8; 16384]).collect()
+}
+
+fn numbered_chr(pages: usize) -> ChrMemory {
+    let pages: Vec<[u8; 8192]> = (0..pages)
+        .map(|i| {
+            let mut page = [2 * i as u8; 8192];
+            page[0x1000..].fill(2 * i as u8 + 1);
+            page
+        })
+        .collect();
+    ChrMemory::rom(&pages)
+}
+
+//writes one value through the mmc1 serial port, instructions are never closer than 2 cycles apart
+fn mmc1_write(mapper: &mut Mmc1, cpu: &mut Cpu, addr: u16, v: u8) {
+    for i in 0..5 {
+        cpu.cycle_count += 2;
+        mapper.write(MemoryPtr(addr), (v >> i) & 1, cpu);
+    }
+}
+
+#[test]
+fn test_mmc1_power_on_fixes_last_bank() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Mmc1::new(&numbered_prg(8), numbered_chr(2), 0x2000).unwrap();
+
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 0);
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 7);
+    assert_eq!(mapper.read(MemoryPtr(0xffff), &mut cpu), 7);
+}
+
+#[test]
+fn test_mmc1_prg_modes() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Mmc1::new(&numbered_prg(8), numbered_chr(2), 0x2000).unwrap();
+
+    mmc1_write(&mut mapper, &mut cpu, 0xe000, 5);
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 5);
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 7);
+
+    //fix the first bank at $8000
+    mmc1_write(&mut mapper, &mut cpu, 0x8000, 0x08);
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 0);
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 5);
+
+    //32K mode ignores the low bit
+    mmc1_write(&mut mapper, &mut cpu, 0x8000, 0x00);
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 4);
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 5);
+}
+
+#[test]
+fn test_mmc1_reset_and_consecutive_writes() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Mmc1::new(&numbered_prg(8), numbered_chr(2), 0x2000).unwrap();
+
+    mmc1_write(&mut mapper, &mut cpu, 0x8000, 0x00);
+
+    //a write with bit 7 set clears the shift register and goes back to prg mode 3
+    cpu.cycle_count += 2;
+    mapper.write(MemoryPtr(0xe000), 1, &mut cpu);
+    cpu.cycle_count += 2;
+    mapper.write(MemoryPtr(0xe000), 0x80, &mut cpu);
+    mmc1_write(&mut mapper, &mut cpu, 0xe000, 2);
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 2);
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 7);
+
+    //the second write of a read-modify-write instruction is ignored
+    for i in 0..5 {
+        cpu.cycle_count += 2;
+        mapper.write(MemoryPtr(0xe000), 1, &mut cpu);
+        if i == 2 {
+            cpu.cycle_count += 1;
+            mapper.write(MemoryPtr(0xe000), 0, &mut cpu);
+        }
+    }
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 15 % 8);
+}
+
+#[test]
+fn test_mmc1_chr_banks_and_mirroring() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Mmc1::new(&numbered_prg(2), numbered_chr(4), 0x2000).unwrap();
+
+    //8K mode ignores the low bit
+    mmc1_write(&mut mapper, &mut cpu, 0xa000, 5);
+    assert_eq!(mapper.ppu_read(0x0000), 4);
+    assert_eq!(mapper.ppu_read(0x1000), 5);
+
+    //4K mode, vertical mirroring
+    mmc1_write(&mut mapper, &mut cpu, 0x8000, 0x1e);
+    mmc1_write(&mut mapper, &mut cpu, 0xc000, 2);
+    assert_eq!(mapper.ppu_read(0x0000), 5);
+    assert_eq!(mapper.ppu_read(0x1000), 2);
+
+    mapper.ppu_write(0x2400, 1);
+    assert_eq!(mapper.ppu_read(0x2c00), 1);
+    assert_eq!(mapper.ppu_read(0x2000), 0);
+
+    //horizontal
+    mmc1_write(&mut mapper, &mut cpu, 0x8000, 0x1f);
+    assert_eq!(mapper.ppu_read(0x2000), 0);
+    assert_eq!(mapper.ppu_read(0x2c00), 1);
+
+    //one screen, upper bank
+    mmc1_write(&mut mapper, &mut cpu, 0x8000, 0x11);
+    for addr in [0x2000, 0x2400, 0x2800, 0x2c00] {
+        assert_eq!(mapper.ppu_read(addr), 1);
+    }
+    mapper.ppu_write(0x2800, 3);
+    assert_eq!(mapper.ppu_read(0x2000), 3);
+}
+
+#[test]
+fn test_mmc1_chr_ram() {
+    let mut mapper = Mmc1::new(&numbered_prg(2), ChrMemory::ram(8192), 0x2000).unwrap();
+
+    mapper.ppu_write(0x0123, 0x42);
+    mapper.ppu_write(0x1123, 0x43);
+    assert_eq!(mapper.ppu_read(0x0123), 0x42);
+    assert_eq!(mapper.ppu_read(0x1123), 0x43);
+}
+
+#[test]
+fn test_mmc1_surom_outer_bank() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Mmc1::new(&numbered_prg(32), ChrMemory::ram(8192), 0x2000).unwrap();
+
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 15);
+
+    mmc1_write(&mut mapper, &mut cpu, 0xa000, 0x10);
+    mmc1_write(&mut mapper, &mut cpu, 0xe000, 3);
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 19);
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 31);
+}
+
+#[test]
+fn test_mmc1_sxrom_prg_ram() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Mmc1::new(&numbered_prg(32), ChrMemory::ram(8192), 0x8000).unwrap();
+
+    for page in 0..4 {
+        mmc1_write(&mut mapper, &mut cpu, 0xa000, page << 2);
+        mapper.write(MemoryPtr(0x6000), page + 1, &mut cpu);
+    }
+    for page in 0..4 {
+        mmc1_write(&mut mapper, &mut cpu, 0xa000, page << 2);
+        assert_eq!(mapper.read(MemoryPtr(0x6000), &mut cpu), page + 1);
+    }
+
+    //bit 4 of the prg register disables the ram
+    mmc1_write(&mut mapper, &mut cpu, 0xe000, 0x10);
+    mapper.write(MemoryPtr(0x6000), 0xff, &mut cpu);
+    assert_eq!(mapper.read(MemoryPtr(0x6000), &mut cpu), 0);
+    mmc1_write(&mut mapper, &mut cpu, 0xe000, 0x00);
+    assert_eq!(mapper.read(MemoryPtr(0x6000), &mut cpu), 4);
+}
+
+#[test]
+fn test_uxrom_banks() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Uxrom::new(&numbered_prg(8), ChrMemory::ram(8192), Mirroring::Vertical, false).ok().unwrap();
+
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 0);
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 7);
+
+    mapper.write(MemoryPtr(0x8000), 3, &mut cpu);
+    assert_eq!(mapper.read(MemoryPtr(0xbfff), &mut cpu), 3);
+    assert_eq!(mapper.read(MemoryPtr(0xffff), &mut cpu), 7);
+
+    mapper.ppu_write(0x1fff, 0x55);
+    assert_eq!(mapper.ppu_read(0x1fff), 0x55);
+}
+
+#[test]
+fn test_uxrom_bus_conflicts() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Uxrom::new(&numbered_prg(8), ChrMemory::ram(8192), Mirroring::Vertical, true).ok().unwrap();
+
+    //the fixed bank holds 7 everywhere, so the written value is masked with it
+    mapper.write(MemoryPtr(0xc000), 0x0e, &mut cpu);
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 6);
+}
+
+#[test]
+fn test_cnrom_chr_banks() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Cnrom::new(&numbered_prg(1), numbered_chr(4), Mirroring::Horizontal, false).ok().unwrap();
+
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 0);
+    assert_eq!(mapper.ppu_read(0x0000), 0);
+
+    mapper.write(MemoryPtr(0x8000), 2, &mut cpu);
+    assert_eq!(mapper.ppu_read(0x0000), 4);
+    assert_eq!(mapper.ppu_read(0x1000), 5);
+
+    //chr rom is read only
+    mapper.ppu_write(0x0000, 0xff);
+    assert_eq!(mapper.ppu_read(0x0000), 4);
+}
+
+#[test]
+fn test_axrom_banks_and_mirroring() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Axrom::new(&numbered_prg(8), ChrMemory::ram(8192), false).ok().unwrap();
+
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 0);
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 1);
+
+    mapper.write(MemoryPtr(0x8000), 0x12, &mut cpu);
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 4);
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 5);
+
+    //one screen, every nametable address maps to the upper page
+    mapper.ppu_write(0x2000, 1);
+    for addr in [0x2000, 0x2400, 0x2800, 0x2c00] {
+        assert_eq!(mapper.ppu_read(addr), 1);
+    }
+
+    mapper.write(MemoryPtr(0x8000), 0x02, &mut cpu);
+    assert_eq!(mapper.ppu_read(0x2000), 0);
+}
+
+#[test]
+fn test_chr_rom_is_read_only() {
+    let mut chr = numbered_chr(1);
+    chr.write(0x1000, 0xff);
+    assert_eq!(chr.read(0x1000), 1);
+    assert!(!chr.is_ram());
+
+    //reads past the end wrap around
+    assert_eq!(chr.read(0x2000), 0);
+}
+
+#[test]
+fn test_nrom_chr_ram() {
+    let mut mapper = Nrom::new(&numbered_prg(1), ChrMemory::ram(8192), Mirroring::Horizontal).ok().unwrap();
+
+    mapper.ppu_write(0x0000, 0x11);
+    mapper.ppu_write(0x1fff, 0x22);
+    assert_eq!(mapper.ppu_read(0x0000), 0x11);
+    assert_eq!(mapper.ppu_read(0x1fff), 0x22);
+}
+
+#[test]
+fn test_nrom_prg_ram() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Nrom::new(&numbered_prg(2), ChrMemory::ram(8192), Mirroring::Horizontal).ok().unwrap();
+
+    mapper.write(MemoryPtr(0x6000), 0x12, &mut cpu);
+    mapper.write(MemoryPtr(0x7fff), 0x34, &mut cpu);
+    assert_eq!(mapper.read(MemoryPtr(0x6000), &mut cpu), 0x12);
+    assert_eq!(mapper.read(MemoryPtr(0x7fff), &mut cpu), 0x34);
+
+    //rom can't be written
+    mapper.write(MemoryPtr(0xc000), 0x56, &mut cpu);
+    assert_eq!(mapper.read(MemoryPtr(0xc000), &mut cpu), 1);
+    assert_eq!(mapper.read(MemoryPtr(0x8000), &mut cpu), 0);
+}
+
+#[test]
+fn test_mmc3_prg_ram_protect() {
+    let mut cpu = Cpu::new();
+    let mut mapper = Mmc3::new(&numbered_prg(4), numbered_chr(1), mmc3::Mirroring::Vertical).ok().unwrap();
+
+    mapper.write(MemoryPtr(0x6000), 1, &mut cpu);
+    assert_eq!(mapper.read(MemoryPtr(0x6000), &mut cpu), 1);
+
+    //enabled but write protected
+    mapper.write(MemoryPtr(0xa001), 0xc0, &mut cpu);
+    mapper.write(MemoryPtr(0x6000), 2, &mut cpu);
+    assert_eq!(mapper.read(MemoryPtr(0x6000), &mut cpu), 1);
+
+    //disabled
+    mapper.write(MemoryPtr(0xa001), 0x00, &mut cpu);
+    assert_eq!(mapper.read(MemoryPtr(0x6000), &mut cpu), 0);
+
+    mapper.write(MemoryPtr(0xa001), 0x80, &mut cpu);
+    mapper.write(MemoryPtr(0x6000), 3, &mut cpu);
+    assert_eq!(mapper.read(MemoryPtr(0x6000), &mut cpu), 3);
+}
+
+#[test]
+fn test_mmc3_irq_survives_apu_acknowledge() {
+    let mut cpu = Cpu::new();
+    let mut ppu = PPU::new();
+    ppu.current_state.ppumask = PPUMASK_SHOW_BACKGROUND;
+    let mut mapper = Mmc3::new(&numbered_prg(4), numbered_chr(1), mmc3::Mirroring::Vertical).ok().unwrap();
+
+    //irq after one counted scanline
+    mapper.write(MemoryPtr(0xc000), 1, &mut cpu);
+    mapper.write(MemoryPtr(0xc001), 0, &mut cpu);
+    mapper.write(MemoryPtr(0xe001), 0, &mut cpu);
+    mapper.on_event(&mut cpu, 0, &mut ppu);
+    mapper.on_event(&mut cpu, 0, &mut ppu);
+    assert!(cpu.irq_requested());
+
+    //acknowledging the apu interrupts leaves the line of the mapper alone
+    let mut apu = Apu::new(DEFAULT_SAMPLE_RATE);
+    cpu.cycle_count = 30000;
+    apu.catch_up(&mut cpu);
+    apu.read(MemoryPtr(0x4015), &mut cpu);
+    apu.write(MemoryPtr(0x4010), 0, &mut cpu);
+    apu.write(MemoryPtr(0x4015), 0, &mut cpu);
+    apu.write(MemoryPtr(0x4017), 0x40, &mut cpu);
+    assert!(cpu.irq_requested());
+
+    mapper.write(MemoryPtr(0xe000), 0, &mut cpu);
+    assert!(!cpu.irq_requested());
+}
+
+#[test]
+fn test_battery_save() {
+    let rom_path = env::temp_dir().join("nesmu_test_battery.nes");
+    let mut cpu = Cpu::new();
+
+    let mut mapper = Mmc1::new(&numbered_prg(2), ChrMemory::ram(8192), 0x2000).unwrap();
+    let mut battery = BatterySave::new(&rom_path);
+    assert_eq!(battery.path(), env::temp_dir().join("nesmu_test_battery.sav"));
+    let _ = fs::remove_file(battery.path());
+
+    //no save file yet
+    battery.load(&mut mapper).unwrap();
+    mapper.write(MemoryPtr(0x6123), 0x45, &mut cpu);
+    battery.flush(&mapper).unwrap();
+
+    let mut restored = Mmc1::new(&numbered_prg(2), ChrMemory::ram(8192), 0x2000).unwrap();
+    BatterySave::new(&rom_path).load(&mut restored).unwrap();
+    assert_eq!(restored.read(MemoryPtr(0x6123), &mut cpu), 0x45);
+
+    //a save of the wrong size is ignored
+    let mut larger = Mmc1::new(&numbered_prg(2), ChrMemory::ram(8192), 0x8000).unwrap();
+    BatterySave::new(&rom_path).load(&mut larger).unwrap();
+    assert_eq!(larger.save_ram().unwrap()[0x123], 0);
+
+    fs::remove_file(battery.path()).unwrap();
+
+    //nrom boards with a battery, like family basic
+    let rom_path = env::temp_dir().join("nesmu_test_battery_nrom.nes");
+    let mut mapper = Nrom::new(&numbered_prg(2), ChrMemory::ram(8192), Mirroring::Horizontal).ok().unwrap();
+    let mut battery = BatterySave::new(&rom_path);
+    let _ = fs::remove_file(battery.path());
+
+    battery.load(&mut mapper).unwrap();
+    mapper.write(MemoryPtr(0x7abc), 0x67, &mut cpu);
+    battery.flush(&mapper).unwrap();
+
+    let mut restored = Nrom::new(&numbered_prg(2), ChrMemory::ram(8192), Mirroring::Horizontal).ok().unwrap();
+    BatterySave::new(&rom_path).load(&mut restored).unwrap();
+    assert_eq!(restored.read(MemoryPtr(0x7abc), &mut cpu), 0x67);
+    assert!(!restored.load_save_ram(&[0; 0x2000 * 4]));
+
+    fs::remove_file(battery.path()).unwrap();
+}
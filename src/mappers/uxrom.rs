@@ -0,0 +1,125 @@
+use crate::{cpu::{CpuMemory, Cpu}, memory_controller::MemoryPtr, ppu::{PPUMemorySpace, PPU}, EventList};
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+use super::{chr::ChrMemory, nrom::Mirroring, Cartridge};
+
+//mapper 2: switchable 16K bank at $8000, last bank fixed at $c000
+pub struct Uxrom {
+    prg_rom: Vec<u8>,
+    chr: ChrMemory,
+    nametables: [[u8; 0x400]; 2],
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    bank: u8,
+}
+
+#[derive(Debug)]
+pub enum UxromMapperError {
+    NoPrgRomPages,
+}
+
+impl Uxrom {
+    pub fn new(
+        prg_rom: &[[u8; 16384]],
+        chr: ChrMemory,
+        mirroring: Mirroring,
+        bus_conflicts: bool,
+    ) -> Result<Uxrom, UxromMapperError> {
+        if prg_rom.is_empty() {
+            return Err(UxromMapperError::NoPrgRomPages);
+        }
+
+        Ok(Uxrom {
+            prg_rom: prg_rom.concat(),
+            chr,
+            nametables: [[0; 0x400]; 2],
+            mirroring,
+            bus_conflicts,
+            bank: 0,
+        })
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let banks = self.prg_rom.len() / 0x4000;
+        let bank = if addr < 0xc000 { self.bank as usize % banks } else { banks - 1 };
+        bank * 0x4000 + (addr as usize & 0x3fff)
+    }
+
+    fn nametable_index(&self, addr: u16) -> (usize, usize) {
+        let table = match self.mirroring {
+            Mirroring::Vertical => (addr >> 10) & 0x1,
+            Mirroring::Horizontal => (addr >> 11) & 0x1,
+        };
+        (table as usize, (addr & 0x3ff) as usize)
+    }
+}
+
+impl Cartridge for Uxrom {
+    fn get_ppu_memory(&mut self) -> &mut dyn PPUMemorySpace {
+        self
+    }
+    fn on_event(&mut self, _: &mut Cpu, _: u32, _: &mut PPU) {
+
+    }
+    fn start_of_frame(&mut self, _: &mut EventList, _: u64) {
+
+    }
+}
+
+impl CpuMemory for Uxrom {
+    fn read(&mut self, addr: MemoryPtr, _: &mut Cpu) -> u8 {
+        if addr.0 < 0x8000 {
+            return 0;
+        }
+        self.prg_rom[self.prg_offset(addr.0)]
+    }
+    fn write(&mut self, addr: MemoryPtr, v: u8, _: &mut Cpu) {
+        if addr.0 < 0x8000 {
+            return;
+        }
+        //with bus conflicts the rom drives the bus at the same time, so the value is and'ed with it
+        self.bank = if self.bus_conflicts { v & self.prg_rom[self.prg_offset(addr.0)] } else { v };
+    }
+}
+
+impl PPUMemorySpace for Uxrom {
+    fn ppu_read(&self, addr: u16) -> u8 {
+        if addr < 0x2000 {
+            return self.chr.read(addr as usize);
+        }
+
+        let (table, offset) = self.nametable_index(addr);
+        self.nametables[table][offset]
+    }
+    fn ppu_write(&mut self, addr: u16, v: u8) {
+        if addr < 0x2000 {
+            self.chr.write(addr as usize, v);
+            return;
+        }
+        if addr >= 0x3000 {
+            return;
+        }
+
+        let (table, offset) = self.nametable_index(addr);
+        self.nametables[table][offset] = v;
+    }
+}
+
+impl SaveState for Uxrom {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.chr.save_state(w);
+        for table in self.nametables.iter() {
+            w.bytes(table);
+        }
+        w.u8(self.bank);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(r)?;
+        for table in self.nametables.iter_mut() {
+            r.bytes(table)?;
+        }
+        self.bank = r.u8()?;
+        Ok(())
+    }
+}
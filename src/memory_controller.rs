@@ -1,6 +1,7 @@
 use std::{ops};
 
-use crate::{cpu::Cpu, ppu::DmaTransferSource};
+use crate::cpu::Cpu;
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
 
 use super::cpu::CpuMemory;
 
@@ -39,6 +40,16 @@ impl Ram {
     }
 }
 
+impl SaveState for Ram {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes(&mut self.ram)
+    }
+}
+
 impl CpuMemory for Ram {
     fn read(&mut self, addr: MemoryPtr, _: &mut Cpu) -> u8 {
         return self.ram[(addr.0 & 0x7ff) as usize];
@@ -48,9 +59,3 @@ impl CpuMemory for Ram {
     }
 }
 
-impl DmaTransferSource for Ram {
-    fn read_page_for_oam(&mut self, page: u8, _: &mut Cpu) -> [u8; 256] {
-        let start = (page as usize) << 8;
-        self.ram.as_slice()[start..(start+0x100)].try_into().unwrap()
-    }
-}
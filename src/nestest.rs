@@ -1,4 +1,5 @@
 use std::{
+    fmt,
     fs::File,
     io::{self, BufRead},
 };
@@ -13,14 +14,23 @@ use crate::{
     memory_controller::{MemoryPtr}, Nes,
 };
 
+//ppu dots per scanline and scanlines per frame, the log counts both from power on
+const DOTS_PER_SCANLINE: u64 = 341;
+const SCANLINES: u64 = 262;
+
+//not part of the repository, like the roms in test_roms.rs. run with `cargo test nestest -- --ignored`
+const ROM_PATH: &str = "test_roms/nestest.nes";
+const LOG_PATH: &str = "test_roms/nestest.log";
+
 #[test]
+#[ignore = "needs nestest.nes and nestest.log in test_roms/"]
 fn nestest() {
     let mut builder = Builder::new();
     builder.target(Target::Stdout);
     builder.filter_level(log::LevelFilter::Debug);
     builder.init();
 
-    let x = ines_rom_file::Rom::new("nestest.nes").unwrap();
+    let x = ines_rom_file::Rom::new(ROM_PATH).unwrap_or_else(|e| panic!("could not open {}: {}", ROM_PATH, e));
 
     let k = x.get_cpu_mapper().unwrap();
 
@@ -35,79 +45,209 @@ fn nestest() {
         program_counter: MemoryPtr(0xc000),
         cycle_count: 7,
         last_instruction: 0,
-        irq_requested: false
+        irq_sources: 0,
+        nmi_pending: false,
+        irq_poll_disabled: true,
+        halted: false,
     };
 
-    for (line, expected_state) in reference_log_iter().enumerate() {
-        if line == 5002 {
-            //from this position unimplemented opcodes are tested
-            break;
-        }
-        if console.cpu != expected_state {
-            panic!(
-                "states differ at line {}!\nGot: {}\t\t Expected: {}",
-                line + 1,
-                console.cpu,
-                expected_state
-            );
+    let mut previous: Option<LogLine> = None;
+    for (line, expected) in reference_log_iter().enumerate() {
+        let state = TraceState::from_cpu(&console.cpu);
+        if state != expected.state {
+            panic!("{}", mismatch_report(line + 1, previous.as_ref(), &expected, &state));
         }
 
-        console.cpu_context().execute_next_instruction().unwrap();
+        console.cpu_context().execute_next_instruction();
+        previous = Some(expected);
     }
 }
 
-impl PartialEq for Cpu {
-    fn eq(&self, other: &Self) -> bool {
-        if self.accumulator != other.accumulator {
-            return false;
-        }
-        if self.flags != other.flags {
-            return false;
-        }
-        if self.program_counter != other.program_counter {
-            return false;
-        }
-        if self.x != other.x {
-            return false;
-        }
-        if self.y != other.y {
-            return false;
-        }
-        if self.stack_pointer != other.stack_pointer {
-            return false;
+//cpu registers, cycle count and ppu position as printed in every line of nestest.log
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+struct TraceState {
+    program_counter: u16,
+    accumulator: u8,
+    x: u8,
+    y: u8,
+    flags: u8,
+    stack_pointer: u8,
+    scanline: u64,
+    dot: u64,
+    cycle_count: u64,
+}
+
+impl TraceState {
+    fn from_cpu(cpu: &Cpu) -> TraceState {
+        let dots = cpu.cycle_count * 3;
+        TraceState {
+            program_counter: cpu.program_counter.0,
+            accumulator: cpu.accumulator,
+            x: cpu.x,
+            y: cpu.y,
+            flags: cpu.flags,
+            stack_pointer: cpu.stack_pointer,
+            scanline: (dots / DOTS_PER_SCANLINE) % SCANLINES,
+            dot: dots % DOTS_PER_SCANLINE,
+            cycle_count: cpu.cycle_count,
         }
-        return true;
     }
-    fn ne(&self, other: &Self) -> bool {
-        !self.eq(other)
+
+    //the printed fields, so a mismatch can be pointed at
+    fn fields(&self) -> [String; 9] {
+        [
+            format!("{:04X}", self.program_counter),
+            format!("A:{:02X}", self.accumulator),
+            format!("X:{:02X}", self.x),
+            format!("Y:{:02X}", self.y),
+            format!("P:{:02X}", self.flags),
+            format!("SP:{:02X}", self.stack_pointer),
+            format!("PPU:{:>3},{:>3}", self.scanline, self.dot),
+            format!("CYC:{}", self.cycle_count),
+            //flags spelled out, since a wrong P is the most common mismatch
+            format!("[{}]", flag_names(self.flags)),
+        ]
+    }
+}
+
+impl fmt::Display for TraceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.fields().join(" "))
+    }
+}
+
+fn flag_names(flags: u8) -> String {
+    "NV-BDIZC".chars().enumerate()
+        .map(|(i, name)| if flags & (0x80 >> i) != 0 { name } else { '.' })
+        .collect()
+}
+
+struct LogLine {
+    //address, bytes and disassembly of the next instruction
+    instruction: String,
+    state: TraceState,
+}
+
+//shows the instruction that produced the wrong state, both states and marks the fields that differ
+fn mismatch_report(line: usize, previous: Option<&LogLine>, expected: &LogLine, got: &TraceState) -> String {
+    let expected_fields = expected.state.fields();
+    let got_fields = got.fields();
+
+    let mut markers = String::new();
+    for (e, g) in expected_fields.iter().zip(got_fields.iter()) {
+        let marker = if e == g { ' ' } else { '^' };
+        markers.extend(std::iter::repeat_n(marker, e.len().max(g.len())));
+        markers.push(' ');
+    }
+
+    let pad = |fields: &[String; 9]| {
+        fields.iter().zip(expected_fields.iter().zip(got_fields.iter()))
+            .map(|(v, (e, g))| format!("{:<width$}", v, width = e.len().max(g.len())))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let mut report = format!("states differ at line {}\n", line);
+    match previous {
+        Some(previous) => report += &format!("after      {}\n", previous.instruction),
+        None => report += "at the start of the log\n",
+    }
+    report += &format!("next       {}\n", expected.instruction);
+    report += &format!("expected   {}\n", pad(&expected_fields));
+    report += &format!("got        {}\n", pad(&got_fields));
+    report += &format!("           {}", markers.trim_end());
+    report
+}
+
+fn parse_log_line(line: &str) -> LogLine {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r"^(([0-9A-F]{4}).*?)\s+A:([0-9A-F]{2}) X:([0-9A-F]{2}) Y:([0-9A-F]{2}) P:([0-9A-F]{2}) SP:([0-9A-F]{2}) PPU:\s*(\d+),\s*(\d+) CYC:(\d+)"
+        ).unwrap();
+    }
+
+    let captures = RE.captures(line).unwrap_or_else(|| panic!("can't parse log line {:?}", line));
+    let hex = |i: usize| u16::from_str_radix(captures.get(i).unwrap().as_str(), 16).unwrap();
+    let dec = |i: usize| captures.get(i).unwrap().as_str().parse::<u64>().unwrap();
+
+    LogLine {
+        instruction: captures.get(1).unwrap().as_str().to_string(),
+        state: TraceState {
+            program_counter: hex(2),
+            accumulator: hex(3) as u8,
+            x: hex(4) as u8,
+            y: hex(5) as u8,
+            flags: hex(6) as u8,
+            stack_pointer: hex(7) as u8,
+            scanline: dec(8),
+            dot: dec(9),
+            cycle_count: dec(10),
+        },
     }
 }
 
-fn reference_log_iter() -> impl Iterator<Item = Cpu> {
-    io::BufReader::new(File::open("nestest.log").unwrap())
+fn reference_log_iter() -> impl Iterator<Item = LogLine> {
+    let log = File::open(LOG_PATH).unwrap_or_else(|e| panic!("could not open {}: {}", LOG_PATH, e));
+    io::BufReader::new(log)
         .lines()
-        .filter_map(|x| {
-            lazy_static! {
-                static ref RE: Regex =
-                    Regex::new("(.{4}).*A:(.{2}) X:(.{2}) Y:(.{2}) P:(.{2}) SP:(.{2})").unwrap();
-            }
-
-            let k = x.unwrap();
-            let captures = RE.captures(&k).unwrap();
-
-            let value = Cpu {
-                program_counter: MemoryPtr(
-                    u16::from_str_radix(captures.get(1).unwrap().as_str(), 16).unwrap(),
-                ),
-                accumulator: u8::from_str_radix(captures.get(2).unwrap().as_str(), 16).unwrap(),
-                x: u8::from_str_radix(captures.get(3).unwrap().as_str(), 16).unwrap(),
-                y: u8::from_str_radix(captures.get(4).unwrap().as_str(), 16).unwrap(),
-                flags: u8::from_str_radix(captures.get(5).unwrap().as_str(), 16).unwrap(),
-                stack_pointer: u8::from_str_radix(captures.get(6).unwrap().as_str(), 16).unwrap(),
-                cycle_count: 0,
-                last_instruction: 0,
-                irq_requested: false,
-            };
-            Some(value)
-        })
+        .map(|x| x.unwrap())
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| parse_log_line(&x))
+}
+
+#[test]
+fn test_parse_log_line() {
+    let line = parse_log_line("C72A  A9 00     LDA #$00                        A:00 X:00 Y:00 P:26 SP:FB PPU:  3,  9 CYC:344");
+    assert_eq!(line.instruction, "C72A  A9 00     LDA #$00");
+    assert_eq!(line.state, TraceState {
+        program_counter: 0xc72a,
+        accumulator: 0,
+        x: 0,
+        y: 0,
+        flags: 0x26,
+        stack_pointer: 0xfb,
+        scanline: 3,
+        dot: 9,
+        cycle_count: 344,
+    });
+
+    //unofficial opcodes are marked with a *
+    let line = parse_log_line("E54C  04 A9    *NOP $A9 = 00                    A:AA X:97 Y:4E P:EF SP:F5 PPU:115,328 CYC:13181");
+    assert_eq!(line.instruction, "E54C  04 A9    *NOP $A9 = 00");
+    assert_eq!((line.state.scanline, line.state.dot, line.state.cycle_count), (115, 328, 13181));
+}
+
+#[test]
+fn test_ppu_position() {
+    let mut cpu = Cpu::new();
+    cpu.cycle_count = 344;
+    let state = TraceState::from_cpu(&cpu);
+    assert_eq!((state.scanline, state.dot), (3, 9));
+
+    cpu.cycle_count = 13181;
+    let state = TraceState::from_cpu(&cpu);
+    assert_eq!((state.scanline, state.dot), (115, 328));
+
+    //the first line of the log
+    cpu.cycle_count = 7;
+    let state = TraceState::from_cpu(&cpu);
+    assert_eq!((state.scanline, state.dot), (0, 21));
+}
+
+#[test]
+fn test_mismatch_report() {
+    let expected = parse_log_line("C72A  A9 00     LDA #$00                        A:00 X:00 Y:00 P:26 SP:FB PPU:  3,  9 CYC:344");
+    let previous = parse_log_line("C728  D0 EC     BNE $C716                       A:01 X:00 Y:00 P:24 SP:FB PPU:  3,  3 CYC:342");
+    let mut got = expected.state;
+    got.flags = 0x24;
+    got.cycle_count = 345;
+
+    let report = mismatch_report(12, Some(&previous), &expected, &got);
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines[0], "states differ at line 12");
+    assert_eq!(lines[1], "after      C728  D0 EC     BNE $C716");
+    assert_eq!(lines[2], "next       C72A  A9 00     LDA #$00");
+    assert_eq!(lines[3], "expected   C72A A:00 X:00 Y:00 P:26 SP:FB PPU:  3,  9 CYC:344 [..-..IZ.]");
+    assert_eq!(lines[4], "got        C72A A:00 X:00 Y:00 P:24 SP:FB PPU:  3,  9 CYC:345 [..-..I..]");
+    assert_eq!(lines[5], "                               ^^^^                   ^^^^^^^ ^^^^^^^^^^");
 }
@@ -0,0 +1,536 @@
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+use crate::FRAME_DOTS;
+
+use super::{
+    PPUDrawingContext, PPUMASK_SHOW_BACKGROUND, PPUMASK_SHOW_BACKGROUND_LEFT, PPUMASK_SHOW_SPRITE,
+    PPUMASK_SHOW_SPRITE_LEFT, PPUSTATUS_SPRITE0_HIT, PPUSTATUS_SPRITE_OVERFLOW, PPUSTATUS_VBLANK, Renderer, PPU,
+};
+
+const DOTS_PER_LINE: u16 = 341;
+const LINES: u16 = 262;
+const VBLANK_LINE: u16 = 241;
+const PRERENDER_LINE: u16 = 261;
+
+//not emulated: vblank starts at an instruction boundary (see Nes::start_frame), so there is no $2002 race
+//with the vblank flag, and every frame has the same length, without the skipped dot of odd frames.
+
+//everything the ppu keeps between dots: the position in the frame, the background shift registers
+//and the sprite evaluation. v, t and fine x are the scroll registers in PPUState
+pub struct DotState {
+    line: u16,
+    dot: u16,
+    //dots run since the start of the frame, which starts when the vblank flag is set
+    frame_dot: u64,
+
+    nametable_latch: u8,
+    attribute_latch: u8,
+    pattern_low_latch: u8,
+    pattern_high_latch: u8,
+    pattern_low: u16,
+    pattern_high: u16,
+    attribute_low: u16,
+    attribute_high: u16,
+
+    secondary_oam: [u8; 32],
+    oam_latch: u8,
+    eval_sprite: u8,
+    eval_byte: u8,
+    eval_index: u8,
+    eval_done: bool,
+    sprite0_next: bool,
+
+    //the sprites of the line being drawn, fetched at the end of the line before it.
+    //the hardware has 8 slots, the rest are for unlimited_sprites
+    sprite_count: u8,
+    sprite0_line: bool,
+    sprite_low: [u8; 64],
+    sprite_high: [u8; 64],
+    sprite_attribute: [u8; 64],
+    sprite_x: [u8; 64],
+}
+
+impl DotState {
+    pub fn new() -> DotState {
+        DotState {
+            line: VBLANK_LINE,
+            dot: 1,
+            frame_dot: 0,
+            nametable_latch: 0,
+            attribute_latch: 0,
+            pattern_low_latch: 0,
+            pattern_high_latch: 0,
+            pattern_low: 0,
+            pattern_high: 0,
+            attribute_low: 0,
+            attribute_high: 0,
+            secondary_oam: [0xff; 32],
+            oam_latch: 0,
+            eval_sprite: 0,
+            eval_byte: 0,
+            eval_index: 0,
+            eval_done: false,
+            sprite0_next: false,
+            sprite_count: 0,
+            sprite0_line: false,
+            sprite_low: [0; 64],
+            sprite_high: [0; 64],
+            sprite_attribute: [0; 64],
+            sprite_x: [0; 64],
+        }
+    }
+
+    //the frame starts at dot 1 of the first vblank line, where the vblank flag gets set
+    pub fn start_frame(&mut self) {
+        self.line = VBLANK_LINE;
+        self.dot = 1;
+        self.frame_dot = 0;
+    }
+}
+
+impl PPU {
+    fn rendering_enabled(&self) -> bool {
+        self.current_state.ppumask & (PPUMASK_SHOW_BACKGROUND | PPUMASK_SHOW_SPRITE) != 0
+    }
+
+    //true while the dot renderer is fetching, on the visible lines and the pre-render line
+    pub(super) fn rendering_dots(&self) -> bool {
+        let line = self.dots.line;
+        self.frame_renderer == Renderer::Dot && self.rendering_enabled() && (line < 240 || line == PRERENDER_LINE)
+    }
+}
+
+impl<'a> PPUDrawingContext<'a> {
+    //runs the ppu dot by dot up to the given dot of the frame
+    pub fn run_dots(&mut self, frame_dot: u64) {
+        if self.ppu.frame_renderer != Renderer::Dot {
+            return;
+        }
+
+        let end = frame_dot.min(FRAME_DOTS);
+        while self.ppu.dots.frame_dot < end {
+            self.tick();
+
+            let d = &mut self.ppu.dots;
+            d.frame_dot += 1;
+            d.dot += 1;
+            if d.dot == DOTS_PER_LINE {
+                d.dot = 0;
+                d.line = (d.line + 1) % LINES;
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        let line = self.ppu.dots.line;
+        let dot = self.ppu.dots.dot;
+
+        if line == PRERENDER_LINE && dot == 1 {
+            self.ppu.current_state.ppustatus &= !(PPUSTATUS_VBLANK | PPUSTATUS_SPRITE0_HIT | PPUSTATUS_SPRITE_OVERFLOW);
+        }
+
+        let visible = line < 240;
+        if !visible && line != PRERENDER_LINE {
+            return;
+        }
+
+        if self.rendering_enabled() {
+            self.background_dot(line, dot);
+            if visible {
+                self.evaluate_sprites(line, dot);
+            }
+            self.fetch_sprites(line, dot);
+        }
+
+        if visible && (1..=256).contains(&dot) {
+            self.output_pixel(line, (dot - 1) as u8);
+        }
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        self.ppu.rendering_enabled()
+    }
+
+    fn background_dot(&mut self, line: u16, dot: u16) {
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            let d = &mut self.ppu.dots;
+            d.pattern_low <<= 1;
+            d.pattern_high <<= 1;
+            d.attribute_low <<= 1;
+            d.attribute_high <<= 1;
+        }
+
+        if (1..258).contains(&dot) || (321..338).contains(&dot) {
+            let v = self.ppu.current_state.get_addr();
+            match (dot - 1) % 8 {
+                0 => {
+                    //dot 1 fetches the nametable byte again, the shifters were already loaded at dot 337
+                    if dot != 1 {
+                        self.load_shifters();
+                    }
+                    self.ppu.dots.nametable_latch = self.cartridge.ppu_read(0x2000 | (v & 0x0fff));
+                },
+                2 => {
+                    let attribute = self.cartridge.ppu_read(0x23c0 | (v & 0x0c00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07));
+                    let shift = ((v >> 4) & 4) | (v & 2);
+                    self.ppu.dots.attribute_latch = (attribute >> shift) & 3;
+                },
+                4 => self.ppu.dots.pattern_low_latch = self.cartridge.ppu_read(self.background_pattern_addr(v)),
+                6 => self.ppu.dots.pattern_high_latch = self.cartridge.ppu_read(self.background_pattern_addr(v) + 8),
+                7 => self.ppu.current_state.set_v(increment_coarse_x(v)),
+                _ => {},
+            }
+        }
+
+        if dot == 256 {
+            let v = self.ppu.current_state.get_addr();
+            self.ppu.current_state.set_v(increment_y(v));
+        }
+
+        if dot == 257 {
+            let v = self.ppu.current_state.get_addr();
+            let t = self.ppu.current_state.temp_addr;
+            self.ppu.current_state.set_v((v & !0x041f) | (t & 0x041f));
+        }
+
+        if line == PRERENDER_LINE && (280..=304).contains(&dot) {
+            let v = self.ppu.current_state.get_addr();
+            let t = self.ppu.current_state.temp_addr;
+            self.ppu.current_state.set_v((v & !0x7be0) | (t & 0x7be0));
+        }
+    }
+
+    fn background_pattern_addr(&self, v: u16) -> u16 {
+        let table = ((self.ppu.current_state.ppuctrl & 0x10) as u16) << 8;
+        table + 16 * self.ppu.dots.nametable_latch as u16 + ((v >> 12) & 7)
+    }
+
+    fn load_shifters(&mut self) {
+        let d = &mut self.ppu.dots;
+        d.pattern_low = (d.pattern_low & 0xff00) | d.pattern_low_latch as u16;
+        d.pattern_high = (d.pattern_high & 0xff00) | d.pattern_high_latch as u16;
+        d.attribute_low = (d.attribute_low & 0xff00) | if d.attribute_latch & 1 != 0 { 0xff } else { 0 };
+        d.attribute_high = (d.attribute_high & 0xff00) | if d.attribute_latch & 2 != 0 { 0xff } else { 0 };
+    }
+
+    fn sprite_height(&self) -> u16 {
+        if self.ppu.current_state.ppuctrl & (1 << 5) != 0 { 16 } else { 8 }
+    }
+
+    //secondary oam is cleared on dots 1-64, then one oam byte is read on odd dots and
+    //written to secondary oam on even dots until all 64 sprites were looked at
+    fn evaluate_sprites(&mut self, line: u16, dot: u16) {
+        if (1..=64).contains(&dot) {
+            if dot.is_multiple_of(2) {
+                self.ppu.dots.secondary_oam[(dot as usize - 1) / 2] = 0xff;
+            }
+            return;
+        }
+
+        if !(65..=256).contains(&dot) {
+            return;
+        }
+
+        let height = self.sprite_height();
+        let s = &mut self.ppu.current_state;
+        let d = &mut self.ppu.dots;
+
+        if dot == 65 {
+            d.eval_sprite = 0;
+            d.eval_byte = 0;
+            d.eval_index = 0;
+            d.eval_done = false;
+            d.sprite0_next = false;
+        }
+
+        if dot % 2 == 1 {
+            d.oam_latch = s.oam[d.eval_sprite as usize * 4 + d.eval_byte as usize];
+            return;
+        }
+
+        if d.eval_done {
+            return;
+        }
+
+        let in_range = line.wrapping_sub(d.oam_latch as u16) < height;
+
+        if d.eval_index < 32 {
+            d.secondary_oam[d.eval_index as usize] = d.oam_latch;
+            if d.eval_byte == 0 && !in_range {
+                d.eval_sprite += 1;
+            } else {
+                if d.eval_byte == 0 && d.eval_sprite == 0 {
+                    d.sprite0_next = true;
+                }
+                d.eval_index += 1;
+                d.eval_byte = (d.eval_byte + 1) % 4;
+                if d.eval_byte == 0 {
+                    d.eval_sprite += 1;
+                }
+            }
+        } else if in_range {
+            s.ppustatus |= PPUSTATUS_SPRITE_OVERFLOW;
+            d.eval_done = true;
+        } else {
+            //the hardware bug: the byte index is incremented together with the sprite index,
+            //so tile numbers, attributes and x positions get compared as if they were y positions
+            d.eval_sprite += 1;
+            d.eval_byte = (d.eval_byte + 1) % 4;
+        }
+
+        if d.eval_sprite == 64 {
+            d.eval_sprite = 0;
+            d.eval_done = true;
+        }
+    }
+
+    //dots 257-320 fetch the patterns of the sprites found for the next line, 8 dots per sprite
+    fn fetch_sprites(&mut self, line: u16, dot: u16) {
+        if !(257..=320).contains(&dot) {
+            return;
+        }
+        self.ppu.current_state.oamaddr = 0;
+
+        if dot == 257 {
+            let d = &mut self.ppu.dots;
+            if line == PRERENDER_LINE {
+                d.sprite_count = 0;
+                d.sprite0_line = false;
+            } else {
+                d.sprite_count = d.eval_index / 4;
+                d.sprite0_line = d.sprite0_next;
+            }
+        }
+
+        let slot = (dot as usize - 257) / 8;
+        let step = (dot - 257) % 8;
+        if step != 5 && step != 7 {
+            return;
+        }
+
+        let sprite = &self.ppu.dots.secondary_oam[slot * 4..slot * 4 + 4];
+        let (y, tile, attribute, x) = (sprite[0], sprite[1], sprite[2], sprite[3]);
+        let empty = slot >= self.ppu.dots.sprite_count as usize;
+
+        //empty slots still fetch tile $ff, the pattern is just not used
+        let addr = self.sprite_pattern_addr(line, y, if empty { 0xff } else { tile }, attribute);
+        let pattern = if step == 5 {
+            self.cartridge.ppu_read(addr)
+        } else {
+            self.cartridge.ppu_read(addr + 8)
+        };
+        let pattern = if empty { 0 } else { flip_pattern(pattern, attribute) };
+
+        let d = &mut self.ppu.dots;
+        if step == 5 {
+            d.sprite_low[slot] = pattern;
+        } else {
+            d.sprite_high[slot] = pattern;
+            d.sprite_attribute[slot] = attribute;
+            d.sprite_x[slot] = x;
+        }
+
+        if dot == 320 && line != PRERENDER_LINE && self.ppu.unlimited_sprites && self.ppu.dots.sprite_count == 8 {
+            self.fetch_extra_sprites(line);
+        }
+    }
+
+    //the sprites the hardware had no room for, in oam order after the first 8
+    fn fetch_extra_sprites(&mut self, line: u16) {
+        let height = self.sprite_height();
+        let mut found = 0;
+        for n in 0..64 {
+            let sprite = &self.ppu.current_state.oam[n * 4..n * 4 + 4];
+            let (y, tile, attribute, x) = (sprite[0], sprite[1], sprite[2], sprite[3]);
+            if line.wrapping_sub(y as u16) >= height {
+                continue;
+            }
+            found += 1;
+            if found <= 8 {
+                continue;
+            }
+
+            let addr = self.sprite_pattern_addr(line, y, tile, attribute);
+            let low = flip_pattern(self.cartridge.ppu_read(addr), attribute);
+            let high = flip_pattern(self.cartridge.ppu_read(addr + 8), attribute);
+
+            let d = &mut self.ppu.dots;
+            let slot = d.sprite_count as usize;
+            d.sprite_low[slot] = low;
+            d.sprite_high[slot] = high;
+            d.sprite_attribute[slot] = attribute;
+            d.sprite_x[slot] = x;
+            d.sprite_count += 1;
+        }
+    }
+
+    //the row of the sprite on the next line, 8x16 sprites pick their pattern table with bit 0 of the tile
+    fn sprite_pattern_addr(&self, line: u16, y: u8, tile: u8, attribute: u8) -> u16 {
+        let height = self.sprite_height();
+        let mut row = line.wrapping_sub(y as u16) & (height - 1);
+        if attribute & 0x80 != 0 {
+            row = height - 1 - row;
+        }
+
+        if height == 16 {
+            let table = (tile as u16 & 1) * 0x1000;
+            table + 16 * ((tile & 0xfe) as u16 + row / 8) + row % 8
+        } else {
+            let table = ((self.ppu.current_state.ppuctrl & 0x8) as u16) << 9;
+            table + 16 * tile as u16 + row
+        }
+    }
+
+    fn output_pixel(&mut self, line: u16, x: u8) {
+        let s = &self.ppu.current_state;
+        let d = &self.ppu.dots;
+
+        if !self.rendering_enabled() {
+            //with rendering off the backdrop is shown, or the palette entry v points at
+            let v = s.get_addr() & 0x3fff;
+            let color = if v >= 0x3f00 { s.pallete[(v & 0x1f) as usize] } else { s.pallete[0] };
+            self.framebuffer[line as usize * 256 + x as usize] = color & 0x3f;
+            return;
+        }
+
+        let mut background = 0u8;
+        let mut background_pallete = 0u8;
+        let show_background_left = s.ppumask & PPUMASK_SHOW_BACKGROUND_LEFT != 0;
+        if s.ppumask & PPUMASK_SHOW_BACKGROUND != 0 && (x >= 8 || show_background_left) {
+            let bit = 0x8000 >> (s.ppuscroll.x & 7);
+            background = ((d.pattern_low & bit != 0) as u8) | (((d.pattern_high & bit != 0) as u8) << 1);
+            background_pallete = ((d.attribute_low & bit != 0) as u8) | (((d.attribute_high & bit != 0) as u8) << 1);
+        }
+
+        //the lowest sprite index with an opaque pixel wins, whatever its priority
+        let mut sprite: Option<(usize, u8)> = None;
+        let show_sprite_left = s.ppumask & PPUMASK_SHOW_SPRITE_LEFT != 0;
+        if s.ppumask & PPUMASK_SHOW_SPRITE != 0 && (x >= 8 || show_sprite_left) {
+            for i in 0..d.sprite_count as usize {
+                if x < d.sprite_x[i] || x - d.sprite_x[i] >= 8 {
+                    continue;
+                }
+                let bit = 0x80 >> (x - d.sprite_x[i]);
+                let color = ((d.sprite_low[i] & bit != 0) as u8) | (((d.sprite_high[i] & bit != 0) as u8) << 1);
+                if color != 0 {
+                    sprite = Some((i, color));
+                    break;
+                }
+            }
+        }
+
+        let mut sprite0_hit = false;
+        let color = match sprite {
+            Some((i, color)) => {
+                sprite0_hit = i == 0 && d.sprite0_line && background != 0 && x != 255;
+                let attribute = d.sprite_attribute[i];
+                if background != 0 && attribute & 0x20 != 0 {
+                    s.pallete[(4 * background_pallete + background) as usize]
+                } else {
+                    s.pallete[(16 + 4 * (attribute & 3) + color) as usize]
+                }
+            },
+            None if background != 0 => s.pallete[(4 * background_pallete + background) as usize],
+            None => s.pallete[0],
+        };
+
+        self.framebuffer[line as usize * 256 + x as usize] = color & 0x3f;
+        if sprite0_hit {
+            self.ppu.set_sprite0_flag();
+        }
+    }
+}
+
+fn flip_pattern(pattern: u8, attribute: u8) -> u8 {
+    if attribute & 0x40 != 0 {
+        pattern.reverse_bits()
+    } else {
+        pattern
+    }
+}
+
+pub(super) fn increment_coarse_x(v: u16) -> u16 {
+    if v & 0x1f == 31 {
+        (v & !0x1f) ^ 0x0400
+    } else {
+        v + 1
+    }
+}
+
+//fine y, then coarse y. row 29 is the last row of a nametable and moves to the one below,
+//rows 30 and 31 hold the attributes and wrap inside the same nametable
+pub(super) fn increment_y(v: u16) -> u16 {
+    if v & 0x7000 != 0x7000 {
+        return v + 0x1000;
+    }
+
+    let v = v & !0x7000;
+    let coarse_y = (v & 0x03e0) >> 5;
+    let (coarse_y, v) = match coarse_y {
+        29 => (0, v ^ 0x0800),
+        31 => (0, v),
+        y => (y + 1, v),
+    };
+    (v & !0x03e0) | (coarse_y << 5)
+}
+
+impl SaveState for DotState {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.line);
+        w.u16(self.dot);
+        w.u64(self.frame_dot);
+        w.u8(self.nametable_latch);
+        w.u8(self.attribute_latch);
+        w.u8(self.pattern_low_latch);
+        w.u8(self.pattern_high_latch);
+        w.u16(self.pattern_low);
+        w.u16(self.pattern_high);
+        w.u16(self.attribute_low);
+        w.u16(self.attribute_high);
+        w.bytes(&self.secondary_oam);
+        w.u8(self.oam_latch);
+        w.u8(self.eval_sprite);
+        w.u8(self.eval_byte);
+        w.u8(self.eval_index);
+        w.bool(self.eval_done);
+        w.bool(self.sprite0_next);
+        w.u8(self.sprite_count);
+        w.bool(self.sprite0_line);
+        w.bytes(&self.sprite_low);
+        w.bytes(&self.sprite_high);
+        w.bytes(&self.sprite_attribute);
+        w.bytes(&self.sprite_x);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.line = r.u16()?;
+        self.dot = r.u16()?;
+        self.frame_dot = r.u64()?;
+        self.nametable_latch = r.u8()?;
+        self.attribute_latch = r.u8()?;
+        self.pattern_low_latch = r.u8()?;
+        self.pattern_high_latch = r.u8()?;
+        self.pattern_low = r.u16()?;
+        self.pattern_high = r.u16()?;
+        self.attribute_low = r.u16()?;
+        self.attribute_high = r.u16()?;
+        r.bytes(&mut self.secondary_oam)?;
+        self.oam_latch = r.u8()?;
+        self.eval_sprite = r.u8()?;
+        self.eval_byte = r.u8()?;
+        self.eval_index = r.u8()?;
+        self.eval_done = r.bool()?;
+        self.sprite0_next = r.bool()?;
+        self.sprite_count = r.u8()?;
+        self.sprite0_line = r.bool()?;
+        r.bytes(&mut self.sprite_low)?;
+        r.bytes(&mut self.sprite_high)?;
+        r.bytes(&mut self.sprite_attribute)?;
+        r.bytes(&mut self.sprite_x)?;
+
+        if self.line >= LINES || self.dot >= DOTS_PER_LINE || self.eval_index > 32 || self.sprite_count > 64
+            || self.eval_sprite >= 64 || self.eval_byte >= 4 {
+            return Err(StateError::InvalidData);
+        }
+        Ok(())
+    }
+}
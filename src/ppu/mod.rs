@@ -1,6 +1,10 @@
 use log::debug;
 
 use crate::{cpu::{Cpu}, EventList, FutureEvent, FutureEventType};
+use crate::savestate::{SaveState, StateError, StateReader, StateWriter};
+
+mod dot;
+use dot::DotState;
 
 pub const PPUMASK_SHOW_SPRITE: u8 = 1 << 4;
 pub const PPUMASK_SHOW_SPRITE_LEFT: u8 = 1 << 2;
@@ -12,6 +16,7 @@ const EVENT_TYPE_SCANLINE_END: u32 = 2;
 
 const PPUSTATUS_VBLANK: u8 = 1 << 7;
 const PPUSTATUS_SPRITE0_HIT: u8 = 1 << 6;
+const PPUSTATUS_SPRITE_OVERFLOW: u8 = 1 << 5;
 const PPUCTRL_VRAM_INCREMENT: u8 = 1 << 2;
 const PPUCTRL_VBLANK: u8 = 1 << 7;
 #[derive(Clone, Copy)]
@@ -54,6 +59,20 @@ impl PPUState {
 pub struct PPU {
     pub current_state: PPUState,
     frame_start_cyc: u64,
+    pub renderer: Renderer,
+    //an enhancement: draws every sprite on a line instead of the first 8, the overflow flag still works as usual
+    pub unlimited_sprites: bool,
+    //the renderer drawing the current frame, a new one is picked up at the next vblank
+    frame_renderer: Renderer,
+    dots: DotState,
+}
+
+//the scanline renderer draws a whole line at once and predicts sprite 0 hits, the dot renderer
+//runs the ppu one dot at a time like the hardware. it is slower but gets mid-line effects right
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Renderer {
+    Scanline,
+    Dot,
 }
 
 
@@ -74,35 +93,37 @@ pub struct Scroll {
     y: u8,
 }
 
-pub trait DmaTransferSource {
-    fn read_page_for_oam(&mut self, page: u8, cpu: &mut Cpu) -> [u8; 256];
-}
-
 impl PPU {
     pub fn new() -> PPU {
         PPU { 
             current_state: PPUState::new(),
             frame_start_cyc: 0,
+            renderer: Renderer::Scanline,
+            unlimited_sprites: false,
+            frame_renderer: Renderer::Scanline,
+            dots: DotState::new(),
         }
     }
 
-    pub fn nmi_active(&self) -> bool {
-        self.current_state.ppuctrl & PPUCTRL_VBLANK != 0
+    //the reset line clears ppuctrl, ppumask and the write latch
+    pub fn reset(&mut self) {
+        self.current_state.ppuctrl = 0;
+        self.current_state.ppumask = 0;
+        self.current_state.next_write_latch = Latch::Low;
     }
 
-    pub fn dma_transfer<T: DmaTransferSource + ?Sized >(&mut self, page: u8, source: &mut T, cpu: &mut Cpu) {
-        self.current_state.oam = source.read_page_for_oam(page, cpu);
-        cpu.cycle_count += 513;
+    pub fn nmi_active(&self) -> bool {
+        self.current_state.ppuctrl & PPUCTRL_VBLANK != 0
     }
 
-    pub fn context<'a>(&'a mut self, cart: &'a mut dyn PPUMemorySpace) -> PPUContext {
+    pub fn context<'a>(&'a mut self, cart: &'a mut dyn PPUMemorySpace) -> PPUContext<'a> {
         PPUContext{
             ppu: self,
             cartridge: cart,
         }
     }
 
-    pub fn drawing_context<'a>(&'a mut self, cart: &'a mut dyn PPUMemorySpace, eventlist: &'a mut EventList, framebuffer: &'a mut [u8; 240*256]) -> PPUDrawingContext {
+    pub fn drawing_context<'a>(&'a mut self, cart: &'a mut dyn PPUMemorySpace, eventlist: &'a mut EventList, framebuffer: &'a mut [u8; 240*256]) -> PPUDrawingContext<'a> {
         PPUDrawingContext{
             ppu: self,
             cartridge: cart,
@@ -121,6 +142,45 @@ impl PPU {
 
 }
 
+impl SaveState for PPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        let s = &self.current_state;
+        w.u8(s.ppustatus);
+        w.u8(s.ppuctrl);
+        w.u8(s.ppumask);
+        w.u8(s.oamaddr);
+        w.u8(s.ppuscroll.x);
+        w.u8(s.ppuscroll.y);
+        w.bytes(&s.oam);
+        w.bytes(&s.pallete);
+        w.u8(s.last_read_byte);
+        w.bool(matches!(s.next_write_latch, Latch::High));
+        w.u16(s.temp_addr);
+        w.u64(self.frame_start_cyc);
+        w.bool(self.frame_renderer == Renderer::Dot);
+        self.dots.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        let s = &mut self.current_state;
+        s.ppustatus = r.u8()?;
+        s.ppuctrl = r.u8()?;
+        s.ppumask = r.u8()?;
+        s.oamaddr = r.u8()?;
+        s.ppuscroll.x = r.u8()?;
+        s.ppuscroll.y = r.u8()?;
+        r.bytes(&mut s.oam)?;
+        r.bytes(&mut s.pallete)?;
+        s.last_read_byte = r.u8()?;
+        s.next_write_latch = if r.bool()? { Latch::High } else { Latch::Low };
+        s.temp_addr = r.u16()?;
+        self.frame_start_cyc = r.u64()?;
+        self.frame_renderer = if r.bool()? { Renderer::Dot } else { Renderer::Scanline };
+        self.dots.load_state(r)?;
+        Ok(())
+    }
+}
+
 pub struct PPUContext<'a> {
     cartridge: &'a mut dyn PPUMemorySpace,
     ppu: &'a mut PPU,
@@ -140,10 +200,26 @@ impl<'a> PPUContext<'a> {
             self.cartridge.ppu_write(current_addr, v);
         }
 
-        if self.ppu.current_state.ppuctrl & PPUCTRL_VRAM_INCREMENT != 0 {
-            self.ppu.current_state.set_addr(current_addr.wrapping_add(32));
+        self.increment_addr(current_addr);
+    }
+
+    fn increment_addr(&mut self, addr: u16) {
+        //while rendering, v is the scroll position and gets both the coarse x and the y increment instead
+        if self.ppu.rendering_dots() {
+            self.ppu.current_state.set_v(dot::increment_y(dot::increment_coarse_x(addr)));
+            return;
+        }
+
+        let next = if self.ppu.current_state.ppuctrl & PPUCTRL_VRAM_INCREMENT != 0 {
+            addr.wrapping_add(32)
         } else {
-            self.ppu.current_state.set_addr(current_addr.wrapping_add(1));
+            addr.wrapping_add(1)
+        };
+
+        //the scanline renderer keeps t in sync with v, the dot renderer needs them apart
+        match self.ppu.frame_renderer {
+            Renderer::Scanline => self.ppu.current_state.set_addr(next),
+            Renderer::Dot => self.ppu.current_state.set_v(next),
         }
     }
 
@@ -151,11 +227,7 @@ impl<'a> PPUContext<'a> {
         let ptr = self.ppu.current_state.get_addr();
         let value = self.cartridge.ppu_read(ptr);
 
-        if self.ppu.current_state.ppuctrl & PPUCTRL_VRAM_INCREMENT != 0 {
-            self.ppu.current_state.set_addr(ptr.wrapping_add(32));
-        } else {
-            self.ppu.current_state.set_addr(ptr.wrapping_add(1));
-        }
+        self.increment_addr(ptr);
 
         if ptr <= 0x3eff {
             let old_value = self.ppu.current_state.last_read_byte;
@@ -181,9 +253,15 @@ impl<'a> PPUContext<'a> {
         };
         v
     }
-    pub fn write(&mut self, addr: crate::memory_controller::MemoryPtr, value: u8, _: &mut Cpu) {
+    pub fn write(&mut self, addr: crate::memory_controller::MemoryPtr, value: u8, cpu: &mut Cpu) {
         match addr.0 & 0x7 {
             0 => {
+                //turning nmis on during vblank raises one right away
+                let state = &self.ppu.current_state;
+                if value & PPUCTRL_VBLANK != 0 && state.ppuctrl & PPUCTRL_VBLANK == 0 && state.ppustatus & PPUSTATUS_VBLANK != 0 {
+                    cpu.nmi_pending = true;
+                }
+
                 let mut tmp = parse_addr(self.ppu.current_state.temp_addr);
 
                 tmp.nametable = value & 0x3;
@@ -199,7 +277,7 @@ impl<'a> PPUContext<'a> {
             3 => self.ppu.current_state.oamaddr = value,            
             4 => self.ppu.current_state.write_oam_byte(value),            
             5 => {
-                self.ppu.current_state.write_scroll(value);
+                self.ppu.current_state.write_scroll(value, self.ppu.frame_renderer == Renderer::Scanline);
             },
             6 => {
                 self.ppu.current_state.write_addr(value);
@@ -225,12 +303,15 @@ pub struct PPUDrawingContext<'a> {
 
 impl<'a> PPUDrawingContext<'a> {
     pub fn after_vblank(&mut self) {
-        self.ppu.current_state.ppustatus &= !PPUSTATUS_SPRITE0_HIT;
+        self.ppu.current_state.ppustatus &= !(PPUSTATUS_SPRITE0_HIT | PPUSTATUS_SPRITE_OVERFLOW);
         
-        let s0 = DrawingContext { cartridge: self.cartridge, ppu: &self.ppu.current_state }.draw_scanline(
+        let (s0, overflow) = DrawingContext { cartridge: self.cartridge, ppu: &self.ppu.current_state, unlimited_sprites: self.ppu.unlimited_sprites }.draw_scanline(
             self.framebuffer[..256].as_mut(),
             0,
         );
+        if overflow {
+            self.ppu.current_state.ppustatus |= PPUSTATUS_SPRITE_OVERFLOW;
+        }
         if let Some((x, y)) = s0 {
             self.event_list.add_event(FutureEvent { cycle: (341*(y+22) + x) as u64, tp: FutureEventType::PPU(EVENT_TYPE_SPRITE0)});
         }
@@ -239,6 +320,13 @@ impl<'a> PPUDrawingContext<'a> {
     pub fn set_vblank_flag(&mut self, cyc: u64) {
         self.ppu.current_state.ppustatus |= PPUSTATUS_VBLANK;
         self.ppu.frame_start_cyc = cyc;
+        self.ppu.frame_renderer = self.ppu.renderer;
+
+        //the dot renderer runs along with the cpu instead of at events
+        if self.ppu.frame_renderer == Renderer::Dot {
+            self.ppu.dots.start_frame();
+            return;
+        }
 
         self.event_list.add_event(FutureEvent { 
             cycle: 7502, tp: FutureEventType::PPU(EVENT_TYPE_VBLANKEND),
@@ -276,10 +364,13 @@ impl<'a> PPUDrawingContext<'a> {
 
                     if scanline >=1 {
                         
-                        let s0 = DrawingContext { cartridge: self.cartridge, ppu: &self.ppu.current_state }.draw_scanline(
+                        let (s0, overflow) = DrawingContext { cartridge: self.cartridge, ppu: &self.ppu.current_state, unlimited_sprites: self.ppu.unlimited_sprites }.draw_scanline(
                             self.framebuffer[scanline as usize*256..(scanline as usize+1)*256].as_mut(), 
                             scanline as u8
                         );
+                        if overflow {
+                            self.ppu.current_state.ppustatus |= PPUSTATUS_SPRITE_OVERFLOW;
+                        }
                         if let Some((x, y)) = s0 {
                             self.event_list.add_event(FutureEvent { cycle: (341*(y+22) + x) as u64, tp: FutureEventType::PPU(EVENT_TYPE_SPRITE0)});
                         }
@@ -308,12 +399,14 @@ impl<'a> PPUDrawingContext<'a> {
 
 pub struct DrawingContext<'a> {
     cartridge: &'a dyn PPUMemorySpace,
-    ppu: &'a PPUState
+    ppu: &'a PPUState,
+    unlimited_sprites: bool,
 }
 
 impl<'a> DrawingContext<'a> {
 
-    fn draw_scanline(&mut self, framebuffer: &mut [u8], scanline: u8) -> Option<(u16, u16)> {
+    //returns where sprite 0 hits the background, and if the line has more than 8 sprites
+    fn draw_scanline(&mut self, framebuffer: &mut [u8], scanline: u8) -> (Option<(u16, u16)>, bool) {
         self.draw_background(
             framebuffer,
             self.ppu.ppuscroll.x as u16, 
@@ -322,12 +415,13 @@ impl<'a> DrawingContext<'a> {
             1
         );
 
-        self.draw_sprites(
-            framebuffer,
-            256,
-            1,
-            scanline
-        )
+        let result = self.draw_sprites(framebuffer, scanline);
+
+        //the opaque background marker is only needed while the line is composited
+        for v in framebuffer.iter_mut() {
+            *v &= 0x3f;
+        }
+        result
     }
 
     fn draw_background(&self, output: &mut [u8], x: u16, y: u16, w: u16, h: u16) {
@@ -365,90 +459,125 @@ impl<'a> DrawingContext<'a> {
                 current_pallete, 
                 (self.ppu.ppuctrl & 0x10) >> 4,
                 tile, 
-                false, 
-                false, 
                 (self.ppu.ppumask & PPUMASK_SHOW_BACKGROUND_LEFT) == 0,
-                true,
-                false,
                 output, w, h,
-                0
             );
         }
     }
 
-    fn draw_sprites(&self, output: &mut [u8], w: u16, h: u16, scanline: u8) -> Option<(u16, u16)> {
+    //finds the sprites on the line like the hardware fills secondary oam: the first 8 in oam order.
+    //after that it keeps looking for a ninth one to set the overflow flag, but with a bug that
+    //also moves to the next byte of each sprite, so it compares tiles, attributes and x positions as y
+    fn evaluate_sprites(&self, scanline: u8) -> (Vec<usize>, bool) {
+        let height = if self.ppu.ppuctrl & (1 << 5) != 0 { 16 } else { 8 };
+        let in_range = |y: u8| (scanline as u16).wrapping_sub(y as u16) < height;
+
+        let mut sprites = Vec::with_capacity(8);
+        let mut n = 0;
+        while n < 64 && sprites.len() < 8 {
+            if in_range(self.ppu.oam[n * 4]) {
+                sprites.push(n);
+            }
+            n += 1;
+        }
+
+        let mut overflow = false;
+        let mut m = 0;
+        while n < 64 {
+            if in_range(self.ppu.oam[n * 4 + m]) {
+                overflow = true;
+                break;
+            }
+            n += 1;
+            m = (m + 1) % 4;
+        }
+
+        if self.unlimited_sprites {
+            sprites = (0..64).filter(|i| in_range(self.ppu.oam[i * 4])).collect();
+        }
+
+        (sprites, overflow)
+    }
+
+    //sprites are combined with the background in two steps, like the hardware does. the multiplexer
+    //picks the opaque pixel of the lowest sprite index first, whatever its priority, and only then the
+    //priority bit of that sprite decides between it and an opaque background pixel. so a behind-background
+    //sprite still hides the sprites after it where the background covers it
+    fn draw_sprites(&self, output: &mut [u8], scanline: u8) -> (Option<(u16, u16)>, bool) {
+        //sprites are only evaluated while rendering is on
+        if self.ppu.ppumask & (PPUMASK_SHOW_BACKGROUND | PPUMASK_SHOW_SPRITE) == 0 {
+            return (None, false);
+        }
+
+        let (sprites, overflow) = self.evaluate_sprites(scanline);
+
+        if self.ppu.ppumask & PPUMASK_SHOW_SPRITE == 0 {
+            return (None, overflow);
+        }
+
+        let mask_left = (self.ppu.ppumask & PPUMASK_SHOW_SPRITE_LEFT) == 0;
         let mut detected_sprite0: Option<(u16, u16)> = None;
 
-        if self.ppu.ppumask & PPUMASK_SHOW_SPRITE != 0 {
-            let sprite_size = self.ppu.ppuctrl & (1 << 5);
-            for i in (0..64).rev() {
-                let pos_y = self.ppu.oam[i * 4];
-                let tile  = self.ppu.oam[i * 4 + 1];
-                let byte3 = self.ppu.oam[i * 4 + 2];
-                let pos_x = self.ppu.oam[i * 4 + 3];
-                if sprite_size == 0 {
-                    //8x8 sprite
-                    let s0 = self.draw_tile_section(
-                        pos_x as i16, 
-                        pos_y as i16 - scanline as i16, 
-                        (byte3 & 0x3) + 4, 
-                        (self.ppu.ppuctrl & 0x8) >> 3,
-                        tile, 
-                        (byte3 & 0x40) != 0, 
-                        (byte3 & 0x80) != 0, 
-                        (self.ppu.ppumask & PPUMASK_SHOW_SPRITE_LEFT) == 0,
-                        false, 
-                        i == 0,
-                        output, w, h,
-                        scanline,
-                    );
-
-                    if let None = detected_sprite0 {
-                        detected_sprite0 = s0;
-                    }
-                } else {
-                    let flipy = (byte3 & 0x80) != 0;
-                    //8x16 sprite
-                    let s0 = self.draw_tile_section(
-                        pos_x as i16, 
-                        pos_y as i16 - scanline as i16, 
-                        (byte3 & 0x3) + 4, 
-                        tile & 0x1,
-                        if !flipy {tile & !0x1} else {tile | 0x1}, 
-                        (byte3 & 0x40) != 0, 
-                        flipy, 
-                        (self.ppu.ppumask & PPUMASK_SHOW_SPRITE_LEFT) == 0,
-                        false, 
-                        i == 0,
-                        output, w, h,
-                        scanline,
-                    );
-                    if let None = detected_sprite0 {
-                        detected_sprite0 = s0;
-                    }
-                    let s0 = self.draw_tile_section(
-                        pos_x as i16, 
-                        pos_y as i16 + 8 - scanline as i16, 
-                        (byte3 & 0x3) + 4, 
-                        tile & 0x1,
-                        if flipy {tile & !0x1} else {tile | 0x1},  
-                        (byte3 & 0x40) != 0, 
-                        flipy, 
-                        (self.ppu.ppumask & PPUMASK_SHOW_SPRITE_LEFT) == 0,
-                        false, 
-                        i == 0,
-                        output, w, h,
-                        scanline,
-                    );
-                    if let None = detected_sprite0 {
-                        detected_sprite0 = s0;
-                    }
+        //color and behind-background flag of the frontmost opaque sprite pixel
+        let mut sprite_layer: [Option<(u8, bool)>; 256] = [None; 256];
+
+        for i in sprites {
+            let pos_y = self.ppu.oam[i * 4];
+            let tile = self.ppu.oam[i * 4 + 1];
+            let byte3 = self.ppu.oam[i * 4 + 2];
+            let pos_x = self.ppu.oam[i * 4 + 3];
+
+            let (byte1, byte2) = self.sprite_row(tile, byte3, scanline.wrapping_sub(pos_y));
+
+            for a in 0..8u16 {
+                let screen_pos_x = pos_x as u16 + a;
+                if screen_pos_x >= 256 || (mask_left && screen_pos_x < 8) {
+                    continue;
+                }
+
+                let mask = if (byte3 & 0x40) != 0 { 1 << a } else { 0x80 >> a };
+                let color_index = ((byte1 & mask != 0) as u8) | (((byte2 & mask != 0) as u8) << 1);
+                if color_index == 0 {
+                    continue;
+                }
+
+                //the top bit marks opaque background pixels
+                let background_opaque = output[screen_pos_x as usize] & 0x80 != 0;
+                if i == 0 && detected_sprite0.is_none() && background_opaque && screen_pos_x != 255 {
+                    detected_sprite0 = Some((screen_pos_x, scanline as u16));
+                }
+
+                let pixel = &mut sprite_layer[screen_pos_x as usize];
+                if pixel.is_none() {
+                    let color = self.ppu.pallete[(4 * ((byte3 & 0x3) + 4) + color_index) as usize];
+                    *pixel = Some((color, (byte3 & 0x20) != 0));
                 }
-                
             }
         }
-        
-        detected_sprite0
+
+        for (v, pixel) in output.iter_mut().zip(sprite_layer.iter()) {
+            if let Some((color, behind_background)) = *pixel {
+                if !(behind_background && *v & 0x80 != 0) {
+                    *v = color;
+                }
+            }
+        }
+
+        (detected_sprite0, overflow)
+    }
+
+    //the two pattern bytes of a row of a sprite, 8x16 sprites pick their pattern table with bit 0 of the tile
+    fn sprite_row(&self, tile: u8, byte3: u8, row: u8) -> (u8, u8) {
+        let flipy = (byte3 & 0x80) != 0;
+        let addr = if self.ppu.ppuctrl & (1 << 5) == 0 {
+            let row = if flipy { 7 - row } else { row } as u16;
+            0x1000 * ((self.ppu.ppuctrl & 0x8) >> 3) as u16 + 16 * tile as u16 + row
+        } else {
+            let row = if flipy { 15 - row } else { row } as u16;
+            0x1000 * (tile & 0x1) as u16 + 16 * ((tile & !0x1) as u16 + row / 8) + row % 8
+        };
+
+        (self.cartridge.ppu_read(addr), self.cartridge.ppu_read(addr + 8))
     }
 
     fn draw_tile_section(&self, 
@@ -457,18 +586,11 @@ impl<'a> DrawingContext<'a> {
         pallete: u8, 
         pattern_t: u8, 
         tile: u8, 
-        flipx: bool, 
-        flipy: bool, 
         mask_left: bool, 
-        background: bool, 
-        sprite0: bool, 
         framebuffer: &mut [u8],
         framebuffer_w: u16,
         framebuffer_h: u16,
-        scanline: u8,
-    ) -> Option<(u16, u16)>{
-        let mut detected_sprite0: Option<(u16, u16)> = None;
-
+    ) {
         for i in 0..8 {
             let byte1 = self.cartridge.ppu_read(i + 16*(tile as u16) + 0x1000 * (pattern_t as u16));
             let byte2 = self.cartridge.ppu_read(i + 16*(tile as u16) + 0x1000 * (pattern_t as u16) + 8);
@@ -483,8 +605,8 @@ impl<'a> DrawingContext<'a> {
                     color_index |= 2;
                 }
 
-                let screen_pos_x = if flipx { (x as i32) + 7 - (a as i32)} else {(x as i32) + (a as i32)};
-                let screen_pos_y = if flipy { (y as i32) + 7 - (i as i32)} else {(y as i32) + (i as i32)};
+                let screen_pos_x = (x as i32) + (a as i32);
+                let screen_pos_y = (y as i32) + (i as i32);
 
                 if screen_pos_x < 0 || screen_pos_y < 0 || screen_pos_x >= framebuffer_w as i32 || screen_pos_y >= framebuffer_h as i32 {
                     continue;
@@ -500,24 +622,10 @@ impl<'a> DrawingContext<'a> {
 
                 let color = self.ppu.pallete[(4 * pallete as u16 + color_index) as usize];
 
-                if sprite0 {
-                    if let None = detected_sprite0 {
-                        if framebuffer[(256 * screen_pos_y + screen_pos_x) as usize] & 0x80 != 0 {
-                            detected_sprite0 = Some((screen_pos_x as u16, scanline as u16 + screen_pos_y as u16));
-                        }
-                    }
-                }
-
-                framebuffer[(256 * screen_pos_y + screen_pos_x) as usize] = color;
-                if background {
-                    //uses the top bit to mark this as an opaque background pixel
-                    framebuffer[(256 * screen_pos_y + screen_pos_x) as usize] |= 0x80;
-                }
-                
+                //uses the top bit to mark this as an opaque background pixel
+                framebuffer[(256 * screen_pos_y + screen_pos_x) as usize] = color | 0x80;
             }
         }
-
-        detected_sprite0
     }
 
 }
@@ -557,7 +665,16 @@ impl PPUState {
         self.temp_addr = data.addr();
     }
 
-    fn write_scroll(&mut self, v: u8) {
+    //sets v without touching t or fine x
+    fn set_v(&mut self, v: u16) {
+        let data = parse_addr(v);
+        self.ppuscroll.x = data.x_pos | (self.ppuscroll.x & 0x7);
+        self.ppuctrl = (self.ppuctrl & !0x3) | data.nametable;
+        self.ppuscroll.y = data.y_pos;
+    }
+
+    //the scanline renderer also copies coarse x to v on the second write
+    fn write_scroll(&mut self, v: u8, copy_coarse_x: bool) {
         match self.next_write_latch {
             Latch::Low => {
                 //self.ppuscroll.x = v;
@@ -576,7 +693,9 @@ impl PPUState {
                 a.y_pos = v;
                 self.temp_addr = a.addr();
 
-                self.ppuscroll.x = (a.x_pos & (!0x7)) | (self.ppuscroll.x & 0x7);
+                if copy_coarse_x {
+                    self.ppuscroll.x = (a.x_pos & (!0x7)) | (self.ppuscroll.x & 0x7);
+                }
                 self.next_write_latch = Latch::Low;
             }
         }
@@ -631,3 +750,6 @@ fn parse_addr(v: u16) -> PPURegister {
          nametable: (((v >> 10) & 0x3) as u8) 
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,194 @@
+use std::cell::RefCell;
+
+use super::dot::{increment_coarse_x, increment_y};
+use super::*;
+use crate::memory_controller::MemoryPtr;
+
+#[test]
+fn test_increment_coarse_x() {
+    assert_eq!(increment_coarse_x(0x0000), 0x0001);
+    //the last column moves to the horizontally adjacent nametable
+    assert_eq!(increment_coarse_x(0x001f), 0x0400);
+    assert_eq!(increment_coarse_x(0x041f), 0x0000);
+}
+
+#[test]
+fn test_increment_y() {
+    assert_eq!(increment_y(0x0000), 0x1000);
+    assert_eq!(increment_y(0x7000), 0x0020);
+    //row 29 moves to the nametable below, rows 30 and 31 wrap in place
+    assert_eq!(increment_y(0x73a0), 0x0800);
+    assert_eq!(increment_y(0x7ba0), 0x0000);
+    assert_eq!(increment_y(0x73e0), 0x0000);
+}
+
+#[test]
+fn test_scroll_registers() {
+    let mut state = PPUState::new();
+
+    //$2005 sets t and fine x, v is only loaded by the second $2006 write
+    state.write_scroll(0x7d, false);
+    state.write_scroll(0x5e, false);
+    assert_eq!(state.temp_addr, 0x616f);
+    assert_eq!(state.ppuscroll.x & 7, 5);
+    assert_eq!(state.get_addr(), 0);
+
+    state.write_addr(0x23);
+    state.write_addr(0x45);
+    assert_eq!(state.get_addr(), 0x2345);
+
+    //updating v keeps fine x and t
+    state.set_v(0x0001);
+    assert_eq!(state.get_addr(), 0x0001);
+    assert_eq!(state.ppuscroll.x & 7, 5);
+    assert_eq!(state.temp_addr, 0x2345);
+}
+
+struct NoMemory;
+
+impl PPUMemorySpace for NoMemory {
+    fn ppu_write(&mut self, _: u16, _: u8) {}
+    fn ppu_read(&self, _: u16) -> u8 {
+        0
+    }
+}
+
+//sprites on the given line and the overflow flag, for oam holding the given sprites followed by empty ones
+fn evaluate(sprites: &[[u8; 4]], scanline: u8, unlimited_sprites: bool) -> (Vec<usize>, bool) {
+    let mut state = PPUState::new();
+    state.oam = [0xff; 256];
+    for (i, sprite) in sprites.iter().enumerate() {
+        state.oam[i * 4..i * 4 + 4].copy_from_slice(sprite);
+    }
+
+    DrawingContext { cartridge: &NoMemory, ppu: &state, unlimited_sprites }.evaluate_sprites(scanline)
+}
+
+#[test]
+fn test_sprite_evaluation() {
+    let on_line = [10, 0, 0, 0];
+    let elsewhere = [100, 0, 0, 0];
+
+    assert_eq!(evaluate(&[on_line, elsewhere, on_line], 12, false), (vec![0, 2], false));
+
+    //a ninth sprite is left out and sets the overflow flag
+    let nine = [on_line; 9];
+    assert_eq!(evaluate(&nine, 12, false), ((0..8).collect(), true));
+    assert_eq!(evaluate(&nine, 12, true), ((0..9).collect(), true));
+}
+
+#[test]
+fn test_sprite_overflow_bug() {
+    let on_line = [10, 0, 0, 0];
+
+    //after 8 sprites the search also steps through the bytes, so the tile of sprite 9 is taken for its y
+    let mut sprites = vec![on_line; 8];
+    sprites.push([100, 0, 0, 0]);
+    sprites.push([100, 10, 0, 0]);
+    assert!(evaluate(&sprites, 12, false).1);
+
+    //and a ninth sprite on the line can be missed
+    let mut sprites = vec![on_line; 8];
+    sprites.push([100, 0, 0, 0]);
+    sprites.push([10, 0, 0, 0]);
+    assert!(!evaluate(&sprites, 12, false).1);
+}
+
+//every pattern byte is set, so sprites are opaque over their whole 8 pixels
+struct SolidPatterns;
+
+impl PPUMemorySpace for SolidPatterns {
+    fn ppu_write(&mut self, _: u16, _: u8) {}
+    fn ppu_read(&self, _: u16) -> u8 {
+        0xff
+    }
+}
+
+#[test]
+fn test_sprite_priority() {
+    let mut state = PPUState::new();
+    state.ppumask = PPUMASK_SHOW_BACKGROUND | PPUMASK_SHOW_BACKGROUND_LEFT | PPUMASK_SHOW_SPRITE | PPUMASK_SHOW_SPRITE_LEFT;
+    state.pallete[16 + 3] = 0x16;
+    state.pallete[20 + 3] = 0x2a;
+    state.oam = [0xff; 256];
+    //sprite 0 is behind the background, sprite 1 in front of it and overlapping sprite 0 by 4 pixels
+    state.oam[0..8].copy_from_slice(&[10, 0, 0x20, 0, 10, 0, 0x01, 4]);
+    //sprite 2 is behind the background too, but over the backdrop
+    state.oam[8..12].copy_from_slice(&[10, 0, 0x20, 100]);
+
+    //the first 64 pixels are opaque background
+    let mut line = [0x0f; 256];
+    for v in line[..64].iter_mut() {
+        *v = 0x30 | 0x80;
+    }
+
+    let context = DrawingContext { cartridge: &SolidPatterns, ppu: &state, unlimited_sprites: false };
+    let (sprite0, overflow) = context.draw_sprites(&mut line, 11);
+    assert_eq!(sprite0, Some((0, 11)));
+    assert!(!overflow);
+
+    assert_eq!(line[0..4], [0x30 | 0x80; 4]);
+    //where both sprites are opaque, sprite 0 wins and its priority hides sprite 1 as well
+    assert_eq!(line[4..8], [0x30 | 0x80; 4]);
+    assert_eq!(line[8..12], [0x2a; 4]);
+    assert_eq!(line[12], 0x30 | 0x80);
+    assert_eq!(line[100..108], [0x16; 8]);
+    assert_eq!(line[108], 0x0f);
+}
+
+//keeps the addresses the ppu reads
+struct RecordedReads(RefCell<Vec<u16>>);
+
+impl PPUMemorySpace for RecordedReads {
+    fn ppu_write(&mut self, _: u16, _: u8) {}
+    fn ppu_read(&self, addr: u16) -> u8 {
+        self.0.borrow_mut().push(addr);
+        0
+    }
+}
+
+//a dot ppu showing the background, run up to the given dot of the frame
+fn run_dot_ppu(ppu: &mut PPU, memory: &mut RecordedReads, frame_dot: u64) {
+    let mut events = EventList::new();
+    let mut framebuffer = [0; 240 * 256];
+    let mut context = ppu.drawing_context(memory, &mut events, &mut framebuffer);
+    if frame_dot == 0 {
+        context.ppu.renderer = Renderer::Dot;
+        context.ppu.current_state.ppumask = PPUMASK_SHOW_BACKGROUND;
+        context.set_vblank_flag(0);
+    }
+    context.run_dots(frame_dot);
+}
+
+//the frame starts at dot 1 of line 241, so line 0 starts 21 lines later
+const LINE_0: u64 = 21 * 341 - 1;
+
+#[test]
+fn test_dot_renderer_fetch_at_dot_1() {
+    let mut ppu = PPU::new();
+    let mut memory = RecordedReads(RefCell::new(Vec::new()));
+    run_dot_ppu(&mut ppu, &mut memory, 0);
+    run_dot_ppu(&mut ppu, &mut memory, LINE_0 + 1);
+    memory.0.borrow_mut().clear();
+
+    //the first two tiles were fetched on the line before, dot 1 fetches the nametable byte of the third
+    run_dot_ppu(&mut ppu, &mut memory, LINE_0 + 2);
+    assert_eq!(*memory.0.borrow(), [0x2002]);
+}
+
+#[test]
+fn test_ppudata_increment_while_rendering() {
+    let mut ppu = PPU::new();
+    let mut memory = RecordedReads(RefCell::new(Vec::new()));
+    run_dot_ppu(&mut ppu, &mut memory, 0);
+    run_dot_ppu(&mut ppu, &mut memory, LINE_0 + 2);
+    assert_eq!(ppu.current_state.get_addr(), 0x0002);
+
+    //a $2007 access increments coarse x and y at the same time, whatever the increment in $2000
+    ppu.context(&mut memory).read(MemoryPtr(0x2007));
+    assert_eq!(ppu.current_state.get_addr(), 0x1003);
+
+    ppu.current_state.ppumask = 0;
+    ppu.context(&mut memory).read(MemoryPtr(0x2007));
+    assert_eq!(ppu.current_state.get_addr(), 0x1004);
+}
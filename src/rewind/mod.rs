@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+
+//ring buffer of console snapshots for rewinding.
+//only the newest snapshot is kept whole, every older one is stored as the difference to the snapshot
+//after it, so the oldest entries can be dropped without touching the rest.
+//differences are xor'ed and the runs of zeros between changed bytes are left out
+pub struct RewindBuffer {
+    interval: u32,
+    capacity: usize,
+    frames_since_snapshot: u32,
+
+    newest: Option<Vec<u8>>,
+    //oldest first
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    //keeps up to `capacity` snapshots, one every `interval` frames
+    pub fn new(interval: u32, capacity: usize) -> RewindBuffer {
+        RewindBuffer {
+            interval: interval.max(1),
+            capacity: capacity.max(1),
+            frames_since_snapshot: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    //called once per emulated frame, `snapshot` is only invoked on the frames that get stored
+    pub fn frame<F: FnOnce() -> Vec<u8>>(&mut self, snapshot: F) {
+        if self.frames_since_snapshot == 0 {
+            self.push(snapshot());
+        }
+        self.frames_since_snapshot = (self.frames_since_snapshot + 1) % self.interval;
+    }
+
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.newest.take() {
+            self.deltas.push_back(encode_delta(&state, &previous));
+        }
+        self.newest = Some(state);
+
+        while self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    //removes and returns the newest snapshot
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        self.newest = self.deltas.pop_back().map(|delta| decode_delta(&newest, &delta));
+        //the restored state is stored again by the next frame, so it stays reachable
+        self.frames_since_snapshot = 0;
+        Some(newest)
+    }
+
+    pub fn len(&self) -> usize {
+        self.newest.iter().count() + self.deltas.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    #[allow(dead_code)]
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.frames_since_snapshot = 0;
+    }
+
+    //total size of the stored snapshots in bytes
+    #[allow(dead_code)]
+    pub fn memory_usage(&self) -> usize {
+        self.newest.as_ref().map_or(0, |v| v.len()) + self.deltas.iter().map(|v| v.len()).sum::<usize>()
+    }
+}
+
+//delta format: the length of `target` followed by blocks of
+//(number of unchanged bytes, number of changed bytes, changed bytes xor'ed with `base`), counts are leb128.
+//bytes of `target` past the end of `base` are xor'ed with zero
+pub fn encode_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, target.len());
+
+    let diff = |i: usize| target[i] ^ base.get(i).copied().unwrap_or(0);
+    let mut i = 0;
+    while i < target.len() {
+        let start = i;
+        while i < target.len() && diff(i) == 0 {
+            i += 1;
+        }
+        let unchanged = i - start;
+        if i == target.len() {
+            break;
+        }
+
+        //a block of changes ends at the first run of zeros long enough to be worth skipping
+        let changes_start = i;
+        let mut zeros = 0;
+        while i < target.len() && zeros < 4 {
+            zeros = if diff(i) == 0 { zeros + 1 } else { 0 };
+            i += 1;
+        }
+        let changes_end = i - zeros;
+
+        write_varint(&mut out, unchanged);
+        write_varint(&mut out, changes_end - changes_start);
+        out.extend((changes_start..changes_end).map(diff));
+        i = changes_end;
+    }
+
+    out
+}
+
+//inverse of encode_delta, returns `target`
+pub fn decode_delta(base: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+
+    let mut out: Vec<u8> = (0..len).map(|i| base.get(i).copied().unwrap_or(0)).collect();
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let changed = read_varint(delta, &mut pos);
+        for (v, d) in out[i..i + changed].iter_mut().zip(delta[pos..pos + changed].iter()) {
+            *v ^= *d;
+        }
+        pos += changed;
+        i += changed;
+    }
+
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut v: usize) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut v = 0;
+    let mut shift = 0;
+    loop {
+        let b = data[*pos];
+        *pos += 1;
+        v |= ((b & 0x7f) as usize) << shift;
+        if b & 0x80 == 0 {
+            return v;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests;
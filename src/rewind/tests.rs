@@ -0,0 +1,78 @@
+use super::{decode_delta, encode_delta, RewindBuffer};
+
+fn state(seed: u8, len: usize) -> Vec<u8> {
+    (0..len).map(|i| if i % 97 == 0 { seed.wrapping_mul(i as u8) } else { i as u8 }).collect()
+}
+
+#[test]
+fn test_delta_round_trip() {
+    let base = state(1, 5000);
+    let cases = [
+        state(1, 5000),
+        state(2, 5000),
+        state(3, 4000),
+        state(4, 6000),
+        vec![],
+        vec![0xff; 300],
+    ];
+
+    for target in cases.iter() {
+        let delta = encode_delta(&base, target);
+        assert_eq!(&decode_delta(&base, &delta), target);
+        assert_eq!(decode_delta(target, &encode_delta(target, &base)), base);
+    }
+
+    //small changes give small deltas
+    assert!(encode_delta(&base, &state(2, 5000)).len() < 500);
+    assert_eq!(encode_delta(&base, &base).len(), 2);
+}
+
+#[test]
+fn test_pop_order() {
+    let mut buffer = RewindBuffer::new(1, 10);
+    for i in 0..5 {
+        buffer.push(state(i, 1000));
+    }
+    assert_eq!(buffer.len(), 5);
+
+    for i in (0..5).rev() {
+        assert_eq!(buffer.pop(), Some(state(i, 1000)));
+    }
+    assert_eq!(buffer.pop(), None);
+    assert!(buffer.is_empty());
+}
+
+#[test]
+fn test_capacity() {
+    let mut buffer = RewindBuffer::new(1, 4);
+    for i in 0..10 {
+        buffer.push(state(i, 1000));
+    }
+    assert_eq!(buffer.len(), 4);
+
+    for i in (6..10).rev() {
+        assert_eq!(buffer.pop(), Some(state(i, 1000)));
+    }
+    assert_eq!(buffer.pop(), None);
+}
+
+#[test]
+fn test_interval() {
+    let mut buffer = RewindBuffer::new(3, 100);
+    let mut taken = 0;
+    for i in 0..10 {
+        buffer.frame(|| {
+            taken += 1;
+            state(i, 100)
+        });
+    }
+    //frames 0, 3, 6 and 9
+    assert_eq!(taken, 4);
+    assert_eq!(buffer.pop(), Some(state(9, 100)));
+
+    //a restored state is stored again by the next frame
+    buffer.frame(|| state(9, 100));
+    assert_eq!(buffer.len(), 4);
+    assert_eq!(buffer.pop(), Some(state(9, 100)));
+    assert_eq!(buffer.pop(), Some(state(6, 100)));
+}
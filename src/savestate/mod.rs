@@ -0,0 +1,168 @@
+use std::{fmt, path::{Path, PathBuf}};
+
+//format: magic, version and then every component in a fixed order, all integers are little endian.
+//the version has to be bumped whenever the layout of any component changes
+const MAGIC: &[u8; 8] = b"NESMUSAV";
+pub const STATE_VERSION: u32 = 7;
+
+#[derive(Debug)]
+pub enum StateError {
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    UnexpectedEnd,
+    //a value that can't be produced by the emulator, or data for a different cartridge
+    InvalidData,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::InvalidMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(v) => write!(f, "unsupported save state version {}", v),
+            StateError::UnexpectedEnd => write!(f, "save state is truncated"),
+            StateError::InvalidData => write!(f, "save state is corrupted or from a different game"),
+        }
+    }
+}
+
+pub trait SaveState {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError>;
+}
+
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        StateWriter::new()
+    }
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        StateWriter { data }
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn u8(&mut self, v: u8) {
+        self.data.push(v);
+    }
+
+    pub fn bool(&mut self, v: bool) {
+        self.data.push(v as u8);
+    }
+
+    pub fn u16(&mut self, v: u16) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, v: u32) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, v: u64) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub fn i64(&mut self, v: i64) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    //fixed size data, the reader has to know the length
+    pub fn bytes(&mut self, v: &[u8]) {
+        self.data.extend_from_slice(v);
+    }
+
+    //length prefixed data
+    pub fn vec(&mut self, v: &[u8]) {
+        self.u32(v.len() as u32);
+        self.bytes(v);
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Result<StateReader<'a>, StateError> {
+        let mut r = StateReader { data };
+        if r.take(MAGIC.len())? != MAGIC {
+            return Err(StateError::InvalidMagic);
+        }
+        let version = r.u32()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        Ok(r)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < n {
+            return Err(StateError::UnexpectedEnd);
+        }
+        let (v, rest) = self.data.split_at(n);
+        self.data = rest;
+        Ok(v)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::InvalidData),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, StateError> {
+        Ok(i64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn bytes(&mut self, out: &mut [u8]) -> Result<(), StateError> {
+        out.copy_from_slice(self.take(out.len())?);
+        Ok(())
+    }
+
+    //reads data written with StateWriter::vec into a buffer that must already have the right size
+    pub fn vec_exact(&mut self, out: &mut [u8]) -> Result<(), StateError> {
+        if self.u32()? as usize != out.len() {
+            return Err(StateError::InvalidData);
+        }
+        self.bytes(out)
+    }
+}
+
+//slot files live next to the rom: game.nes -> game.st1
+pub fn slot_path<P: AsRef<Path>>(rom_path: P, slot: u8) -> PathBuf {
+    rom_path.as_ref().with_extension(format!("st{}", slot))
+}
+
+#[cfg(test)]
+mod tests;
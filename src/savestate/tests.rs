@@ -0,0 +1,99 @@
+use super::{slot_path, StateError, STATE_VERSION};
+use crate::{mappers::{chr::ChrMemory, nrom}, Nes};
+
+//plays a tone whose pitch and the backdrop color change on every loop iteration
+const PROGRAM: [u8; 45] = [
+    0xa9, 0x01, 0x8d, 0x15, 0x40, //lda #$01, sta $4015
+    0xa9, 0xbf, 0x8d, 0x00, 0x40, //lda #$bf, sta $4000
+    0xa9, 0x08, 0x8d, 0x03, 0x40, //lda #$08, sta $4003
+    0xa9, 0x0a, 0x8d, 0x01, 0x20, //lda #$0a, sta $2001
+    0xe6, 0x00, 0xa5, 0x00, //inc $00, lda $00
+    0x8d, 0x02, 0x40, //sta $4002
+    0xa9, 0x3f, 0x8d, 0x06, 0x20, //lda #$3f, sta $2006
+    0xa9, 0x00, 0x8d, 0x06, 0x20, //lda #$00, sta $2006
+    0xa5, 0x00, 0x8d, 0x07, 0x20, //lda $00, sta $2007
+    0x4c, 0x14, 0x80, //jmp $8014
+];
+
+fn test_console() -> Nes {
+    let mut prg_rom = [0; 16384];
+    prg_rom[..PROGRAM.len()].copy_from_slice(&PROGRAM);
+    //reset vector
+    prg_rom[0x3ffc] = 0x00;
+    prg_rom[0x3ffd] = 0x80;
+
+    let cartridge = nrom::Nrom::new(&vec![prg_rom], ChrMemory::ram(8192), nrom::Mirroring::Horizontal).ok().unwrap();
+    Nes::new(Box::new(cartridge))
+}
+
+//framebuffer and audio of every frame
+fn run(console: &mut Nes, frames: u32) -> Vec<(Vec<u8>, Vec<i16>)> {
+    (0..frames).map(|_| {
+        console.run_frame();
+        (console.framebuffer().to_vec(), console.audio_samples().to_vec())
+    }).collect()
+}
+
+#[test]
+fn test_round_trip() {
+    let mut console = test_console();
+    run(&mut console, 10);
+    let state = console.save_state();
+
+    let first = run(&mut console, 20);
+    let end_state = console.save_state();
+
+    console.load_state(&state).unwrap();
+    assert_eq!(console.save_state(), state);
+    let second = run(&mut console, 20);
+
+    assert!(first == second);
+    assert!(first[0].0 != first[19].0);
+    assert_eq!(console.save_state(), end_state);
+    assert_ne!(state, end_state);
+}
+
+#[test]
+fn test_load_into_new_console() {
+    let mut console = test_console();
+    run(&mut console, 7);
+    let state = console.save_state();
+    let expected = run(&mut console, 5);
+
+    let mut other = test_console();
+    other.load_state(&state).unwrap();
+    assert!(run(&mut other, 5) == expected);
+}
+
+#[test]
+fn test_invalid_states() {
+    let mut console = test_console();
+    run(&mut console, 3);
+    let state = console.save_state();
+    run(&mut console, 1);
+    let current = console.save_state();
+
+    let mut bad_magic = state.clone();
+    bad_magic[0] = b'X';
+    assert!(matches!(console.load_state(&bad_magic), Err(StateError::InvalidMagic)));
+
+    let mut bad_version = state.clone();
+    bad_version[8..12].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+    assert!(matches!(console.load_state(&bad_version), Err(StateError::UnsupportedVersion(v)) if v == STATE_VERSION + 1));
+
+    assert!(matches!(console.load_state(&state[..state.len() - 1]), Err(StateError::UnexpectedEnd)));
+    assert!(matches!(console.load_state(&state[..6]), Err(StateError::UnexpectedEnd)));
+
+    let mut trailing = state.clone();
+    trailing.push(0);
+    assert!(matches!(console.load_state(&trailing), Err(StateError::InvalidData)));
+
+    //failed loads leave the console as it was
+    assert_eq!(console.save_state(), current);
+}
+
+#[test]
+fn test_slot_path() {
+    assert_eq!(slot_path("roms/smb.nes", 3), std::path::PathBuf::from("roms/smb.st3"));
+    assert_eq!(slot_path("game", 10), std::path::PathBuf::from("game.st10"));
+}
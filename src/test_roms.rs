@@ -0,0 +1,93 @@
+use std::path::Path;
+
+use crate::{headless::blargg::run_test_rom, Nes};
+
+//blargg's accuracy test roms, checked through the $6000 status protocol.
+//the roms are not part of the repository, so the tests are ignored by default: copy the test suites
+//into test_roms/ and run them with `cargo test -- --ignored`. a missing rom fails the test
+const ROM_DIRECTORY: &str = "test_roms";
+
+//about a minute, the slowest single roms take less than half of that
+const MAX_FRAMES: u64 = 3600;
+
+//a rom with a known failure has to keep failing, so it gets moved back to the passing ones once it's fixed
+fn run(path: &str, known_failure: Option<&str>) {
+    let path = Path::new(ROM_DIRECTORY).join(path);
+    assert!(path.exists(), "{} not found, copy the test suites into {}/", path.display(), ROM_DIRECTORY);
+
+    let mut console = Nes::load_rom(&path).unwrap_or_else(|e| panic!("could not load {}: {}", path.display(), e));
+    let failure = match run_test_rom(&mut console, MAX_FRAMES) {
+        Ok(result) if result.passed() => None,
+        Ok(result) => Some(format!("{} failed with code {}:\n{}", path.display(), result.code, result.text)),
+        Err(e) => Some(format!("{}: {}", path.display(), e)),
+    };
+
+    match (failure, known_failure) {
+        (None, None) => {},
+        (Some(failure), None) => panic!("{}", failure),
+        (None, Some(reason)) => panic!("{} passes now, it is no longer a known failure ({})", path.display(), reason),
+        (Some(failure), Some(reason)) => println!("known failure, {}\n{}", reason, failure),
+    }
+}
+
+macro_rules! test_roms {
+    ($($name:ident: $path:expr $(=> $known_failure:expr)?,)*) => {
+        $(
+            #[test]
+            #[ignore = "needs the rom in test_roms/"]
+            fn $name() {
+                run($path, None $(.or(Some($known_failure)))?);
+            }
+        )*
+    };
+}
+
+const VBLANK_AT_INSTRUCTION: &str = "vblank and nmi are set at an instruction boundary, not at line 241 dot 1";
+//the counter is clocked once per line by an event instead of by a12, and a reload to 0 doesn't raise an irq
+const MMC3_SCANLINE_EVENTS: &str = "the mmc3 irq counter is clocked by a per line event instead of a12";
+
+test_roms! {
+    instr_test_01_basics: "instr_test-v5/rom_singles/01-basics.nes",
+    instr_test_02_implied: "instr_test-v5/rom_singles/02-implied.nes",
+    instr_test_03_immediate: "instr_test-v5/rom_singles/03-immediate.nes",
+    instr_test_04_zero_page: "instr_test-v5/rom_singles/04-zero_page.nes",
+    instr_test_05_zp_xy: "instr_test-v5/rom_singles/05-zp_xy.nes",
+    instr_test_06_absolute: "instr_test-v5/rom_singles/06-absolute.nes",
+    instr_test_07_abs_xy: "instr_test-v5/rom_singles/07-abs_xy.nes",
+    instr_test_08_ind_x: "instr_test-v5/rom_singles/08-ind_x.nes",
+    instr_test_09_ind_y: "instr_test-v5/rom_singles/09-ind_y.nes",
+    instr_test_10_branches: "instr_test-v5/rom_singles/10-branches.nes",
+    instr_test_11_stack: "instr_test-v5/rom_singles/11-stack.nes",
+    instr_test_12_jmp_jsr: "instr_test-v5/rom_singles/12-jmp_jsr.nes",
+    instr_test_13_rts: "instr_test-v5/rom_singles/13-rts.nes",
+    instr_test_14_rti: "instr_test-v5/rom_singles/14-rti.nes",
+    instr_test_15_brk: "instr_test-v5/rom_singles/15-brk.nes",
+    instr_test_16_special: "instr_test-v5/rom_singles/16-special.nes",
+
+    ppu_vbl_nmi_01_vbl_basics: "ppu_vbl_nmi/rom_singles/01-vbl_basics.nes",
+    ppu_vbl_nmi_02_vbl_set_time: "ppu_vbl_nmi/rom_singles/02-vbl_set_time.nes" => VBLANK_AT_INSTRUCTION,
+    ppu_vbl_nmi_03_vbl_clear_time: "ppu_vbl_nmi/rom_singles/03-vbl_clear_time.nes" => "the scanline renderer clears vblank two lines after dot 1 of the pre-render line",
+    ppu_vbl_nmi_04_nmi_control: "ppu_vbl_nmi/rom_singles/04-nmi_control.nes" => "an nmi raised by a $2000 write is taken right after it instead of after the next instruction",
+    ppu_vbl_nmi_05_nmi_timing: "ppu_vbl_nmi/rom_singles/05-nmi_timing.nes" => VBLANK_AT_INSTRUCTION,
+    ppu_vbl_nmi_06_suppression: "ppu_vbl_nmi/rom_singles/06-suppression.nes" => "there is no $2002 read race that suppresses the vblank flag and nmi",
+    ppu_vbl_nmi_07_nmi_on_timing: "ppu_vbl_nmi/rom_singles/07-nmi_on_timing.nes" => VBLANK_AT_INSTRUCTION,
+    ppu_vbl_nmi_08_nmi_off_timing: "ppu_vbl_nmi/rom_singles/08-nmi_off_timing.nes" => VBLANK_AT_INSTRUCTION,
+    ppu_vbl_nmi_09_even_odd_frames: "ppu_vbl_nmi/rom_singles/09-even_odd_frames.nes" => "the skipped dot of odd frames is not emulated",
+    ppu_vbl_nmi_10_even_odd_timing: "ppu_vbl_nmi/rom_singles/10-even_odd_timing.nes" => "the skipped dot of odd frames is not emulated",
+
+    apu_test_1_len_ctr: "apu_test/rom_singles/1-len_ctr.nes",
+    apu_test_2_len_table: "apu_test/rom_singles/2-len_table.nes",
+    apu_test_3_irq_flag: "apu_test/rom_singles/3-irq_flag.nes",
+    apu_test_4_jitter: "apu_test/rom_singles/4-jitter.nes",
+    apu_test_5_len_timing: "apu_test/rom_singles/5-len_timing.nes",
+    apu_test_6_irq_flag_timing: "apu_test/rom_singles/6-irq_flag_timing.nes",
+    apu_test_7_dmc_basics: "apu_test/rom_singles/7-dmc_basics.nes",
+    apu_test_8_dmc_rates: "apu_test/rom_singles/8-dmc_rates.nes",
+
+    mmc3_test_1_clocking: "mmc3_test_2/rom_singles/1-clocking.nes" => MMC3_SCANLINE_EVENTS,
+    mmc3_test_2_details: "mmc3_test_2/rom_singles/2-details.nes" => MMC3_SCANLINE_EVENTS,
+    mmc3_test_3_a12_clocking: "mmc3_test_2/rom_singles/3-A12_clocking.nes" => MMC3_SCANLINE_EVENTS,
+    mmc3_test_4_scanline_timing: "mmc3_test_2/rom_singles/4-scanline_timing.nes" => MMC3_SCANLINE_EVENTS,
+    mmc3_test_5_mmc3: "mmc3_test_2/rom_singles/5-MMC3.nes" => MMC3_SCANLINE_EVENTS,
+    mmc3_test_6_mmc3_alt: "mmc3_test_2/rom_singles/6-MMC3_alt.nes" => MMC3_SCANLINE_EVENTS,
+}
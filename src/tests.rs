@@ -0,0 +1,327 @@
+use crate::{
+    mappers::{chr::ChrMemory, nrom, SystemMemoryMapper},
+    cpu::CpuMemory,
+    joypad::Joypad,
+    memory_controller::MemoryPtr,
+    savestate::{SaveState, StateError, StateReader, StateWriter},
+    Button, Nes, Player, Renderer,
+};
+
+//counts in ram forever: inc $00, jmp $8000
+const PROGRAM: [u8; 5] = [0xe6, 0x00, 0x4c, 0x00, 0x80];
+
+fn test_console() -> Nes {
+    console_with_program(&PROGRAM)
+}
+
+fn console_with_program(program: &[u8]) -> Nes {
+    let mut prg_rom = [0; 16384];
+    prg_rom[..program.len()].copy_from_slice(program);
+    //reset vector
+    prg_rom[0x3ffc] = 0x00;
+    prg_rom[0x3ffd] = 0x80;
+
+    let cartridge = nrom::Nrom::new(&vec![prg_rom], ChrMemory::ram(8192), nrom::Mirroring::Horizontal).ok().unwrap();
+    Nes::new(Box::new(cartridge))
+}
+
+#[test]
+fn test_step_instruction() {
+    let mut by_frame = test_console();
+    let mut by_instruction = test_console();
+
+    for _ in 0..3 {
+        by_frame.run_frame();
+
+        let mut instructions = 1;
+        while !by_instruction.step_instruction() {
+            instructions += 1;
+        }
+        assert!(instructions > 1000);
+    }
+
+    assert_eq!(by_frame.save_state(), by_instruction.save_state());
+
+    //the frame in progress is part of a save state
+    by_frame.step_instruction();
+    let state = by_frame.save_state();
+    by_frame.run_frame();
+    by_instruction.load_state(&state).unwrap();
+    by_instruction.run_frame();
+    assert_eq!(by_frame.save_state(), by_instruction.save_state());
+}
+
+#[test]
+fn test_controller_ports() {
+    let mut console = test_console();
+    console.set_button(Player::One, Button::START, true);
+    console.set_button(Player::Two, Button::B, true);
+
+    let mut memory = SystemMemoryMapper::new(&mut console.ram, console.cartridge.as_mut(), &mut console.ppu, &mut console.apu, &mut console.gamepads, &mut console.events, &mut console.framebuffer_nes, None);
+    let cpu = &mut console.cpu;
+    memory.write(MemoryPtr(0x4016), 1, cpu);
+    memory.write(MemoryPtr(0x4016), 0, cpu);
+
+    let port1: Vec<u8> = (0..8).map(|_| memory.read(MemoryPtr(0x4016), cpu)).collect();
+    let port2: Vec<u8> = (0..8).map(|_| memory.read(MemoryPtr(0x4017), cpu)).collect();
+    assert_eq!(port1, [0, 0, 0, 1, 0, 0, 0, 0]);
+    assert_eq!(port2, [0, 1, 0, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn test_joypad_after_eight_reads() {
+    let mut gamepad = Joypad::new();
+    gamepad.set_state(Button::A, true);
+    gamepad.write(MemoryPtr(0x4016), 1);
+    gamepad.write(MemoryPtr(0x4016), 0);
+    let bits: Vec<u8> = (0..10).map(|_| gamepad.read(MemoryPtr(0x4016))).collect();
+    assert_eq!(bits, [1, 0, 0, 0, 0, 0, 0, 0, 1, 1]);
+
+    //a state can be saved after all buttons were read, but not past that
+    let mut w = StateWriter::new();
+    gamepad.save_state(&mut w);
+    let mut data = w.into_inner();
+    assert!(gamepad.load_state(&mut StateReader::new(&data).unwrap()).is_ok());
+    data[21] = 9;
+    assert!(matches!(gamepad.load_state(&mut StateReader::new(&data).unwrap()), Err(StateError::InvalidData)));
+}
+
+#[test]
+fn test_reset() {
+    let mut console = test_console();
+    console.run_frame();
+    console.step_instruction();
+    let counter = console.ram.dump_ram()[0];
+    let stack_pointer = console.cpu.stack_pointer;
+
+    console.reset();
+    assert_eq!(console.cpu.program_counter, MemoryPtr(0x8000));
+    assert_eq!(console.cpu.stack_pointer, stack_pointer.wrapping_sub(3));
+    //ram survives a reset
+    assert_eq!(console.ram.dump_ram()[0], counter);
+
+    console.run_frame();
+    assert_ne!(console.ram.dump_ram()[0], counter);
+}
+
+#[test]
+fn test_jam_until_reset() {
+    //inc $00, jam
+    let mut console = console_with_program(&[0xe6, 0x00, 0x02]);
+    console.run_frame();
+    console.run_frame();
+    assert!(console.cpu.halted);
+    assert_eq!(console.cpu.program_counter, MemoryPtr(0x8002));
+    assert_eq!(console.ram.dump_ram()[0], 1);
+
+    console.reset();
+    assert!(!console.cpu.halted);
+    console.run_frame();
+    assert_eq!(console.ram.dump_ram()[0], 2);
+}
+
+#[test]
+fn test_dummy_read_of_ppudata() {
+    let program = [
+        //writes $11, $22 to vram $2000 and points the ppu back at it
+        0xa9, 0x20, 0x8d, 0x06, 0x20, 0xa9, 0x00, 0x8d, 0x06, 0x20,
+        0xa9, 0x11, 0x8d, 0x07, 0x20, 0xa9, 0x22, 0x8d, 0x07, 0x20,
+        0xa9, 0x20, 0x8d, 0x06, 0x20, 0xa9, 0x00, 0x8d, 0x06, 0x20,
+        //fills the read buffer, then lda $20ff,x crosses a page and reads $2007 before $2107
+        0xad, 0x07, 0x20, 0xa2, 0x08, 0xbd, 0xff, 0x20,
+        //sta $00, jam
+        0x85, 0x00, 0x02,
+    ];
+    let mut console = console_with_program(&program);
+    console.run_frame();
+
+    //the dummy read took $11 out of the buffer, so the real one sees $22
+    assert!(console.cpu.halted);
+    assert_eq!(console.ram.dump_ram()[0], 0x22);
+}
+
+#[test]
+fn test_nmi_enabled_during_vblank() {
+    //lda #$80, sta $2000
+    let enable = [0xa9, 0x80, 0x8d, 0x00, 0x20];
+    let mut console = console_with_program(&enable);
+    console.step_instruction();
+    console.step_instruction();
+    assert!(console.cpu.nmi_pending);
+
+    //not once the vblank flag was read off: lda $2002 first
+    let program = [[0xad, 0x02, 0x20].as_slice(), &enable].concat();
+    let mut console = console_with_program(&program);
+    for _ in 0..3 {
+        console.step_instruction();
+    }
+    assert!(!console.cpu.nmi_pending);
+}
+
+//cpu cycles taken by each of the next `count` instructions
+fn instruction_cycles(console: &mut Nes, count: usize) -> Vec<u64> {
+    (0..count).map(|_| {
+        let start = console.cpu.cycle_count;
+        console.step_instruction();
+        console.cpu.cycle_count - start
+    }).collect()
+}
+
+#[test]
+fn test_oam_dma() {
+    let program = [
+        //$5a at $0700, oam address 0
+        0xa9, 0x5a, 0x8d, 0x00, 0x07, 0xa9, 0x00, 0x8d, 0x03, 0x20,
+        //dma from $0f00, a mirror of $0700, then nop so the second dma starts on the other cycle parity
+        0xa9, 0x0f, 0x8d, 0x14, 0x40, 0xea, 0x8d, 0x14, 0x40,
+        //jam
+        0x02,
+    ];
+    let mut console = console_with_program(&program);
+    let cycles = instruction_cycles(&mut console, 8);
+
+    //the sta takes 4 cycles, the dma 513 plus one to line up with a read cycle on every other cycle
+    assert_eq!(cycles[5] + cycles[7], 4 + 513 + 4 + 514);
+    assert!(cycles[5] == 4 + 513 || cycles[5] == 4 + 514);
+    assert_eq!(console.ppu.current_state.oam[0], 0x5a);
+    assert_eq!(console.ppu.current_state.oam[1], 0);
+    //the bytes go through $2004, which wraps back to where it started
+    assert_eq!(console.ppu.current_state.oamaddr, 0);
+}
+
+#[test]
+fn test_dmc_fetch_stalls_cpu() {
+    let program = [
+        //rate 15, a one byte sample at $c000
+        0xa9, 0x0f, 0x8d, 0x10, 0x40, 0xa9, 0x00, 0x8d, 0x12, 0x40, 0x8d, 0x13, 0x40,
+        //enabling the dmc fetches the first byte right away, then nop, jam
+        0xa9, 0x10, 0x8d, 0x15, 0x40, 0xea, 0x02,
+    ];
+    let mut console = console_with_program(&program);
+    let cycles = instruction_cycles(&mut console, 8);
+
+    assert_eq!(cycles[6], 4 + 4);
+    assert_eq!(cycles[7], 2);
+}
+
+//draws a row of solid tiles on the second tile row with sprite 0 over it, then loops
+const SPRITE0_PROGRAM: [u8; 129] = [
+    //tile 1 is solid color 1
+    0xa9, 0x00, 0x8d, 0x06, 0x20, 0xa9, 0x10, 0x8d, 0x06, 0x20, 0xa2, 0x08, 0xa9, 0xff, 0x8d, 0x07, 0x20, 0xca, 0xd0, 0xfa, 0xa2, 0x08, 0xa9, 0x00, 0x8d, 0x07, 0x20, 0xca, 0xd0, 0xfa,
+    //a row of tile 1 at the second nametable row
+    0xa9, 0x20, 0x8d, 0x06, 0x20, 0xa9, 0x21, 0x8d, 0x06, 0x20, 0xa2, 0x08, 0xa9, 0x01, 0x8d, 0x07, 0x20, 0xca, 0xd0, 0xfa,
+    //palette
+    0xa9, 0x3f, 0x8d, 0x06, 0x20, 0xa9, 0x00, 0x8d, 0x06, 0x20, 0xa9, 0x0f, 0x8d, 0x07, 0x20, 0xa9, 0x30, 0x8d, 0x07, 0x20, 0xa9, 0x3f, 0x8d, 0x06, 0x20, 0xa9, 0x11, 0x8d, 0x06, 0x20, 0xa9, 0x16, 0x8d, 0x07, 0x20,
+    //sprite 0 at x=16 on lines 8-15
+    0xa9, 0x00, 0x8d, 0x03, 0x20, 0xa9, 0x07, 0x8d, 0x04, 0x20, 0xa9, 0x01, 0x8d, 0x04, 0x20, 0xa9, 0x00, 0x8d, 0x04, 0x20, 0xa9, 0x10, 0x8d, 0x04, 0x20,
+    //nametable, scroll and rendering
+    0xa9, 0x00, 0x8d, 0x00, 0x20, 0x8d, 0x05, 0x20, 0x8d, 0x05, 0x20, 0xa9, 0x1e, 0x8d, 0x01, 0x20,
+    //jmp to itself
+    0x4c, 0x7e, 0x80,
+];
+
+#[test]
+fn test_renderers_match() {
+    //only the background, the scanline renderer draws sprites a line higher than the hardware
+    let mut program = SPRITE0_PROGRAM;
+    assert_eq!(program[122], 0x1e);
+    program[122] = 0x0a;
+
+    let mut scanline = console_with_program(&program);
+    let mut dot = console_with_program(&program);
+    dot.set_renderer(Renderer::Dot);
+
+    for _ in 0..3 {
+        scanline.run_frame();
+        dot.run_frame();
+    }
+
+    assert_eq!(dot.framebuffer().to_vec(), scanline.framebuffer().to_vec());
+    //only palette indices are exposed, without the opaque background marker of the scanline renderer
+    assert!(scanline.framebuffer().iter().all(|v| *v < 0x40));
+    assert_eq!(dot.framebuffer()[8 * 256 + 8], 0x30);
+}
+
+#[test]
+fn test_dot_renderer_sprites() {
+    let mut console = console_with_program(&SPRITE0_PROGRAM);
+    console.set_renderer(Renderer::Dot);
+    for _ in 0..3 {
+        console.run_frame();
+    }
+
+    //a sprite shows up on the line after its y position
+    let column: Vec<u8> = (6..17).map(|y| console.framebuffer()[y * 256 + 16]).collect();
+    assert_eq!(column, [0x0f, 0x0f, 0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x16, 0x0f]);
+    assert_eq!(console.framebuffer()[8 * 256 + 24], 0x30);
+}
+
+//steps until the ppu status has the given bits set (or cleared), and returns the dot of the frame it happened by
+fn dot_of_status_change(console: &mut Nes, bits: u8, set: bool) -> u64 {
+    loop {
+        assert!(!console.step_instruction(), "the status didn't change within the frame");
+        if (console.ppu.current_state.ppustatus & bits != 0) == set {
+            return 3 * (console.cpu.cycle_count - console.frame_start.unwrap());
+        }
+    }
+}
+
+#[test]
+fn test_dot_renderer_flag_timing() {
+    let mut console = console_with_program(&SPRITE0_PROGRAM);
+    console.set_renderer(Renderer::Dot);
+    console.run_frame();
+    console.step_instruction();
+
+    //the frame starts at dot 1 of line 241 and an instruction takes up to 21 dots
+    let at = |line: u64, dot: u64| ((line + 262 - 241) % 262) * 341 + dot - 1;
+
+    //vblank ends at dot 1 of the pre-render line
+    let cleared = dot_of_status_change(&mut console, 0x80, false);
+    assert!((at(261, 1)..at(261, 1) + 21).contains(&cleared), "{}", cleared);
+
+    //the ninth sprite on line 0 is found after 8 sprites of 8 dots each, starting at dot 65
+    let overflow = dot_of_status_change(&mut console, 0x20, true);
+    assert!((at(0, 132)..at(0, 132) + 21).contains(&overflow), "{}", overflow);
+
+    //the first opaque pixel of sprite 0 is x=16 on line 8, output at dot 17
+    let hit = dot_of_status_change(&mut console, 0x40, true);
+    assert!((at(8, 17)..at(8, 17) + 21).contains(&hit), "{}", hit);
+}
+
+//nine solid sprites side by side at y=50, 16 pixels apart
+fn nine_sprites_program() -> Vec<u8> {
+    //tile, nametable row and palette setup
+    let mut program = SPRITE0_PROGRAM[..85].to_vec();
+    program.extend_from_slice(&[
+        //lda #0, sta $2003, ldx #0
+        0xa9, 0x00, 0x8d, 0x03, 0x20, 0xa2, 0x00,
+        //y=50, tile 1, attributes 0, x
+        0xa9, 0x32, 0x8d, 0x04, 0x20, 0xa9, 0x01, 0x8d, 0x04, 0x20, 0xa9, 0x00, 0x8d, 0x04, 0x20, 0x8a, 0x8d, 0x04, 0x20,
+        //x += 16 until 9 sprites are written
+        0x18, 0x69, 0x10, 0xaa, 0xe0, 0x90, 0xd0, 0xe5,
+    ]);
+    program.extend_from_slice(&SPRITE0_PROGRAM[110..126]);
+    let end = 0x8000 + program.len() as u16;
+    program.extend_from_slice(&[0x4c, end as u8, (end >> 8) as u8]);
+    program
+}
+
+#[test]
+fn test_sprite_limit() {
+    for renderer in [Renderer::Scanline, Renderer::Dot] {
+        for unlimited in [false, true] {
+            let mut console = console_with_program(&nine_sprites_program());
+            console.set_renderer(renderer);
+            console.set_unlimited_sprites(unlimited);
+            for _ in 0..3 {
+                console.run_frame();
+            }
+
+            let pixel = |x: usize| console.framebuffer()[52 * 256 + x];
+            assert_eq!(pixel(7 * 16 + 4), 0x16, "{:?}", renderer);
+            assert_eq!(pixel(8 * 16 + 4), if unlimited { 0x16 } else { 0x0f }, "{:?} {}", renderer, unlimited);
+            assert!(console.ppu.current_state.ppustatus & 0x20 != 0);
+        }
+    }
+}